@@ -0,0 +1,347 @@
+use crate::apu::dmc::DMC;
+use crate::apu::frame_counter::{FrameClock, FrameCounter};
+use crate::apu::mixer::mix;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::sweep::SweepNegate;
+use crate::apu::triangle::Triangle;
+use crate::error::EmuError;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: DMC,
+    frame_counter: FrameCounter,
+    cycles: u64,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new(Region::default())
+    }
+}
+
+impl APU {
+    pub fn new(region: Region) -> Self {
+        Self {
+            pulse1: Pulse::new(SweepNegate::OnesComplement),
+            pulse2: Pulse::new(SweepNegate::TwosComplement),
+            triangle: Triangle::default(),
+            noise: Noise::new(region),
+            dmc: DMC::new(region),
+            frame_counter: FrameCounter::new(region),
+            cycles: 0,
+        }
+    }
+
+    pub fn write(&mut self, address: usize, val: u8) {
+        match address {
+            0x4000 => self.pulse1.write_control(val),
+            0x4001 => self.pulse1.write_sweep(val),
+            0x4002 => self.pulse1.write_timer_low(val),
+            0x4003 => self.pulse1.write_timer_high(val),
+            0x4004 => self.pulse2.write_control(val),
+            0x4005 => self.pulse2.write_sweep(val),
+            0x4006 => self.pulse2.write_timer_low(val),
+            0x4007 => self.pulse2.write_timer_high(val),
+            0x4008 => self.triangle.write_linear(val),
+            0x400a => self.triangle.write_timer_low(val),
+            0x400b => self.triangle.write_timer_high(val),
+            0x400c => self.noise.write_control(val),
+            0x400e => self.noise.write_period(val),
+            0x400f => self.noise.write_length(val),
+            0x4010 => self.dmc.write_control(val),
+            0x4011 => self.dmc.write_output_level(val),
+            0x4012 => self.dmc.write_sample_address(val),
+            0x4013 => self.dmc.write_sample_length(val),
+            0x4015 => self.write_status(val),
+            0x4017 => self.frame_counter.write(val, self.cycles & 1 == 1),
+            _ => {}
+        }
+    }
+
+    fn write_status(&mut self, val: u8) {
+        self.pulse1.set_enabled(val & 0x1 != 0);
+        self.pulse2.set_enabled(val & 0x2 != 0);
+        self.triangle.set_enabled(val & 0x4 != 0);
+        self.noise.set_enabled(val & 0x8 != 0);
+        self.dmc.set_enabled(val & 0x10 != 0);
+    }
+
+    /// $4015 read
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.is_active() {
+            status |= 0x1;
+        }
+        if self.pulse2.is_active() {
+            status |= 0x2;
+        }
+        if self.triangle.is_active() {
+            status |= 0x4;
+        }
+        if self.noise.is_active() {
+            status |= 0x8;
+        }
+        if self.dmc.is_active() {
+            status |= 0x10;
+        }
+        if self.frame_counter.irq_flag() {
+            status |= 0x40;
+        }
+        if self.dmc.irq_flag() {
+            status |= 0x80;
+        }
+        self.frame_counter.acknowledge_irq();
+        status
+    }
+
+    /// advance one cpu cycle
+    pub fn clock(&mut self) {
+        match self.frame_counter.clock() {
+            FrameClock::None => {}
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycles & 1 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.pulse1.commit_length();
+        self.pulse2.commit_length();
+        self.triangle.commit_length();
+        self.noise.commit_length();
+        self.cycles += 1;
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.irq_flag() || self.dmc.irq_flag()
+    }
+
+    /// address of a pending dmc sample fetch, the caller reads it through the
+    /// cpu bus, stalls the cpu and hands the byte back with `dmc_dma_fill`
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+
+    pub fn dmc_dma_fill(&mut self, val: u8) {
+        self.dmc.dma_fill(val)
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_envelope();
+        self.pulse2.clock_envelope();
+        self.triangle.clock_linear();
+        self.noise.clock_envelope();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_length_sweep();
+        self.pulse2.clock_length_sweep();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    /// mixed output level of all channels, in 0.0..=1.0
+    pub fn output(&self) -> f32 {
+        mix(
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    /// raw 4-bit levels of (pulse 1, pulse 2)
+    pub fn pulse_output(&self) -> (u8, u8) {
+        (self.pulse1.output(), self.pulse2.output())
+    }
+
+    /// raw levels of (triangle, noise, dmc)
+    pub fn tnd_output(&self) -> (u8, u8, u8) {
+        (
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    /// channel and sequencer state, the output filters and mixer aren't part of it
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        self.frame_counter.save_state(state);
+        state.write_u64(self.cycles);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.frame_counter.load_state(state)?;
+        self.cycles = state.read_u64()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_status() {
+    let mut apu = APU::default();
+    apu.write(0x4003, 0x08);
+    assert_eq!(apu.read_status(), 0);
+
+    apu.write(0x4015, 0x03);
+    apu.write(0x4003, 0x08);
+    apu.write(0x4007, 0x08);
+    // length loads land at the end of the cycle
+    assert_eq!(apu.read_status(), 0);
+    apu.clock();
+    assert_eq!(apu.read_status(), 0x3);
+
+    apu.write(0x4015, 0x02);
+    assert_eq!(apu.read_status(), 0x2);
+}
+
+#[test]
+fn test_length_counter() {
+    let mut apu = APU::default();
+    apu.write(0x4015, 0x01);
+    // length index 1 loads 254
+    apu.write(0x4003, 0x08);
+    apu.clock();
+    for _ in 0..253 {
+        apu.clock_half_frame();
+    }
+    assert_eq!(apu.read_status(), 0x1);
+    apu.clock_half_frame();
+    assert_eq!(apu.read_status(), 0);
+
+    // halted counter keeps its value
+    apu.write(0x4000, 0x20);
+    apu.write(0x4003, 0x08);
+    apu.clock();
+    for _ in 0..300 {
+        apu.clock_half_frame();
+    }
+    assert_eq!(apu.read_status(), 0x1);
+}
+
+#[test]
+fn test_triangle_linear_counter() {
+    let mut apu = APU::default();
+    apu.write(0x4015, 0x04);
+    // linear counter reload of 2, control clear
+    apu.write(0x4008, 0x02);
+    apu.write(0x400a, 0x10);
+    apu.write(0x400b, 0x08);
+    apu.clock();
+    apu.clock_quarter_frame();
+
+    let start = apu.tnd_output().0;
+    for _ in 0..0x11 {
+        apu.clock();
+    }
+    assert_ne!(apu.tnd_output().0, start);
+
+    // once the linear counter runs out the sequencer holds its step
+    apu.clock_quarter_frame();
+    apu.clock_quarter_frame();
+    let held = apu.tnd_output().0;
+    for _ in 0..0x100 {
+        apu.clock();
+    }
+    assert_eq!(apu.tnd_output().0, held);
+}
+
+#[test]
+fn test_dmc_status() {
+    let mut apu = APU::default();
+    apu.write(0x4010, 0x80);
+    apu.write(0x4013, 0x00);
+    apu.write(0x4015, 0x10);
+    assert_eq!(apu.read_status(), 0x10);
+
+    let address = apu.dmc_dma_request().unwrap();
+    assert_eq!(address, 0xc000);
+    apu.dmc_dma_fill(0x00);
+    assert_eq!(apu.read_status(), 0x80);
+
+    // reading $4015 leaves the dmc irq alone, writing it acknowledges
+    assert!(apu.irq_pending());
+    apu.write(0x4015, 0x00);
+    assert!(!apu.irq_pending());
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = APU::default();
+    for _ in 0..29827 {
+        apu.clock();
+    }
+    assert!(!apu.irq_pending());
+    apu.clock();
+    assert!(apu.irq_pending());
+    assert_eq!(apu.read_status() & 0x40, 0x40);
+    // reading $4015 acknowledges the frame irq
+    assert!(!apu.irq_pending());
+
+    // the inhibit flag clears the flag and keeps it from being raised
+    apu.write(0x4017, 0x40);
+    for _ in 0..29830 * 2 {
+        apu.clock();
+    }
+    assert!(!apu.irq_pending());
+}
+
+#[test]
+fn test_length_reload_during_clock() {
+    let mut apu = APU::default();
+    apu.write(0x4015, 0x01);
+    apu.write(0x4003, 0x18);
+    apu.clock();
+    assert_eq!(apu.read_status(), 0x1);
+
+    // run up to the cycle before the first half frame clock at 14913
+    for _ in 1..14912 {
+        apu.clock();
+    }
+    // a reload in the same cycle as the length clock is ignored
+    apu.write(0x4003, 0x08);
+    apu.clock();
+    assert_eq!(apu.pulse1.length_counter(), 1);
+}
+
+#[test]
+fn test_sweep_negate() {
+    let mut apu = APU::default();
+    apu.write(0x4015, 0x03);
+    for base in [0x4000, 0x4004] {
+        apu.write(base, 0x3f);
+        // enabled, period 0, negate, shift 1
+        apu.write(base + 1, 0x89);
+        apu.write(base + 2, 0x00);
+        apu.write(base + 3, 0x09);
+    }
+    apu.clock_half_frame();
+    // 0x100 - 0x80 - 1 on pulse 1, 0x100 - 0x80 on pulse 2
+    assert_eq!(apu.pulse1.timer_period(), 0x7f);
+    assert_eq!(apu.pulse2.timer_period(), 0x80);
+}
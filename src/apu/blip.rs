@@ -0,0 +1,158 @@
+use lazy_static::lazy_static;
+
+// band-limited step synthesis: every amplitude change is spread over a short
+// windowed-sinc kernel at its sub-sample position, so the square edges of the
+// channels never fold back into the audible range when decimating
+
+const PHASE_BITS: u32 = 6;
+const PHASES: usize = 1 << PHASE_BITS;
+const HALF_WIDTH: usize = 8;
+const KERNEL_WIDTH: usize = HALF_WIDTH * 2;
+const TIME_BITS: u32 = 32;
+const TIME_UNIT: u64 = 1 << TIME_BITS;
+/// passband edge as a fraction of the output sample rate
+const CUTOFF: f64 = 0.45;
+
+lazy_static! {
+    static ref KERNEL: Vec<[f32; KERNEL_WIDTH]> = generate_kernel();
+}
+
+fn generate_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    let mut kernel = vec![[0f32; KERNEL_WIDTH]; PHASES];
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let frac = phase as f64 / PHASES as f64;
+        let mut impulse = [0f64; KERNEL_WIDTH];
+        for (i, tap) in impulse.iter_mut().enumerate() {
+            let x = i as f64 - (HALF_WIDTH - 1) as f64 - frac;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                let t = std::f64::consts::PI * 2.0 * CUTOFF * x;
+                t.sin() / t
+            };
+            // blackman window over the kernel span
+            let w = std::f64::consts::PI * x / HALF_WIDTH as f64;
+            let window = if x.abs() >= HALF_WIDTH as f64 {
+                0.0
+            } else {
+                0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos()
+            };
+            *tap = sinc * window;
+        }
+        // each phase must add up to exactly one so steps settle at the right level
+        let sum: f64 = impulse.iter().sum();
+        for (tap, val) in taps.iter_mut().zip(impulse.iter()) {
+            *tap = (val / sum) as f32;
+        }
+    }
+    kernel
+}
+
+pub struct BlipBuffer {
+    /// output samples per input clock, fixed point
+    factor: u64,
+    /// fractional output position carried over from the last frame
+    offset: u64,
+    deltas: Vec<f32>,
+    avail: usize,
+    integrator: f32,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            factor: Self::factor(clock_rate, sample_rate),
+            offset: 0,
+            deltas: vec![0.0; KERNEL_WIDTH],
+            avail: 0,
+            integrator: 0.0,
+        }
+    }
+
+    fn factor(clock_rate: f64, sample_rate: u32) -> u64 {
+        (sample_rate as f64 / clock_rate * TIME_UNIT as f64).round() as u64
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: u32) {
+        self.factor = Self::factor(clock_rate, sample_rate);
+    }
+
+    /// add an amplitude change `time` clocks after the start of the current frame
+    pub fn add_delta(&mut self, time: u64, delta: f32) {
+        let position = time * self.factor + self.offset;
+        let index = self.avail + (position >> TIME_BITS) as usize;
+        let phase = ((position >> (TIME_BITS - PHASE_BITS)) as usize) & (PHASES - 1);
+
+        if self.deltas.len() < index + KERNEL_WIDTH {
+            self.deltas.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        for (slot, tap) in self.deltas[index..index + KERNEL_WIDTH]
+            .iter_mut()
+            .zip(KERNEL[phase].iter())
+        {
+            *slot += delta * tap;
+        }
+    }
+
+    /// close the frame after `time` clocks, making its samples readable
+    pub fn end_frame(&mut self, time: u64) {
+        let position = time * self.factor + self.offset;
+        self.avail += (position >> TIME_BITS) as usize;
+        self.offset = position & (TIME_UNIT - 1);
+        if self.deltas.len() < self.avail + KERNEL_WIDTH {
+            self.deltas.resize(self.avail + KERNEL_WIDTH, 0.0);
+        }
+    }
+
+    pub fn samples_avail(&self) -> usize {
+        self.avail
+    }
+
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.avail);
+        for (sample, delta) in out.iter_mut().zip(self.deltas.iter()).take(count) {
+            self.integrator += delta;
+            *sample = self.integrator;
+        }
+        self.deltas.drain(..count);
+        self.deltas.resize(self.deltas.len().max(KERNEL_WIDTH), 0.0);
+        self.avail -= count;
+        count
+    }
+}
+
+#[test]
+fn test_sample_count() {
+    let mut blip = BlipBuffer::new(1_789_773.0, 44100);
+    for _ in 0..60 {
+        blip.end_frame(29830);
+    }
+    let expect = (29830.0 * 60.0 * 44100.0 / 1_789_773.0) as usize;
+    assert!(blip.samples_avail().abs_diff(expect) <= 1);
+}
+
+#[test]
+fn test_step_settles() {
+    let mut blip = BlipBuffer::new(1_789_773.0, 48000);
+    blip.add_delta(1000, 0.5);
+    blip.end_frame(29830);
+    let mut out = vec![0f32; blip.samples_avail()];
+    blip.read_samples(&mut out);
+    assert!(out[0].abs() < 1e-6);
+    assert!((out[out.len() - 1] - 0.5).abs() < 1e-4);
+}
+
+#[test]
+fn test_read_past_avail() {
+    let mut blip = BlipBuffer::new(1_789_773.0, 48000);
+    // the step's kernel runs over the end of the first frame
+    blip.add_delta(29820, 0.5);
+    blip.end_frame(29830);
+    let mut first = vec![0f32; blip.samples_avail() + 64];
+    let count = blip.read_samples(&mut first);
+    blip.end_frame(29830);
+    let mut second = vec![0f32; blip.samples_avail() + 64];
+    let rest = blip.read_samples(&mut second);
+    assert!((first[count - 1] - second[0]).abs() < 0.1);
+    assert!((second[rest - 1] - 0.5).abs() < 1e-4);
+}
@@ -0,0 +1,280 @@
+use crate::error::EmuError;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+const NTSC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+const PAL_RATE_TABLE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// cpu cycles lost to a sample fetch requested after `cycle` cpu cycles, with
+/// `writes` cpu write cycles coming up. the cpu can't be halted while writing,
+/// so those overlap the halt and dummy cycles, then the fetch waits for a get
+/// (even) cycle. inside oam dma the halt is already done and the fetch only
+/// takes one get slot plus a cycle to realign, 2 cycles (the 1 and 3 cycle
+/// cases at the very end of the copy aren't modelled)
+pub fn dma_stall_cycles(cycle: u64, writes: usize, oam_dma: bool) -> usize {
+    if oam_dma {
+        return 2;
+    }
+    let start = cycle + writes.max(2) as u64;
+    let get = start + (start & 0x1);
+    (get + 1 - cycle) as usize - writes
+}
+
+pub struct DMC {
+    rate_table: &'static [u16; 16],
+    irq_enabled: bool,
+    irq_flag: bool,
+    looped: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl DMC {
+    pub fn new(region: Region) -> Self {
+        let rate_table = match region {
+            Region::Ntsc => &NTSC_RATE_TABLE,
+            Region::Pal => &PAL_RATE_TABLE,
+        };
+        Self {
+            rate_table,
+            irq_enabled: false,
+            irq_flag: false,
+            looped: false,
+            timer_period: rate_table[0] - 1,
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    /// $4010: `IL-- RRRR`, clearing the irq enable also acknowledges the irq
+    pub fn write_control(&mut self, val: u8) {
+        self.irq_enabled = val & 0x80 != 0;
+        self.looped = val & 0x40 != 0;
+        self.timer_period = self.rate_table[(val & 0xf) as usize] - 1;
+        if !self.irq_enabled {
+            self.irq_flag = false;
+        }
+    }
+
+    /// $4011: direct load of the 7-bit output level
+    pub fn write_output_level(&mut self, val: u8) {
+        self.output_level = val & 0x7f;
+    }
+
+    /// $4012: sample address = $C000 + A * 64
+    pub fn write_sample_address(&mut self, val: u8) {
+        self.sample_address = 0xc000 | (val as u16) << 6;
+    }
+
+    /// $4013: sample length = L * 16 + 1
+    pub fn write_sample_length(&mut self, val: u8) {
+        self.sample_length = (val as u16) << 4 | 1;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    /// address the memory reader wants to fetch, if the sample buffer needs refilling
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub fn dma_fill(&mut self, val: u8) {
+        self.sample_buffer = Some(val);
+        self.current_address = if self.current_address == 0xffff {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looped {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// clocked every cpu cycle, the rate table is in cpu cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if self.shift_register & 0x1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_flag);
+        state.write_bool(self.looped);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        state.write_u8(self.output_level);
+        state.write_u16(self.sample_address);
+        state.write_u16(self.sample_length);
+        state.write_u16(self.current_address);
+        state.write_u16(self.bytes_remaining);
+        state.write_bool(self.sample_buffer.is_some());
+        state.write_u8(self.sample_buffer.unwrap_or_default());
+        state.write_u8(self.shift_register);
+        state.write_u8(self.bits_remaining);
+        state.write_bool(self.silence);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.irq_enabled = state.read_bool()?;
+        self.irq_flag = state.read_bool()?;
+        self.looped = state.read_bool()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.output_level = state.read_u8()?;
+        self.sample_address = state.read_u16()?;
+        self.sample_length = state.read_u16()?;
+        self.current_address = state.read_u16()?;
+        self.bytes_remaining = state.read_u16()?;
+        let buffered = state.read_bool()?;
+        let sample = state.read_u8()?;
+        self.sample_buffer = buffered.then_some(sample);
+        self.shift_register = state.read_u8()?;
+        self.bits_remaining = state.read_u8()?;
+        self.silence = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dma_stall_cycles() {
+    assert_eq!(dma_stall_cycles(10, 0, false), 3);
+    assert_eq!(dma_stall_cycles(11, 0, false), 4);
+    assert_eq!(dma_stall_cycles(10, 1, false), 2);
+    assert_eq!(dma_stall_cycles(11, 1, false), 3);
+    assert_eq!(dma_stall_cycles(10, 2, false), 1);
+    assert_eq!(dma_stall_cycles(11, 2, false), 2);
+    assert_eq!(dma_stall_cycles(11, 0, true), 2);
+}
+
+#[test]
+fn test_sample_playback() {
+    let mut dmc = DMC::new(Region::Ntsc);
+    dmc.write_control(0x8f);
+    dmc.write_sample_address(0x00);
+    dmc.write_sample_length(0x00);
+    dmc.write_output_level(0x40);
+    dmc.set_enabled(true);
+
+    assert_eq!(dmc.dma_request(), Some(0xc000));
+    dmc.dma_fill(0xff);
+    // a single byte sample ends and raises the irq as soon as it is fetched
+    assert!(dmc.irq_flag());
+    assert!(!dmc.is_active());
+    assert_eq!(dmc.dma_request(), None);
+
+    // the buffer is picked up when the current silent byte runs out, then
+    // eight bits of ones each raise the level by two
+    for _ in 0..16 * 54 {
+        dmc.clock_timer();
+    }
+    assert_eq!(dmc.output(), 0x40 + 2 * 8);
+}
+
+#[test]
+fn test_loop_and_wrap() {
+    let mut dmc = DMC::new(Region::Ntsc);
+    dmc.write_control(0x40);
+    dmc.write_sample_address(0xff);
+    dmc.write_sample_length(0x04);
+    dmc.set_enabled(true);
+    assert_eq!(dmc.dma_request(), Some(0xffc0));
+
+    // 65 bytes starting at $FFC0 wrap around to $8000 for the last one
+    for _ in 0..0x3f {
+        dmc.dma_fill(0);
+        dmc.sample_buffer = None;
+    }
+    assert_eq!(dmc.dma_request(), Some(0xffff));
+    dmc.dma_fill(0);
+    dmc.sample_buffer = None;
+    assert_eq!(dmc.dma_request(), Some(0x8000));
+
+    // a looping sample restarts instead of raising the irq
+    while dmc.dma_request() != Some(0xffc0) {
+        dmc.dma_fill(0);
+        dmc.sample_buffer = None;
+    }
+    assert!(dmc.is_active());
+    assert!(!dmc.irq_flag());
+}
@@ -0,0 +1,351 @@
+use crate::apu::expansion::ExpansionAudio;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// wave at 63 with full gain and master volume is about 2.4 full 2A03 pulses
+const GAIN: f32 = 0.36 / 2016.0;
+/// the output stage is an RC lowpass at roughly 2 kHz, applied per cpu cycle
+const FILTER_ALPHA: f32 = 0.007;
+/// master volume as a multiplier out of 30: 2/2, 2/3, 2/4, 2/5
+const MASTER_VOLUME: [u32; 4] = [30, 20, 15, 12];
+/// modulation counter step for each 3-bit table entry, 4 resets the counter
+const MOD_STEP: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+#[derive(Default)]
+struct FdsEnvelope {
+    direct: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    counter: u32,
+}
+
+impl FdsEnvelope {
+    /// $4080/$4084: `MDSS SSSS`, direct mode sets the gain immediately
+    fn write(&mut self, val: u8) {
+        self.direct = val & 0x80 != 0;
+        self.increase = val & 0x40 != 0;
+        self.speed = val & 0x3f;
+        self.counter = 0;
+        if self.direct {
+            self.gain = self.speed;
+        }
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.direct || master_speed == 0 {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.counter = 0;
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.direct);
+        state.write_bool(self.increase);
+        state.write_u8(self.speed);
+        state.write_u8(self.gain);
+        state.write_u32(self.counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.direct = state.read_bool()?;
+        self.increase = state.read_bool()?;
+        self.speed = state.read_u8()?;
+        self.gain = state.read_u8()?;
+        self.counter = state.read_u32()?;
+        Ok(())
+    }
+}
+
+/// famicom disk system audio: one 64-step wavetable channel with a volume
+/// envelope and a frequency modulator driven by its own 32-entry table
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write: bool,
+    wave_frequency: u16,
+    wave_halt: bool,
+    wave_accumulator: u32,
+    envelope_halt: bool,
+    master_volume: u8,
+    envelope_speed: u8,
+    volume: FdsEnvelope,
+    volume_latch: u8,
+    modulation: FdsEnvelope,
+    mod_table: [u8; 64],
+    mod_position: u8,
+    mod_frequency: u16,
+    mod_halt: bool,
+    mod_accumulator: u32,
+    mod_counter: i8,
+    enabled: bool,
+    filtered: f32,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self {
+            wave_table: [0; 64],
+            wave_write: false,
+            wave_frequency: 0,
+            wave_halt: true,
+            wave_accumulator: 0,
+            envelope_halt: true,
+            master_volume: 0,
+            envelope_speed: 0xe8,
+            volume: FdsEnvelope::default(),
+            volume_latch: 0,
+            modulation: FdsEnvelope::default(),
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_frequency: 0,
+            mod_halt: true,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            enabled: true,
+            filtered: 0.0,
+        }
+    }
+}
+
+impl FdsAudio {
+    pub fn write(&mut self, address: u16, val: u8) {
+        match address {
+            0x4040..=0x407f if self.wave_write => {
+                self.wave_table[address as usize - 0x4040] = val & 0x3f;
+            }
+            0x4080 => self.volume.write(val),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0xf00) | val as u16,
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0xff) | ((val as u16 & 0xf) << 8);
+                self.wave_halt = val & 0x80 != 0;
+                self.envelope_halt = val & 0x40 != 0;
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(val),
+            0x4085 => self.mod_counter = ((val << 1) as i8) >> 1,
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0xf00) | val as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0xff) | ((val as u16 & 0xf) << 8);
+                self.mod_halt = val & 0x80 != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // each write fills two consecutive entries, only while halted
+            0x4088 if self.mod_halt => {
+                let position = self.mod_position as usize & 0x3e;
+                self.mod_table[position] = val & 0x7;
+                self.mod_table[position + 1] = val & 0x7;
+                self.mod_position = (position as u8 + 2) & 0x3f;
+            }
+            0x4089 => {
+                self.wave_write = val & 0x80 != 0;
+                self.master_volume = val & 0x3;
+            }
+            0x408a => self.envelope_speed = val,
+            _ => {}
+        }
+    }
+
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407f => Some(self.wave_table[address as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    /// $4023 bit 1, the disk system's sound register enable
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn clock_modulator(&mut self) {
+        if self.mod_halt || self.mod_frequency == 0 {
+            return;
+        }
+        self.mod_accumulator += self.mod_frequency as u32;
+        if self.mod_accumulator < 0x10000 {
+            return;
+        }
+        self.mod_accumulator &= 0xffff;
+        let entry = self.mod_table[self.mod_position as usize];
+        self.mod_position = (self.mod_position + 1) & 0x3f;
+        self.mod_counter = if entry == 4 {
+            0
+        } else {
+            // the counter is a signed 7-bit value that wraps
+            let counter = self.mod_counter.wrapping_add(MOD_STEP[entry as usize]);
+            (counter << 1) >> 1
+        };
+    }
+
+    /// wave frequency bent by the modulator, following the hardware's rounding
+    fn pitch(&self) -> u32 {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0xf;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        let frequency = self.wave_frequency as i32;
+        let mut offset = frequency * temp;
+        let remainder = offset & 0x3f;
+        offset >>= 6;
+        if remainder >= 32 {
+            offset += 1;
+        }
+        (frequency + offset).max(0) as u32
+    }
+
+    fn clock_wave(&mut self) {
+        if self.wave_halt {
+            return;
+        }
+        let position = self.wave_position();
+        self.wave_accumulator = (self.wave_accumulator + self.pitch()) & 0x3f_ffff;
+        // volume changes only reach the output when the wave wraps around
+        if self.wave_position() < position {
+            self.volume_latch = self.volume.gain.min(32);
+        }
+    }
+
+    fn wave_position(&self) -> usize {
+        (self.wave_accumulator >> 16) as usize
+    }
+}
+
+impl ExpansionAudio for FdsAudio {
+    fn clock(&mut self) {
+        if !self.envelope_halt && !self.wave_halt {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+        self.clock_modulator();
+        self.clock_wave();
+
+        // the dac holds its level while the wave table is writable
+        if !self.wave_write {
+            let sample = self.wave_table[self.wave_position()] as u32;
+            let level =
+                sample * self.volume_latch as u32 * MASTER_VOLUME[self.master_volume as usize] / 30;
+            self.filtered += (level as f32 - self.filtered) * FILTER_ALPHA;
+        }
+    }
+
+    fn output(&self) -> f32 {
+        if self.enabled {
+            self.filtered * GAIN
+        } else {
+            0.0
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.wave_table);
+        state.write_bool(self.wave_write);
+        state.write_u16(self.wave_frequency);
+        state.write_bool(self.wave_halt);
+        state.write_u32(self.wave_accumulator);
+        state.write_bool(self.envelope_halt);
+        state.write_u8(self.master_volume);
+        state.write_u8(self.envelope_speed);
+        self.volume.save_state(state);
+        state.write_u8(self.volume_latch);
+        self.modulation.save_state(state);
+        state.write_bytes(&self.mod_table);
+        state.write_u8(self.mod_position);
+        state.write_u16(self.mod_frequency);
+        state.write_bool(self.mod_halt);
+        state.write_u32(self.mod_accumulator);
+        state.write_u8(self.mod_counter as u8);
+        state.write_bool(self.enabled);
+        state.write_u32(self.filtered.to_bits());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.wave_table)?;
+        self.wave_write = state.read_bool()?;
+        self.wave_frequency = state.read_u16()?;
+        self.wave_halt = state.read_bool()?;
+        self.wave_accumulator = state.read_u32()?;
+        self.envelope_halt = state.read_bool()?;
+        self.master_volume = state.read_u8()?;
+        self.envelope_speed = state.read_u8()?;
+        self.volume.load_state(state)?;
+        self.volume_latch = state.read_u8()?;
+        self.modulation.load_state(state)?;
+        state.read_bytes(&mut self.mod_table)?;
+        self.mod_position = state.read_u8()?;
+        self.mod_frequency = state.read_u16()?;
+        self.mod_halt = state.read_bool()?;
+        self.mod_accumulator = state.read_u32()?;
+        self.mod_counter = state.read_u8()? as i8;
+        self.enabled = state.read_bool()?;
+        self.filtered = f32::from_bits(state.read_u32()?);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_wave_write_gate() {
+    let mut chip = FdsAudio::default();
+    chip.write(0x4040, 0x3f);
+    assert_eq!(chip.read(0x4040), Some(0x40));
+    chip.write(0x4089, 0x80);
+    chip.write(0x4040, 0xff);
+    assert_eq!(chip.read(0x4040), Some(0x7f));
+}
+
+#[test]
+fn test_modulation_pitch() {
+    let mut chip = FdsAudio::default();
+    chip.write(0x4082, 0x00);
+    chip.write(0x4083, 0x01);
+    assert_eq!(chip.pitch(), 0x100);
+
+    // direct gain 32 with counter 2: 2 * 32 / 16 = 4, so 256 + 256 * 4 / 64
+    chip.write(0x4084, 0x80 | 32);
+    chip.write(0x4085, 0x02);
+    assert_eq!(chip.pitch(), 0x110);
+
+    // negative counters bend downwards, the 7-bit counter wraps at 64
+    chip.write(0x4085, 0x7e);
+    assert_eq!(chip.mod_counter, -2);
+    assert_eq!(chip.pitch(), 0xf0);
+}
+
+#[test]
+fn test_modulation_table() {
+    let mut chip = FdsAudio::default();
+    chip.write(0x4087, 0x80);
+    for _ in 0..32 {
+        chip.write(0x4088, 0x01);
+    }
+    chip.write(0x4086, 0xff);
+    chip.write(0x4087, 0x0f);
+    // 100 * 0xfff / 0x10000 = 6 steps
+    for _ in 0..100 {
+        chip.clock_modulator();
+    }
+    assert_eq!(chip.mod_counter, 6);
+}
@@ -0,0 +1,157 @@
+use crate::apu::expansion::ExpansionAudio;
+use crate::apu::pulse::Pulse;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+const PULSE_GAIN: f32 = 0.15 / 15.0;
+const PCM_GAIN: f32 = 0.25 / 255.0;
+/// the mmc5 has no frame counter, envelopes and length counters run at a fixed 240 Hz
+const FRAME_PERIOD: u16 = 7457;
+
+/// mmc5 audio: two 2A03 style pulse channels without sweep and an 8-bit pcm
+/// channel written directly or captured from reads of $8000-$BFFF
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    frame_divider: u16,
+    cycles: u64,
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Self {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            frame_divider: 0,
+            cycles: 0,
+        }
+    }
+}
+
+impl Mmc5Audio {
+    pub fn write(&mut self, address: u16, val: u8) {
+        match address {
+            0x5000 => self.pulse1.write_control(val),
+            0x5002 => self.pulse1.write_timer_low(val),
+            0x5003 => self.pulse1.write_timer_high(val),
+            0x5004 => self.pulse2.write_control(val),
+            0x5006 => self.pulse2.write_timer_low(val),
+            0x5007 => self.pulse2.write_timer_high(val),
+            0x5010 => {
+                self.pcm_read_mode = val & 0x1 != 0;
+                self.pcm_irq_enabled = val & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && val != 0 => self.pcm = val,
+            0x5015 => {
+                self.pulse1.set_enabled(val & 0x1 != 0);
+                self.pulse2.set_enabled(val & 0x2 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5010 => {
+                let val = if self.pcm_irq { 0x80 } else { 0 };
+                self.pcm_irq = false;
+                Some(val)
+            }
+            0x5015 => Some(self.pulse1.is_active() as u8 | (self.pulse2.is_active() as u8) << 1),
+            _ => None,
+        }
+    }
+
+    /// the mapper forwards cpu reads of $8000-$BFFF, in read mode they set the
+    /// pcm level and a zero byte raises the irq instead
+    pub fn pcm_read(&mut self, val: u8) {
+        if !self.pcm_read_mode {
+            return;
+        }
+        if val == 0 {
+            self.pcm_irq = self.pcm_irq_enabled;
+        } else {
+            self.pcm = val;
+        }
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.pcm_irq
+    }
+}
+
+impl ExpansionAudio for Mmc5Audio {
+    fn clock(&mut self) {
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.pulse1.clock_length_sweep();
+            self.pulse2.clock_length_sweep();
+        }
+        if self.cycles & 1 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.pulse1.commit_length();
+        self.pulse2.commit_length();
+        self.cycles += 1;
+    }
+
+    fn output(&self) -> f32 {
+        let pulse = self.pulse1.output() + self.pulse2.output();
+        pulse as f32 * PULSE_GAIN + self.pcm as f32 * PCM_GAIN
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        state.write_u8(self.pcm);
+        state.write_bool(self.pcm_read_mode);
+        state.write_bool(self.pcm_irq_enabled);
+        state.write_bool(self.pcm_irq);
+        state.write_u16(self.frame_divider);
+        state.write_u64(self.cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.pcm = state.read_u8()?;
+        self.pcm_read_mode = state.read_bool()?;
+        self.pcm_irq_enabled = state.read_bool()?;
+        self.pcm_irq = state.read_bool()?;
+        self.frame_divider = state.read_u16()?;
+        self.cycles = state.read_u64()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_status_and_pcm() {
+    let mut chip = Mmc5Audio::default();
+    chip.write(0x5015, 0x03);
+    chip.write(0x5003, 0x08);
+    chip.clock();
+    assert_eq!(chip.read(0x5015), Some(0x1));
+
+    chip.write(0x5011, 0x80);
+    assert_eq!(chip.pcm, 0x80);
+
+    chip.write(0x5010, 0x81);
+    chip.pcm_read(0x40);
+    assert_eq!(chip.pcm, 0x40);
+    chip.pcm_read(0x00);
+    assert!(chip.irq_pending());
+    assert_eq!(chip.read(0x5010), Some(0x80));
+    assert!(!chip.irq_pending());
+}
@@ -0,0 +1,29 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+pub use fds::FdsAudio;
+pub use mmc5::Mmc5Audio;
+pub use namco163::Namco163Audio;
+pub use sunsoft5b::Sunsoft5BAudio;
+pub use vrc6::Vrc6Audio;
+pub use vrc7::Vrc7Audio;
+
+mod fds;
+mod mmc5;
+mod namco163;
+mod sunsoft5b;
+mod vrc6;
+mod vrc7;
+
+/// a sound chip on the cartridge, mixed into the console output
+pub trait ExpansionAudio {
+    /// advance one cpu cycle
+    fn clock(&mut self);
+    /// current level in the units of the apu mixer, where a 2A03 pulse at full
+    /// volume is about 0.15
+    fn output(&self) -> f32;
+    /// registers, internal ram and where the generators are, the cpu can read
+    /// some of it back so savestates need all of it
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError>;
+}
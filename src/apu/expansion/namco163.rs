@@ -0,0 +1,191 @@
+use crate::apu::expansion::ExpansionAudio;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// one full-volume channel on its own next to a 2A03 pulse
+const GAIN: f32 = 0.15 / 120.0;
+/// cpu cycles spent on each channel before the chip moves to the next one
+const CHANNEL_CYCLES: u8 = 15;
+
+/// namco 163: up to eight wavetable channels whose registers and 4-bit
+/// samples share 128 bytes of internal ram. only one channel is updated and
+/// output at a time, so more enabled channels means each one is quieter and
+/// the switching rate drops towards the audible range
+pub struct Namco163Audio {
+    ram: [u8; 128],
+    address: u8,
+    auto_increment: bool,
+    enabled: bool,
+    cycle: u8,
+    channel: u8,
+    output: i16,
+}
+
+impl Default for Namco163Audio {
+    fn default() -> Self {
+        Self {
+            ram: [0; 128],
+            address: 0,
+            auto_increment: false,
+            enabled: true,
+            cycle: 0,
+            channel: 0,
+            output: 0,
+        }
+    }
+}
+
+impl Namco163Audio {
+    /// $F800: `IAAA AAAA`, address into internal ram with optional auto increment
+    pub fn write_address(&mut self, val: u8) {
+        self.address = val & 0x7f;
+        self.auto_increment = val & 0x80 != 0;
+    }
+
+    /// $4800 write
+    pub fn write_data(&mut self, val: u8) {
+        self.ram[self.address as usize] = val;
+        self.step_address();
+    }
+
+    /// $4800 read
+    pub fn read_data(&mut self) -> u8 {
+        let val = self.ram[self.address as usize];
+        self.step_address();
+        val
+    }
+
+    fn step_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7f;
+        }
+    }
+
+    /// boards can silence the chip, e.g. bit 6 of $E000 on mapper 19
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn ram(&self) -> &[u8; 128] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8; 128] {
+        &mut self.ram
+    }
+
+    fn active_channels(&self) -> u8 {
+        ((self.ram[0x7f] >> 4) & 0x7) + 1
+    }
+
+    /// advance the phase of one channel and return its signed output
+    fn update_channel(&mut self, channel: u8) -> i16 {
+        let base = 0x40 + channel as usize * 8;
+        let reg = &self.ram[base..base + 8];
+        let freq = reg[0] as u32 | (reg[2] as u32) << 8 | (reg[4] as u32 & 0x3) << 16;
+        let length = 256 - (reg[4] as u32 & 0xfc);
+        let mut phase = reg[1] as u32 | (reg[3] as u32) << 8 | (reg[5] as u32) << 16;
+        let offset = reg[6] as u32;
+        let volume = (reg[7] & 0xf) as i16;
+
+        phase = (phase + freq) % (length << 16);
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        let index = ((phase >> 16) + offset) & 0xff;
+        let byte = self.ram[index as usize >> 1];
+        let sample = if index & 1 != 0 {
+            byte >> 4
+        } else {
+            byte & 0xf
+        };
+        (sample as i16 - 8) * volume
+    }
+}
+
+impl ExpansionAudio for Namco163Audio {
+    fn clock(&mut self) {
+        self.cycle += 1;
+        if self.cycle < CHANNEL_CYCLES {
+            return;
+        }
+        self.cycle = 0;
+
+        // channels are serviced from the last one downwards
+        let lowest = 8 - self.active_channels();
+        self.channel = if self.channel <= lowest {
+            7
+        } else {
+            self.channel - 1
+        };
+        self.output = self.update_channel(self.channel);
+    }
+
+    fn output(&self) -> f32 {
+        if self.enabled {
+            self.output as f32 * GAIN
+        } else {
+            0.0
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_u8(self.address);
+        state.write_bool(self.auto_increment);
+        state.write_bool(self.enabled);
+        state.write_u8(self.cycle);
+        state.write_u8(self.channel);
+        state.write_u16(self.output as u16);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.ram)?;
+        self.address = state.read_u8()?;
+        self.auto_increment = state.read_bool()?;
+        self.enabled = state.read_bool()?;
+        self.cycle = state.read_u8()?;
+        self.channel = state.read_u8()?;
+        self.output = state.read_u16()? as i16;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ram_port() {
+    let mut chip = Namco163Audio::default();
+    chip.write_address(0x80 | 0x7e);
+    chip.write_data(0x12);
+    chip.write_data(0x34);
+    // auto increment wraps inside the 128 bytes
+    chip.write_data(0x56);
+    chip.write_address(0x7e);
+    assert_eq!(chip.read_data(), 0x12);
+    assert_eq!(chip.read_data(), 0x12);
+    assert_eq!(chip.ram()[0x7f], 0x34);
+    assert_eq!(chip.ram()[0x00], 0x56);
+}
+
+#[test]
+fn test_multiplexing() {
+    let mut chip = Namco163Audio::default();
+    // two channels reading wave byte 0 (samples 0xf/0xf), only channel 6 audible
+    chip.ram[0x00] = 0xff;
+    chip.ram[0x70 + 4] = 0xfc;
+    chip.ram[0x70 + 7] = 0x0f;
+    chip.ram[0x78 + 4] = 0xfc;
+    // $7F doubles as the channel count and channel 7's volume
+    chip.ram[0x7f] = 0x10;
+
+    let mut serviced = vec![];
+    for _ in 0..CHANNEL_CYCLES as usize * 4 {
+        chip.clock();
+        if chip.cycle == 0 {
+            serviced.push(chip.channel);
+        }
+    }
+    assert_eq!(serviced, [7, 6, 7, 6]);
+    assert_eq!(chip.update_channel(6), 7 * 15);
+    assert_eq!(chip.update_channel(7), 0);
+}
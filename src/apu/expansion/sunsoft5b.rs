@@ -0,0 +1,346 @@
+use lazy_static::lazy_static;
+
+use crate::apu::expansion::ExpansionAudio;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// a full volume 5B channel is roughly twice as loud as a 2A03 pulse
+const GAIN: f32 = 0.30;
+
+lazy_static! {
+    /// 32 envelope levels 1.5 dB apart, fixed volumes use every other entry
+    static ref VOLUME_TABLE: [f32; 32] = {
+        let mut table = [0f32; 32];
+        for (i, val) in table.iter_mut().enumerate().skip(1) {
+            *val = 10f32.powf(-1.5 * (31 - i) as f32 / 20.0);
+        }
+        table
+    };
+}
+
+#[derive(Default)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.period);
+        state.write_u16(self.counter);
+        state.write_bool(self.output);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.period = state.read_u16()?;
+        self.counter = state.read_u16()?;
+        self.output = state.read_bool()?;
+        Ok(())
+    }
+}
+
+struct Envelope {
+    period: u16,
+    counter: u16,
+    step: u8,
+    continue_flag: bool,
+    attack: bool,
+    alternate: bool,
+    hold: bool,
+    holding: bool,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            step: 31,
+            continue_flag: false,
+            attack: false,
+            alternate: false,
+            hold: false,
+            holding: true,
+        }
+    }
+}
+
+impl Envelope {
+    /// R13: continue, attack, alternate and hold bits, writing restarts the envelope
+    fn write_shape(&mut self, val: u8) {
+        self.continue_flag = val & 0x8 != 0;
+        self.attack = val & 0x4 != 0;
+        self.alternate = val & 0x2 != 0;
+        self.hold = val & 0x1 != 0;
+        self.step = 0;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        if self.holding {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < self.period.max(1) {
+            return;
+        }
+        self.counter = 0;
+        self.step += 1;
+        if self.step < 32 {
+            return;
+        }
+        if !self.continue_flag {
+            // one-shot shapes end at zero volume
+            self.holding = true;
+            self.attack = false;
+            self.step = 31;
+        } else if self.hold {
+            self.holding = true;
+            self.step = 31;
+            if self.alternate {
+                self.attack = !self.attack;
+            }
+        } else {
+            self.step = 0;
+            if self.alternate {
+                self.attack = !self.attack;
+            }
+        }
+    }
+
+    fn level(&self) -> usize {
+        if self.attack {
+            self.step as usize
+        } else {
+            31 - self.step as usize
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.period);
+        state.write_u16(self.counter);
+        state.write_u8(self.step);
+        state.write_bool(self.continue_flag);
+        state.write_bool(self.attack);
+        state.write_bool(self.alternate);
+        state.write_bool(self.hold);
+        state.write_bool(self.holding);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.period = state.read_u16()?;
+        self.counter = state.read_u16()?;
+        self.step = state.read_u8()?;
+        self.continue_flag = state.read_bool()?;
+        self.attack = state.read_bool()?;
+        self.alternate = state.read_bool()?;
+        self.hold = state.read_bool()?;
+        self.holding = state.read_bool()?;
+        Ok(())
+    }
+}
+
+/// sunsoft 5B, a licensed YM2149 (AY-3-8910 family) with three square
+/// channels, a shared noise generator and a shared envelope. tones and noise
+/// tick every 16 cpu cycles, the 32-step envelope every 8
+pub struct Sunsoft5BAudio {
+    latch: u8,
+    tones: [Tone; 3],
+    noise_period: u8,
+    noise_counter: u8,
+    noise_shift: u32,
+    mixer: u8,
+    amplitude: [u8; 3],
+    envelope: Envelope,
+    prescaler: u8,
+}
+
+impl Default for Sunsoft5BAudio {
+    fn default() -> Self {
+        Self {
+            latch: 0,
+            tones: Default::default(),
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+            mixer: 0xff,
+            amplitude: [0; 3],
+            envelope: Envelope::default(),
+            prescaler: 0,
+        }
+    }
+}
+
+impl Sunsoft5BAudio {
+    /// $C000-$DFFF selects a register, $E000-$FFFF writes it
+    pub fn write(&mut self, address: u16, val: u8) {
+        match address & 0xe000 {
+            0xc000 => self.latch = val & 0xf,
+            0xe000 => self.write_register(self.latch, val),
+            _ => {}
+        }
+    }
+
+    fn write_register(&mut self, register: u8, val: u8) {
+        match register {
+            0 | 2 | 4 => {
+                let tone = &mut self.tones[register as usize / 2];
+                tone.period = (tone.period & 0xf00) | val as u16;
+            }
+            1 | 3 | 5 => {
+                let tone = &mut self.tones[register as usize / 2];
+                tone.period = (tone.period & 0xff) | ((val as u16 & 0xf) << 8);
+            }
+            6 => self.noise_period = val & 0x1f,
+            7 => self.mixer = val,
+            8..=10 => self.amplitude[register as usize - 8] = val & 0x1f,
+            11 => self.envelope.period = (self.envelope.period & 0xff00) | val as u16,
+            12 => self.envelope.period = (self.envelope.period & 0xff) | (val as u16) << 8,
+            13 => self.envelope.write_shape(val),
+            _ => {}
+        }
+    }
+
+    fn clock_noise(&mut self) {
+        self.noise_counter += 1;
+        // the noise divider runs at half the tone rate
+        if self.noise_counter >= self.noise_period.max(1) * 2 {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x1;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+    }
+
+    fn channel_output(&self, channel: usize) -> f32 {
+        let tone_off = self.mixer & (1 << channel) != 0;
+        let noise_off = self.mixer & (8 << channel) != 0;
+        let tone = tone_off || self.tones[channel].output;
+        let noise = noise_off || self.noise_shift & 0x1 != 0;
+        if !(tone && noise) {
+            return 0.0;
+        }
+        let amplitude = self.amplitude[channel];
+        let level = if amplitude & 0x10 != 0 {
+            self.envelope.level()
+        } else if amplitude & 0xf == 0 {
+            0
+        } else {
+            (amplitude as usize & 0xf) * 2 + 1
+        };
+        VOLUME_TABLE[level]
+    }
+}
+
+impl ExpansionAudio for Sunsoft5BAudio {
+    fn clock(&mut self) {
+        self.prescaler = (self.prescaler + 1) & 0xf;
+        if self.prescaler & 0x7 == 0 {
+            self.envelope.clock();
+        }
+        if self.prescaler == 0 {
+            for tone in self.tones.iter_mut() {
+                tone.clock();
+            }
+            self.clock_noise();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        (0..3)
+            .map(|channel| self.channel_output(channel))
+            .sum::<f32>()
+            * GAIN
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.latch);
+        for tone in &self.tones {
+            tone.save_state(state);
+        }
+        state.write_u8(self.noise_period);
+        state.write_u8(self.noise_counter);
+        state.write_u32(self.noise_shift);
+        state.write_u8(self.mixer);
+        state.write_bytes(&self.amplitude);
+        self.envelope.save_state(state);
+        state.write_u8(self.prescaler);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.latch = state.read_u8()?;
+        for tone in self.tones.iter_mut() {
+            tone.load_state(state)?;
+        }
+        self.noise_period = state.read_u8()?;
+        self.noise_counter = state.read_u8()?;
+        self.noise_shift = state.read_u32()?;
+        self.mixer = state.read_u8()?;
+        state.read_bytes(&mut self.amplitude)?;
+        self.envelope.load_state(state)?;
+        self.prescaler = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_envelope_shapes() {
+    let run = |shape: u8| {
+        let mut envelope = Envelope {
+            period: 1,
+            ..Envelope::default()
+        };
+        envelope.write_shape(shape);
+        let mut levels = vec![envelope.level()];
+        for _ in 0..64 {
+            envelope.clock();
+            levels.push(envelope.level());
+        }
+        levels
+    };
+    // single decay then silence
+    let levels = run(0x00);
+    assert_eq!(levels[0], 31);
+    assert_eq!(levels[32..], [0; 33]);
+    // attack then hold at the top
+    let levels = run(0x0d);
+    assert_eq!(levels[0], 0);
+    assert_eq!(levels[40], 31);
+    // repeating triangle
+    let levels = run(0x0e);
+    assert_eq!(levels[31], 31);
+    assert_eq!(levels[32], 31);
+    assert_eq!(levels[63], 0);
+}
+
+#[test]
+fn test_tone_period() {
+    let mut chip = Sunsoft5BAudio::default();
+    // channel A tone only, fixed volume 15, period 4
+    chip.write(0xc000, 7);
+    chip.write(0xe000, 0x3e);
+    chip.write(0xc000, 8);
+    chip.write(0xe000, 0x0f);
+    chip.write(0xc000, 0);
+    chip.write(0xe000, 4);
+
+    let mut edges = 0;
+    let mut last = chip.output();
+    for _ in 0..16 * 4 * 10 {
+        chip.clock();
+        if chip.output() != last {
+            edges += 1;
+            last = chip.output();
+        }
+    }
+    assert_eq!(edges, 10);
+}
@@ -0,0 +1,263 @@
+use crate::apu::expansion::ExpansionAudio;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// one step of the vrc6 dac next to a 2A03 pulse at full volume
+const GAIN: f32 = 0.15 / 15.0;
+
+#[derive(Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    /// $9000/$A000: `MDDD VVVV`
+    fn write_control(&mut self, val: u8) {
+        self.ignore_duty = val & 0x80 != 0;
+        self.duty = (val >> 4) & 0x7;
+        self.volume = val & 0xf;
+    }
+
+    fn write_period_low(&mut self, val: u8) {
+        self.period = (self.period & 0xf00) | val as u16;
+    }
+
+    /// $9002/$A002: `E--- PPPP`, disabling resets the duty position
+    fn write_period_high(&mut self, val: u8) {
+        self.period = (self.period & 0xff) | ((val as u16 & 0xf) << 8);
+        self.enabled = val & 0x80 != 0;
+        if !self.enabled {
+            self.step = 15;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0xf;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.volume);
+        state.write_u8(self.duty);
+        state.write_bool(self.ignore_duty);
+        state.write_bool(self.enabled);
+        state.write_u16(self.period);
+        state.write_u16(self.timer);
+        state.write_u8(self.step);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.volume = state.read_u8()?;
+        self.duty = state.read_u8()?;
+        self.ignore_duty = state.read_bool()?;
+        self.enabled = state.read_bool()?;
+        self.period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.step = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Vrc6Saw {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write_rate(&mut self, val: u8) {
+        self.rate = val & 0x3f;
+    }
+
+    fn write_period_low(&mut self, val: u8) {
+        self.period = (self.period & 0xf00) | val as u16;
+    }
+
+    fn write_period_high(&mut self, val: u8) {
+        self.period = (self.period & 0xff) | ((val as u16 & 0xf) << 8);
+        self.enabled = val & 0x80 != 0;
+        if !self.enabled {
+            self.step = 0;
+            self.accumulator = 0;
+        }
+    }
+
+    /// the accumulator gains the rate on every second timer clock and resets
+    /// after seven additions
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rate);
+        state.write_bool(self.enabled);
+        state.write_u16(self.period);
+        state.write_u16(self.timer);
+        state.write_u8(self.step);
+        state.write_u8(self.accumulator);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.rate = state.read_u8()?;
+        self.enabled = state.read_bool()?;
+        self.period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.step = state.read_u8()?;
+        self.accumulator = state.read_u8()?;
+        Ok(())
+    }
+}
+
+/// konami vrc6: two pulse channels with 16-step duty and a sawtooth.
+/// registers use the mapper 24 layout, boards with swapped address lines
+/// (mapper 26) translate before calling `write`
+#[derive(Default)]
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    saw: Vrc6Saw,
+    halt: bool,
+    shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn write(&mut self, address: u16, val: u8) {
+        match address & 0xf003 {
+            0x9000 => self.pulse1.write_control(val),
+            0x9001 => self.pulse1.write_period_low(val),
+            0x9002 => self.pulse1.write_period_high(val),
+            0x9003 => {
+                self.halt = val & 0x1 != 0;
+                self.shift = if val & 0x4 != 0 {
+                    8
+                } else if val & 0x2 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0xa000 => self.pulse2.write_control(val),
+            0xa001 => self.pulse2.write_period_low(val),
+            0xa002 => self.pulse2.write_period_high(val),
+            0xb000 => self.saw.write_rate(val),
+            0xb001 => self.saw.write_period_low(val),
+            0xb002 => self.saw.write_period_high(val),
+            _ => {}
+        }
+    }
+}
+
+impl ExpansionAudio for Vrc6Audio {
+    fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse1.clock(self.shift);
+        self.pulse2.clock(self.shift);
+        self.saw.clock(self.shift);
+    }
+
+    fn output(&self) -> f32 {
+        let level = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+        level as f32 * GAIN
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.saw.save_state(state);
+        state.write_bool(self.halt);
+        state.write_u8(self.shift);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.saw.load_state(state)?;
+        self.halt = state.read_bool()?;
+        self.shift = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_pulse_duty() {
+    let mut vrc6 = Vrc6Audio::default();
+    // duty 8/16, volume 15, period 0 so every cycle steps the sequencer
+    vrc6.write(0x9000, 0x7f);
+    vrc6.write(0x9001, 0x00);
+    vrc6.write(0x9002, 0x80);
+    let mut high = 0;
+    for _ in 0..16 {
+        vrc6.clock();
+        if vrc6.pulse1.output() > 0 {
+            high += 1;
+        }
+    }
+    assert_eq!(high, 8);
+
+    // ignoring the duty outputs the volume constantly
+    vrc6.write(0x9000, 0x8f);
+    for _ in 0..16 {
+        vrc6.clock();
+        assert_eq!(vrc6.pulse1.output(), 15);
+    }
+}
+
+#[test]
+fn test_saw_ramp() {
+    let mut vrc6 = Vrc6Audio::default();
+    vrc6.write(0xb000, 0x2a);
+    vrc6.write(0xb001, 0x00);
+    vrc6.write(0xb002, 0x80);
+    let mut levels = vec![];
+    for _ in 0..14 {
+        vrc6.clock();
+        levels.push(vrc6.saw.accumulator);
+    }
+    assert_eq!(*levels.iter().max().unwrap(), 6 * 0x2a);
+    assert_eq!(levels[13], 0);
+}
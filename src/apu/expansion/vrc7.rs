@@ -0,0 +1,590 @@
+use lazy_static::lazy_static;
+
+use crate::apu::expansion::ExpansionAudio;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// one channel at full volume against a 2A03 pulse at full volume
+const GAIN: f32 = 0.15 / 2048.0;
+/// the chip runs at 3.58 MHz and finishes one sample every 72 of its clocks
+const SAMPLE_CYCLES: u8 = 36;
+const CHANNELS: usize = 6;
+/// envelope attenuation is 7 bits of 0.375 dB
+const EG_MAX: u8 = 127;
+
+/// the built-in instruments of the vrc7, 1-15 (0 is the user patch)
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27], // buzzy bell
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12], // guitar
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12], // wurly
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27], // flute
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28], // clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4], // synth
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07], // trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17], // organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // bells
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02], // vibes
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12], // vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // tutti
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02], // fretless
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6], // synth bass
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06], // sweep
+];
+
+/// frequency multiplier doubled, so MULT 0 is x0.5
+const MULTIPLIER: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+/// key scale level at block 7 by the top four fnum bits, in envelope steps
+const KSL_TABLE: [u8; 16] = [
+    0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64,
+];
+/// envelope increments for the four fractional rates, picked by the eg counter
+const EG_STEPS: [[u8; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+/// vibrato steps, scaled by the top fnum bits
+const VIBRATO: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+/// tremolo depth in envelope steps, about 4.8 dB
+const TREMOLO_DEPTH: u8 = 13;
+
+lazy_static! {
+    /// quarter sine wave as -log2, 8 fractional bits
+    static ref LOG_SIN: [u16; 256] = {
+        let mut table = [0u16; 256];
+        for (i, val) in table.iter_mut().enumerate() {
+            let x = ((i as f64 + 0.5) * std::f64::consts::PI / 512.0).sin();
+            *val = (-x.log2() * 256.0).round() as u16;
+        }
+        table
+    };
+    /// fractional part of 2^x, 10 bits
+    static ref EXP: [u16; 256] = {
+        let mut table = [0u16; 256];
+        for (i, val) in table.iter_mut().enumerate() {
+            *val = ((2f64.powf(i as f64 / 256.0) - 1.0) * 1024.0).round() as u16;
+        }
+        table
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EgState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+impl EgState {
+    fn from_u8(val: u8) -> Self {
+        match val {
+            0 => EgState::Attack,
+            1 => EgState::Decay,
+            2 => EgState::Sustain,
+            3 => EgState::Release,
+            _ => EgState::Off,
+        }
+    }
+}
+
+/// one operator's share of an instrument, unpacked from the patch bytes
+#[derive(Default, Clone, Copy)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    half_sine: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Patch {
+    modulator: OperatorPatch,
+    carrier: OperatorPatch,
+    total_level: u8,
+    feedback: u8,
+}
+
+impl Patch {
+    fn from_bytes(bytes: &[u8; 8]) -> Self {
+        let operator = |reg: u8, ksl: u8, half_sine: bool, rates: u8, levels: u8| OperatorPatch {
+            tremolo: reg & 0x80 != 0,
+            vibrato: reg & 0x40 != 0,
+            sustained: reg & 0x20 != 0,
+            key_scale_rate: reg & 0x10 != 0,
+            multiplier: reg & 0xf,
+            key_scale_level: ksl >> 6,
+            half_sine,
+            attack: rates >> 4,
+            decay: rates & 0xf,
+            sustain_level: levels >> 4,
+            release: levels & 0xf,
+        };
+        Self {
+            modulator: operator(bytes[0], bytes[2], bytes[3] & 0x08 != 0, bytes[4], bytes[6]),
+            carrier: operator(bytes[1], bytes[3], bytes[3] & 0x10 != 0, bytes[5], bytes[7]),
+            total_level: bytes[2] & 0x3f,
+            feedback: bytes[3] & 0x7,
+        }
+    }
+}
+
+struct Operator {
+    phase: u32,
+    eg: u8,
+    state: EgState,
+    output: i32,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            eg: EG_MAX,
+            state: EgState::Off,
+            output: 0,
+        }
+    }
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EgState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EgState::Off {
+            self.state = EgState::Release;
+        }
+    }
+
+    /// advance the envelope by one sample, `rks` is the key scale rate offset
+    fn clock_envelope(&mut self, patch: &OperatorPatch, rks: u8, sustain_on: bool, counter: u32) {
+        let rate = match self.state {
+            EgState::Attack => patch.attack,
+            EgState::Decay => patch.decay,
+            EgState::Sustain if patch.sustained => 0,
+            EgState::Sustain => patch.release,
+            EgState::Release if sustain_on => 5,
+            EgState::Release if patch.sustained => patch.release,
+            EgState::Release => 7,
+            EgState::Off => return,
+        };
+        let step = envelope_step(rate, rks, counter);
+
+        match self.state {
+            EgState::Attack => {
+                if rate == 15 {
+                    self.eg = 0;
+                } else if step > 0 {
+                    let decrement = ((self.eg as u32 + 1) * step as u32).div_ceil(4);
+                    self.eg = self.eg.saturating_sub(decrement as u8);
+                }
+                if self.eg == 0 {
+                    self.state = EgState::Decay;
+                }
+            }
+            _ => {
+                self.eg = (self.eg + step).min(EG_MAX);
+                if self.state == EgState::Decay && self.eg >= patch.sustain_level << 3 {
+                    self.state = EgState::Sustain;
+                }
+                if self.eg >= EG_MAX - 3 {
+                    self.eg = EG_MAX;
+                    self.state = EgState::Off;
+                }
+            }
+        }
+    }
+
+    /// one sample of the operator for a phase offset in 1/1024ths of a cycle
+    fn compute(&mut self, modulation: i32, attenuation: u32, half_sine: bool) -> i32 {
+        let index = ((self.phase >> 9) as i32 + modulation) as u32 & 0x3ff;
+        self.output = if self.state == EgState::Off || half_sine && index & 0x200 != 0 {
+            0
+        } else {
+            let quarter = if index & 0x100 != 0 {
+                !index & 0xff
+            } else {
+                index & 0xff
+            };
+            let level = LOG_SIN[quarter as usize] as u32 + (attenuation << 4);
+            let magnitude = if level >= 0x1800 {
+                0
+            } else {
+                (EXP[(!level & 0xff) as usize] as i32 + 1024) >> (level >> 8)
+            };
+            if index & 0x200 != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        };
+        self.output
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u32(self.phase);
+        state.write_u8(self.eg);
+        state.write_u8(self.state as u8);
+        state.write_u32(self.output as u32);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.phase = state.read_u32()?;
+        self.eg = state.read_u8()?;
+        self.state = EgState::from_u8(state.read_u8()?);
+        self.output = state.read_u32()? as i32;
+        Ok(())
+    }
+}
+
+/// number of envelope steps to take this sample for a 4-bit rate
+fn envelope_step(rate: u8, rks: u8, counter: u32) -> u8 {
+    if rate == 0 {
+        return 0;
+    }
+    let rate = (rate * 4 + rks).min(63);
+    let (high, low) = (rate >> 2, rate & 0x3);
+    if high < 13 {
+        let shift = 13 - high;
+        if counter & ((1 << shift) - 1) != 0 {
+            return 0;
+        }
+        EG_STEPS[low as usize][((counter >> shift) & 0x7) as usize]
+    } else {
+        EG_STEPS[low as usize][(counter & 0x7) as usize] << (high - 12)
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [i32; 2],
+}
+
+impl Channel {
+    fn key_scale_level(&self, level: u8) -> u32 {
+        if level == 0 {
+            return 0;
+        }
+        let ksl =
+            (KSL_TABLE[(self.fnum >> 5) as usize] as i32) * 2 - ((7 - self.block as i32) << 4);
+        (ksl.max(0) as u32) >> (3 - level)
+    }
+
+    fn key_scale_rate(&self, patch: &OperatorPatch) -> u8 {
+        let rks = (self.block << 1) | (self.fnum >> 8) as u8;
+        if patch.key_scale_rate {
+            rks
+        } else {
+            rks >> 2
+        }
+    }
+
+    fn phase_increment(&self, patch: &OperatorPatch, vibrato: usize) -> u32 {
+        let mut fnum = (self.fnum as i32) << 1;
+        if patch.vibrato {
+            fnum += VIBRATO[vibrato] * (self.fnum >> 7) as i32;
+        }
+        (((fnum as u32) << self.block) * MULTIPLIER[patch.multiplier as usize]) >> 2
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.fnum);
+        state.write_u8(self.block);
+        state.write_bool(self.key);
+        state.write_bool(self.sustain);
+        state.write_u8(self.instrument);
+        state.write_u8(self.volume);
+        self.modulator.save_state(state);
+        self.carrier.save_state(state);
+        for feedback in self.feedback {
+            state.write_u32(feedback as u32);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.fnum = state.read_u16()?;
+        self.block = state.read_u8()?;
+        self.key = state.read_bool()?;
+        self.sustain = state.read_bool()?;
+        self.instrument = state.read_u8()? & 0xf;
+        self.volume = state.read_u8()?;
+        self.modulator.load_state(state)?;
+        self.carrier.load_state(state)?;
+        for feedback in self.feedback.iter_mut() {
+            *feedback = state.read_u32()? as i32;
+        }
+        Ok(())
+    }
+}
+
+/// konami vrc7: a six channel two-operator FM synthesizer derived from the
+/// yamaha ym2413 (OPLL), with its own set of fixed instruments
+pub struct Vrc7Audio {
+    address: u8,
+    custom: [u8; 8],
+    patches: [Patch; 16],
+    channels: [Channel; CHANNELS],
+    eg_counter: u32,
+    vibrato_counter: u32,
+    tremolo_counter: u32,
+    cycle: u8,
+    output: i32,
+    muted: bool,
+}
+
+impl Default for Vrc7Audio {
+    fn default() -> Self {
+        let mut patches = [Patch::default(); 16];
+        for (patch, bytes) in patches.iter_mut().skip(1).zip(PATCHES.iter()) {
+            *patch = Patch::from_bytes(bytes);
+        }
+        Self {
+            address: 0,
+            custom: [0; 8],
+            patches,
+            channels: Default::default(),
+            eg_counter: 0,
+            vibrato_counter: 0,
+            tremolo_counter: 0,
+            cycle: 0,
+            output: 0,
+            muted: false,
+        }
+    }
+}
+
+impl Vrc7Audio {
+    /// $9010
+    pub fn write_address(&mut self, val: u8) {
+        self.address = val;
+    }
+
+    /// $9030
+    pub fn write_data(&mut self, val: u8) {
+        let address = self.address as usize;
+        match address {
+            0x00..=0x07 => {
+                self.custom[address] = val;
+                self.patches[0] = Patch::from_bytes(&self.custom);
+            }
+            0x10..=0x15 => {
+                let channel = &mut self.channels[address - 0x10];
+                channel.fnum = (channel.fnum & 0x100) | val as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[address - 0x20];
+                channel.fnum = (channel.fnum & 0xff) | (val as u16 & 0x1) << 8;
+                channel.block = (val >> 1) & 0x7;
+                channel.sustain = val & 0x20 != 0;
+                let key = val & 0x10 != 0;
+                if key && !channel.key {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !key && channel.key {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+                channel.key = key;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[address - 0x30];
+                channel.instrument = val >> 4;
+                channel.volume = val & 0xf;
+            }
+            _ => {}
+        }
+    }
+
+    /// $E000 bit 6 holds the chip in reset and silences it
+    pub fn set_muted(&mut self, muted: bool) {
+        if muted && !self.muted {
+            let custom = self.custom;
+            *self = Self::default();
+            self.custom = custom;
+            self.patches[0] = Patch::from_bytes(&custom);
+        }
+        self.muted = muted;
+    }
+
+    fn tremolo(&self) -> u8 {
+        // triangle 0..13..0, one step every 512 samples for about 3.7 Hz
+        let step = (self.tremolo_counter >> 9) % (TREMOLO_DEPTH as u32 * 2);
+        if step < TREMOLO_DEPTH as u32 {
+            step as u8
+        } else {
+            (TREMOLO_DEPTH as u32 * 2 - step) as u8
+        }
+    }
+
+    fn generate_sample(&mut self) -> i32 {
+        self.eg_counter = self.eg_counter.wrapping_add(1);
+        self.vibrato_counter = self.vibrato_counter.wrapping_add(1);
+        self.tremolo_counter = self.tremolo_counter.wrapping_add(1);
+        let vibrato = ((self.vibrato_counter >> 10) & 0x7) as usize;
+        let tremolo = self.tremolo();
+
+        let mut output = 0;
+        for channel in self.channels.iter_mut() {
+            let patch = self.patches[channel.instrument as usize];
+
+            let rks = channel.key_scale_rate(&patch.modulator);
+            let modulator = &patch.modulator;
+            channel
+                .modulator
+                .clock_envelope(modulator, rks, channel.sustain, self.eg_counter);
+            let rks = channel.key_scale_rate(&patch.carrier);
+            let carrier = &patch.carrier;
+            channel
+                .carrier
+                .clock_envelope(carrier, rks, channel.sustain, self.eg_counter);
+
+            channel.modulator.phase += channel.phase_increment(modulator, vibrato);
+            channel.carrier.phase += channel.phase_increment(carrier, vibrato);
+            channel.modulator.phase &= 0x7ffff;
+            channel.carrier.phase &= 0x7ffff;
+
+            let am = |op: &OperatorPatch| if op.tremolo { tremolo as u32 } else { 0 };
+
+            let feedback = if patch.feedback > 0 {
+                (channel.feedback[0] + channel.feedback[1]) >> (8 - patch.feedback)
+            } else {
+                0
+            };
+            let attenuation = channel.modulator.eg as u32
+                + patch.total_level as u32 * 2
+                + channel.key_scale_level(modulator.key_scale_level)
+                + am(modulator);
+            let modulation = channel
+                .modulator
+                .compute(feedback, attenuation, modulator.half_sine);
+            channel.feedback = [channel.feedback[1], modulation];
+
+            let attenuation = channel.carrier.eg as u32
+                + channel.volume as u32 * 8
+                + channel.key_scale_level(carrier.key_scale_level)
+                + am(carrier);
+            output += channel
+                .carrier
+                .compute(modulation << 1, attenuation, carrier.half_sine);
+        }
+        output
+    }
+}
+
+impl ExpansionAudio for Vrc7Audio {
+    fn clock(&mut self) {
+        if self.muted {
+            return;
+        }
+        self.cycle += 1;
+        if self.cycle == SAMPLE_CYCLES {
+            self.cycle = 0;
+            self.output = self.generate_sample();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.output as f32 * GAIN
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.address);
+        state.write_bytes(&self.custom);
+        for channel in &self.channels {
+            channel.save_state(state);
+        }
+        state.write_u32(self.eg_counter);
+        state.write_u32(self.vibrato_counter);
+        state.write_u32(self.tremolo_counter);
+        state.write_u8(self.cycle);
+        state.write_u32(self.output as u32);
+        state.write_bool(self.muted);
+    }
+
+    /// the user patch is rebuilt from the custom registers it came from
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.address = state.read_u8()?;
+        state.read_bytes(&mut self.custom)?;
+        self.patches[0] = Patch::from_bytes(&self.custom);
+        for channel in self.channels.iter_mut() {
+            channel.load_state(state)?;
+        }
+        self.eg_counter = state.read_u32()?;
+        self.vibrato_counter = state.read_u32()?;
+        self.tremolo_counter = state.read_u32()?;
+        self.cycle = state.read_u8()?;
+        self.output = state.read_u32()? as i32;
+        self.muted = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_operator_tables() {
+    // no attenuation at the peak of the sine, silent at the zero crossing
+    let mut op = Operator {
+        state: EgState::Sustain,
+        phase: 0x100 << 9,
+        ..Default::default()
+    };
+    assert!(op.compute(0, 0, false) > 2040);
+    op.phase = 0x300 << 9;
+    assert!(op.compute(0, 0, false) < -2040);
+    assert_eq!(op.compute(0, 0, true), 0);
+    op.phase = 0;
+    assert!(op.compute(0, 0, false) < 10);
+    // 3 dB per 8 steps
+    op.phase = 0x100 << 9;
+    let quiet = op.compute(0, 16, false);
+    assert!((1000..1030).contains(&quiet));
+}
+
+#[test]
+fn test_key_on_envelope() {
+    let mut chip = Vrc7Audio::default();
+    // channel 0: flute, fnum 0x100, block 4, key on
+    for (reg, val) in [(0x30, 0x40), (0x10, 0x00), (0x20, 0x19)] {
+        chip.write_address(reg);
+        chip.write_data(val);
+    }
+    assert_eq!(chip.channels[0].carrier.state, EgState::Attack);
+
+    let mut peak = 0;
+    for _ in 0..SAMPLE_CYCLES as usize * 4000 {
+        chip.clock();
+        peak = peak.max(chip.output.abs());
+    }
+    assert!(peak > 1000);
+
+    chip.write_address(0x20);
+    chip.write_data(0x09);
+    assert_eq!(chip.channels[0].carrier.state, EgState::Release);
+    for _ in 0..SAMPLE_CYCLES as usize * 50000 {
+        chip.clock();
+    }
+    assert_eq!(chip.channels[0].carrier.state, EgState::Off);
+}
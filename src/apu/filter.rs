@@ -0,0 +1,86 @@
+use std::f32::consts::PI;
+
+/// first order filters run at the output rate, modelling the RC stages
+/// between the 2A03 and the audio jack: high-pass at 90 Hz and 440 Hz, low-pass at 14 kHz
+enum Filter {
+    HighPass {
+        alpha: f32,
+        prev_in: f32,
+        prev_out: f32,
+    },
+    LowPass {
+        alpha: f32,
+        prev_out: f32,
+    },
+}
+
+impl Filter {
+    fn high_pass(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter::HighPass {
+            alpha: rc / (rc + dt),
+            prev_in: 0.0,
+            prev_out: 0.0,
+        }
+    }
+
+    fn low_pass(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter::LowPass {
+            alpha: dt / (rc + dt),
+            prev_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        match self {
+            Filter::HighPass {
+                alpha,
+                prev_in,
+                prev_out,
+            } => {
+                *prev_out = *alpha * (*prev_out + input - *prev_in);
+                *prev_in = input;
+                *prev_out
+            }
+            Filter::LowPass { alpha, prev_out } => {
+                *prev_out += *alpha * (input - *prev_out);
+                *prev_out
+            }
+        }
+    }
+}
+
+pub struct FilterChain {
+    filters: Vec<Filter>,
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            filters: vec![
+                Filter::high_pass(90.0, sample_rate),
+                Filter::high_pass(440.0, sample_rate),
+                Filter::low_pass(14000.0, sample_rate),
+            ],
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(input, |sample, filter| filter.process(sample))
+    }
+}
+
+#[test]
+fn test_dc_removed() {
+    let mut chain = FilterChain::new(44100);
+    let mut out = 0.0;
+    for _ in 0..44100 {
+        out = chain.process(0.5);
+    }
+    assert!(out.abs() < 1e-3);
+}
@@ -0,0 +1,200 @@
+use crate::error::EmuError;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+// cpu cycles after the sequence (re)starts at which each step fires,
+// indexed by [mode][step]
+const NTSC_STEP_CYCLES: [[u32; 6]; 2] = [
+    [7457, 14913, 22371, 29828, 29829, 29830],
+    [7457, 14913, 22371, 29829, 37281, 37282],
+];
+
+const PAL_STEP_CYCLES: [[u32; 6]; 2] = [
+    [8313, 16627, 24939, 33252, 33253, 33254],
+    [8313, 16627, 24939, 33253, 41565, 41566],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameClock {
+    None,
+    /// envelopes and the triangle linear counter
+    Quarter,
+    /// quarter frame units plus length counters and sweeps
+    Half,
+}
+
+pub struct FrameCounter {
+    step_cycles: &'static [[u32; 6]; 2],
+    five_step: bool,
+    irq_inhibit: bool,
+    irq_flag: bool,
+    cycle: u32,
+    step: usize,
+    pending_write: Option<u8>,
+    write_delay: u8,
+}
+
+impl FrameCounter {
+    pub fn new(region: Region) -> Self {
+        let step_cycles = match region {
+            Region::Ntsc => &NTSC_STEP_CYCLES,
+            Region::Pal => &PAL_STEP_CYCLES,
+        };
+        Self {
+            step_cycles,
+            five_step: false,
+            irq_inhibit: false,
+            irq_flag: false,
+            cycle: 0,
+            step: 0,
+            pending_write: None,
+            write_delay: 0,
+        }
+    }
+
+    /// $4017: `MI-- ----`. the inhibit flag acts at once, the sequencer reset
+    /// lands 3 cpu cycles later when written on an apu cycle and 4 otherwise
+    pub fn write(&mut self, val: u8, apu_cycle: bool) {
+        self.irq_inhibit = val & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_flag = false;
+        }
+        self.pending_write = Some(val);
+        self.write_delay = if apu_cycle { 3 } else { 4 };
+    }
+
+    pub fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    pub fn acknowledge_irq(&mut self) {
+        self.irq_flag = false;
+    }
+
+    /// advance one cpu cycle and report which units the sequencer clocks
+    pub fn clock(&mut self) -> FrameClock {
+        let mut clock = FrameClock::None;
+
+        self.cycle += 1;
+        if self.cycle == self.step_cycles[self.five_step as usize][self.step] {
+            clock = self.run_step();
+            self.step += 1;
+            if self.step == 6 {
+                self.step = 0;
+                self.cycle = 0;
+            }
+        }
+
+        if let Some(val) = self.pending_write {
+            self.write_delay -= 1;
+            if self.write_delay == 0 {
+                self.pending_write = None;
+                self.five_step = val & 0x80 != 0;
+                self.cycle = 0;
+                self.step = 0;
+                // 5-step mode clocks everything right away
+                if self.five_step {
+                    clock = FrameClock::Half;
+                }
+            }
+        }
+        clock
+    }
+
+    fn run_step(&mut self) -> FrameClock {
+        if !self.five_step && self.step >= 3 && !self.irq_inhibit {
+            self.irq_flag = true;
+        }
+        match (self.five_step, self.step) {
+            (_, 0) | (_, 2) => FrameClock::Quarter,
+            (_, 1) | (_, 4) => FrameClock::Half,
+            _ => FrameClock::None,
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.five_step);
+        state.write_bool(self.irq_inhibit);
+        state.write_bool(self.irq_flag);
+        state.write_u32(self.cycle);
+        state.write_u8(self.step as u8);
+        state.write_bool(self.pending_write.is_some());
+        state.write_u8(self.pending_write.unwrap_or_default());
+        state.write_u8(self.write_delay);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.five_step = state.read_bool()?;
+        self.irq_inhibit = state.read_bool()?;
+        self.irq_flag = state.read_bool()?;
+        self.cycle = state.read_u32()?;
+        self.step = state.read_u8()? as usize % 6;
+        let pending = state.read_bool()?;
+        let val = state.read_u8()?;
+        self.pending_write = pending.then_some(val);
+        self.write_delay = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_four_step_sequence() {
+    let mut counter = FrameCounter::new(Region::Ntsc);
+    let mut clocks = vec![];
+    for cycle in 1..=29830 * 2 {
+        match counter.clock() {
+            FrameClock::None => {}
+            clock => clocks.push((cycle, clock)),
+        }
+    }
+    assert_eq!(
+        clocks[..4],
+        [
+            (7457, FrameClock::Quarter),
+            (14913, FrameClock::Half),
+            (22371, FrameClock::Quarter),
+            (29829, FrameClock::Half),
+        ]
+    );
+    // the sequence repeats every 29830 cycles
+    assert_eq!(clocks[4], (29830 + 7457, FrameClock::Quarter));
+    assert!(counter.irq_flag());
+}
+
+#[test]
+fn test_five_step_and_inhibit() {
+    let mut counter = FrameCounter::new(Region::Ntsc);
+    counter.write(0xc0, true);
+    assert_eq!(counter.clock(), FrameClock::None);
+    assert_eq!(counter.clock(), FrameClock::None);
+    // effects land on the third cycle after a write on an apu cycle
+    assert_eq!(counter.clock(), FrameClock::Half);
+
+    let mut clocks = vec![];
+    for cycle in 1..=37282 {
+        match counter.clock() {
+            FrameClock::None => {}
+            clock => clocks.push((cycle, clock)),
+        }
+    }
+    assert_eq!(
+        clocks,
+        [
+            (7457, FrameClock::Quarter),
+            (14913, FrameClock::Half),
+            (22371, FrameClock::Quarter),
+            (37281, FrameClock::Half),
+        ]
+    );
+    assert!(!counter.irq_flag());
+}
+
+#[test]
+fn test_write_delay() {
+    let mut counter = FrameCounter::new(Region::Ntsc);
+    counter.write(0x80, false);
+    for _ in 0..3 {
+        assert_eq!(counter.clock(), FrameClock::None);
+    }
+    assert_eq!(counter.clock(), FrameClock::Half);
+}
@@ -0,0 +1,84 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    new_halt: bool,
+    counter: u8,
+    reload_value: u8,
+    previous_value: u8,
+}
+
+impl LengthCounter {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.new_halt = halt;
+    }
+
+    /// load the counter from the upper five bits of $4003/$4007/$400B/$400F
+    pub fn load(&mut self, val: u8) {
+        if self.enabled {
+            self.reload_value = LENGTH_TABLE[(val >> 3) as usize];
+            self.previous_value = self.counter;
+        }
+    }
+
+    /// apply register writes at the end of the cpu cycle: a reload is dropped if a
+    /// length clock decremented the counter in the same cycle, and a halt change
+    /// only takes effect after that clock
+    pub fn commit(&mut self) {
+        if self.reload_value != 0 {
+            if self.counter == self.previous_value {
+                self.counter = self.reload_value;
+            }
+            self.reload_value = 0;
+        }
+        self.halt = self.new_halt;
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        self.counter
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_bool(self.halt);
+        state.write_bool(self.new_halt);
+        state.write_u8(self.counter);
+        state.write_u8(self.reload_value);
+        state.write_u8(self.previous_value);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.enabled = state.read_bool()?;
+        self.halt = state.read_bool()?;
+        self.new_halt = state.read_bool()?;
+        self.counter = state.read_u8()?;
+        self.reload_value = state.read_u8()?;
+        self.previous_value = state.read_u8()?;
+        Ok(())
+    }
+}
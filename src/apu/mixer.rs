@@ -0,0 +1,121 @@
+use lazy_static::lazy_static;
+
+use crate::apu::blip::BlipBuffer;
+use crate::apu::filter::FilterChain;
+
+lazy_static! {
+    static ref PULSE_TABLE: [f32; 31] = {
+        let mut table = [0f32; 31];
+        for (n, val) in table.iter_mut().enumerate().skip(1) {
+            *val = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        table
+    };
+    static ref TND_TABLE: [f32; 203] = {
+        let mut table = [0f32; 203];
+        for (n, val) in table.iter_mut().enumerate().skip(1) {
+            *val = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        table
+    };
+}
+
+/// the 2A03's nonlinear DAC, inputs are the raw channel levels
+pub fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse = PULSE_TABLE[(pulse1 + pulse2) as usize];
+    let tnd = TND_TABLE[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+    pulse + tnd
+}
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// turns the per cpu cycle output level into filtered samples at the host rate
+pub struct Mixer {
+    clock_rate: f64,
+    sample_rate: u32,
+    blip: BlipBuffer,
+    filters: FilterChain,
+    last_amp: f32,
+    time: u64,
+}
+
+impl Mixer {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            sample_rate,
+            blip: BlipBuffer::new(clock_rate, sample_rate),
+            filters: FilterChain::new(sample_rate),
+            last_amp: 0.0,
+            time: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.blip.set_rates(self.clock_rate, sample_rate);
+        self.filters = FilterChain::new(sample_rate);
+    }
+
+    /// feed the output level for one cpu cycle
+    pub fn clock(&mut self, amp: f32) {
+        if amp != self.last_amp {
+            self.blip.add_delta(self.time, amp - self.last_amp);
+            self.last_amp = amp;
+        }
+        self.time += 1;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.blip.end_frame(self.time);
+        self.time = 0;
+    }
+
+    pub fn samples_avail(&self) -> usize {
+        self.blip.samples_avail()
+    }
+
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        let count = self.blip.read_samples(out);
+        for sample in out[..count].iter_mut() {
+            *sample = self.filters.process(*sample);
+        }
+        count
+    }
+}
+
+#[test]
+fn test_mix_range() {
+    assert_eq!(mix(0, 0, 0, 0, 0), 0.0);
+    let full = mix(15, 15, 15, 15, 127);
+    assert!((full - 1.0).abs() < 0.01);
+}
+
+#[test]
+fn test_square_wave() {
+    let mut mixer = Mixer::new(1_789_773.0, 48000);
+    // 1 kHz square wave for one second
+    let half_period = 1_789_773 / 2000;
+    for cycle in 0..1_789_773u32 {
+        let high = (cycle / half_period) % 2 == 0;
+        mixer.clock(if high { 0.25 } else { 0.0 });
+        if cycle % 29830 == 29829 {
+            mixer.end_frame();
+        }
+    }
+    mixer.end_frame();
+
+    let mut out = vec![0f32; mixer.samples_avail()];
+    let count = mixer.read_samples(&mut out);
+    assert!(count.abs_diff(48000) <= 1);
+    // after the high-pass settles the wave swings around zero
+    let tail = &out[24000..];
+    let max = tail.iter().cloned().fold(f32::MIN, f32::max);
+    let min = tail.iter().cloned().fold(f32::MAX, f32::min);
+    assert!(max > 0.08 && min < -0.08);
+    assert!(tail.iter().sum::<f32>().abs() / (tail.len() as f32) < 0.01);
+}
@@ -0,0 +1,22 @@
+pub use apu::APU;
+pub use expansion::ExpansionAudio;
+pub use dmc::dma_stall_cycles;
+pub use mixer::{Mixer, DEFAULT_SAMPLE_RATE};
+pub use recorder::{to_i16, Recorder, Source};
+pub use wav::{hash_samples, read_wav, WavWriter};
+
+mod apu;
+mod blip;
+mod dmc;
+mod envelope;
+pub mod expansion;
+mod filter;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod pulse;
+mod recorder;
+mod sweep;
+mod triangle;
+mod wav;
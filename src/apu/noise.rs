@@ -0,0 +1,138 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::error::EmuError;
+use crate::region::Region;
+use crate::state::{StateReader, StateWriter};
+
+const NTSC_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+const PAL_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+    period_table: &'static [u16; 16],
+    short_mode: bool,
+    shift_register: u16,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new(region: Region) -> Self {
+        let period_table = match region {
+            Region::Ntsc => &NTSC_PERIOD_TABLE,
+            Region::Pal => &PAL_PERIOD_TABLE,
+        };
+        Self {
+            period_table,
+            short_mode: false,
+            shift_register: 1,
+            timer_period: period_table[0] - 1,
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// $400C: `--LC VVVV`
+    pub fn write_control(&mut self, val: u8) {
+        self.length_counter.set_halt(val & 0x20 != 0);
+        self.envelope.write(val);
+    }
+
+    /// $400E: `M--- PPPP`
+    pub fn write_period(&mut self, val: u8) {
+        self.short_mode = val & 0x80 != 0;
+        self.timer_period = self.period_table[(val & 0xf) as usize] - 1;
+    }
+
+    /// $400F: `LLLL L---`
+    pub fn write_length(&mut self, val: u8) {
+        self.length_counter.load(val);
+        self.envelope.restart();
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// clocked every cpu cycle, the period table is already in cpu cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn commit_length(&mut self) {
+        self.length_counter.commit();
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_length(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.is_active() || self.shift_register & 0x1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.short_mode);
+        state.write_u16(self.shift_register);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.short_mode = state.read_bool()?;
+        self.shift_register = state.read_u16()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.envelope.load_state(state)?;
+        self.length_counter.load_state(state)
+    }
+}
+
+#[test]
+fn test_lfsr_period() {
+    let steps_until_repeat = |mode: u8| {
+        let mut noise = Noise::new(Region::Ntsc);
+        noise.write_period(mode);
+        let mut steps = 0;
+        loop {
+            // shortest period, one lfsr step every 4 cpu cycles
+            for _ in 0..4 {
+                noise.clock_timer();
+            }
+            steps += 1;
+            if noise.shift_register == 1 {
+                return steps;
+            }
+        }
+    };
+    assert_eq!(steps_until_repeat(0x00), 32767);
+    assert_eq!(steps_until_repeat(0x80), 93);
+}
@@ -0,0 +1,148 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::sweep::{Sweep, SweepNegate};
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+pub struct Pulse {
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+    length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(negate_mode: SweepNegate) -> Self {
+        Self {
+            sweep: Some(Sweep::new(negate_mode)),
+            ..Self::without_sweep()
+        }
+    }
+
+    /// the mmc5 copies of the pulse channel have no sweep unit and never mute
+    pub fn without_sweep() -> Self {
+        Self {
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: None,
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// $4000/$4004: `DDLC VVVV`
+    pub fn write_control(&mut self, val: u8) {
+        self.duty = val >> 6;
+        self.length_counter.set_halt(val & 0x20 != 0);
+        self.envelope.write(val);
+    }
+
+    pub fn write_sweep(&mut self, val: u8) {
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.write(val);
+        }
+    }
+
+    pub fn write_timer_low(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0x700) | val as u16;
+    }
+
+    /// $4003/$4007: `LLLL LTTT`, also restarts the sequencer and envelope
+    pub fn write_timer_high(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0xff) | ((val as u16 & 0x7) << 8);
+        self.length_counter.load(val);
+        self.sequence = 0;
+        self.envelope.restart();
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn timer_period(&self) -> u16 {
+        self.timer_period
+    }
+
+    pub fn length_counter(&self) -> u8 {
+        self.length_counter.value()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// clocked once every apu cycle (two cpu cycles)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = self.sequence.wrapping_sub(1) & 0x7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn commit_length(&mut self) {
+        self.length_counter.commit();
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_length_sweep(&mut self) {
+        self.length_counter.clock();
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.clock(&mut self.timer_period);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.is_active()
+            || self
+                .sweep
+                .as_ref()
+                .is_some_and(|sweep| sweep.is_muting(self.timer_period))
+            || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.duty);
+        state.write_u8(self.sequence);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        self.envelope.save_state(state);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(state);
+        }
+        self.length_counter.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.duty = state.read_u8()?;
+        self.sequence = state.read_u8()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.envelope.load_state(state)?;
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.load_state(state)?;
+        }
+        self.length_counter.load_state(state)
+    }
+}
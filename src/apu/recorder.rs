@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use crate::apu::apu::APU;
+use crate::apu::mixer::{mix, Mixer};
+use crate::apu::wav::WavWriter;
+use crate::error::EmuError;
+
+/// what a recorded track listens to, single channels go through the dac on their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Mixed,
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Expansion,
+}
+
+impl Source {
+    pub const CHANNELS: [Source; 6] = [
+        Source::Pulse1,
+        Source::Pulse2,
+        Source::Triangle,
+        Source::Noise,
+        Source::Dmc,
+        Source::Expansion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Mixed => "mixed",
+            Source::Pulse1 => "pulse1",
+            Source::Pulse2 => "pulse2",
+            Source::Triangle => "triangle",
+            Source::Noise => "noise",
+            Source::Dmc => "dmc",
+            Source::Expansion => "expansion",
+        }
+    }
+
+    fn level(&self, apu: &APU, expansion: f32) -> f32 {
+        let (pulse1, pulse2) = apu.pulse_output();
+        let (triangle, noise, dmc) = apu.tnd_output();
+        match self {
+            Source::Mixed => apu.output() + expansion,
+            Source::Pulse1 => mix(pulse1, 0, 0, 0, 0),
+            Source::Pulse2 => mix(0, pulse2, 0, 0, 0),
+            Source::Triangle => mix(0, 0, triangle, 0, 0),
+            Source::Noise => mix(0, 0, 0, noise, 0),
+            Source::Dmc => mix(0, 0, 0, 0, dmc),
+            Source::Expansion => expansion,
+        }
+    }
+}
+
+struct Track {
+    source: Source,
+    mixer: Mixer,
+    writer: WavWriter,
+}
+
+/// writes apu output to wav files, with its own resamplers so recording never
+/// takes samples away from the pull api
+pub struct Recorder {
+    tracks: Vec<Track>,
+    buf: Vec<f32>,
+}
+
+impl Recorder {
+    /// record the mixed output to `path`, and with `stems` every channel to
+    /// `<stem>.<channel>.wav` next to it
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        clock_rate: f64,
+        sample_rate: u32,
+        stems: bool,
+    ) -> Result<Self, EmuError> {
+        let path = path.as_ref();
+        let mut sources = vec![Source::Mixed];
+        if stems {
+            sources.extend_from_slice(&Source::CHANNELS);
+        }
+
+        let mut tracks = vec![];
+        for source in sources {
+            let track_path = if source == Source::Mixed {
+                path.to_path_buf()
+            } else {
+                stem_path(path, source.name())
+            };
+            tracks.push(Track {
+                source,
+                mixer: Mixer::new(clock_rate, sample_rate),
+                writer: WavWriter::create(track_path, sample_rate, 1)?,
+            });
+        }
+        Ok(Self {
+            tracks,
+            buf: vec![],
+        })
+    }
+
+    /// `expansion` is the cartridge sound chip's level, already in mixer units
+    pub fn clock(&mut self, apu: &APU, expansion: f32) {
+        for track in self.tracks.iter_mut() {
+            track.mixer.clock(track.source.level(apu, expansion));
+        }
+    }
+
+    pub fn end_frame(&mut self) -> Result<(), EmuError> {
+        for track in self.tracks.iter_mut() {
+            track.mixer.end_frame();
+            self.buf.resize(track.mixer.samples_avail(), 0.0);
+            let count = track.mixer.read_samples(&mut self.buf);
+            let samples = self.buf[..count]
+                .iter()
+                .map(|val| to_i16(*val))
+                .collect::<Vec<i16>>();
+            track.writer.write_samples(&samples)?;
+        }
+        Ok(())
+    }
+
+    /// hash of the mixed track so far
+    pub fn hash(&self) -> u64 {
+        self.tracks[0].writer.hash()
+    }
+
+    pub fn finish(self) -> Result<(), EmuError> {
+        for track in self.tracks {
+            track.writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn to_i16(val: f32) -> i16 {
+    (val.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+fn stem_path(path: &Path, channel: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}.wav", stem, channel))
+}
+
+#[test]
+fn test_stem_path() {
+    assert_eq!(
+        stem_path(Path::new("/tmp/out/smb.wav"), "pulse1"),
+        PathBuf::from("/tmp/out/smb.pulse1.wav")
+    );
+}
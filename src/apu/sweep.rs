@@ -0,0 +1,91 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// pulse 1 negates with one's complement (adds -c - 1), pulse 2 with two's complement (adds -c)
+#[derive(Debug, Clone, Copy)]
+pub enum SweepNegate {
+    OnesComplement,
+    TwosComplement,
+}
+
+pub struct Sweep {
+    negate_mode: SweepNegate,
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+}
+
+impl Sweep {
+    pub fn new(negate_mode: SweepNegate) -> Self {
+        Self {
+            negate_mode,
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            reload: false,
+            divider: 0,
+        }
+    }
+
+    /// $4001/$4005: `EPPP NSSS`
+    pub fn write(&mut self, val: u8) {
+        self.enabled = val & 0x80 != 0;
+        self.period = (val >> 4) & 0x7;
+        self.negate = val & 0x08 != 0;
+        self.shift = val & 0x7;
+        self.reload = true;
+    }
+
+    pub fn target_period(&self, current: u16) -> u16 {
+        let change = current >> self.shift;
+        if self.negate {
+            match self.negate_mode {
+                SweepNegate::OnesComplement => current.saturating_sub(change + 1),
+                SweepNegate::TwosComplement => current.saturating_sub(change),
+            }
+        } else {
+            current + change
+        }
+    }
+
+    /// the sweep unit mutes the channel even when it is disabled
+    pub fn is_muting(&self, current: u16) -> bool {
+        current < 8 || self.target_period(current) > 0x7ff
+    }
+
+    pub fn clock(&mut self, current: &mut u16) {
+        if self.divider == 0 && self.enabled && self.shift > 0 && !self.is_muting(*current) {
+            *current = self.target_period(*current);
+        }
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    /// the negate mode is fixed by the channel, so it isn't saved
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.period);
+        state.write_bool(self.negate);
+        state.write_u8(self.shift);
+        state.write_bool(self.reload);
+        state.write_u8(self.divider);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.enabled = state.read_bool()?;
+        self.period = state.read_u8()?;
+        self.negate = state.read_bool()?;
+        self.shift = state.read_u8()?;
+        self.reload = state.read_bool()?;
+        self.divider = state.read_u8()?;
+        Ok(())
+    }
+}
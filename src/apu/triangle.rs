@@ -0,0 +1,112 @@
+use crate::apu::length_counter::LengthCounter;
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    length_counter: LengthCounter,
+}
+
+impl Triangle {
+    /// $4008: `CRRR RRRR`, the control flag doubles as the length counter halt
+    pub fn write_linear(&mut self, val: u8) {
+        self.control = val & 0x80 != 0;
+        self.linear_reload_value = val & 0x7f;
+        self.length_counter.set_halt(self.control);
+    }
+
+    pub fn write_timer_low(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0x700) | val as u16;
+    }
+
+    /// $400B: `LLLL LTTT`
+    pub fn write_timer_high(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0xff) | ((val as u16 & 0x7) << 8);
+        self.length_counter.load(val);
+        self.linear_reload = true;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// clocked every cpu cycle, the sequencer only moves while both counters are non-zero
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length_counter.is_active() {
+                self.sequence = (self.sequence + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn commit_length(&mut self) {
+        self.length_counter.commit();
+    }
+
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// a halted triangle holds its current step instead of dropping to zero.
+    /// periods below 2 run the sequencer far above audible range, which real
+    /// hardware low-passes down to the middle of the waveform
+    pub fn output(&self) -> u8 {
+        if self.timer_period < 2 {
+            7
+        } else {
+            SEQUENCE[self.sequence as usize]
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.control);
+        state.write_u8(self.linear_reload_value);
+        state.write_u8(self.linear_counter);
+        state.write_bool(self.linear_reload);
+        state.write_u8(self.sequence);
+        state.write_u16(self.timer_period);
+        state.write_u16(self.timer);
+        self.length_counter.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.control = state.read_bool()?;
+        self.linear_reload_value = state.read_u8()?;
+        self.linear_counter = state.read_u8()?;
+        self.linear_reload = state.read_bool()?;
+        self.sequence = state.read_u8()?;
+        self.timer_period = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.length_counter.load_state(state)
+    }
+}
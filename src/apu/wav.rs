@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::EmuError;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a over the little endian sample bytes, stable across platforms so it
+/// can be pinned in regression tests
+pub fn hash_samples(samples: &[i16]) -> u64 {
+    samples
+        .iter()
+        .fold(FNV_OFFSET, |hash, sample| fnv(hash, *sample))
+}
+
+fn fnv(hash: u64, sample: i16) -> u64 {
+    sample.to_le_bytes().iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn io_error(e: std::io::Error) -> EmuError {
+    EmuError::new(e.to_string(), file!().to_string(), line!())
+}
+
+/// 16-bit pcm wav file, the chunk sizes are patched in when the writer finishes
+pub struct WavWriter {
+    file: BufWriter<File>,
+    channels: u16,
+    data_len: u32,
+    hash: u64,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Self, EmuError> {
+        let file = File::create(path).map_err(io_error)?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            channels,
+            data_len: 0,
+            hash: FNV_OFFSET,
+            finished: false,
+        };
+        writer.write_header(sample_rate)?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> Result<(), EmuError> {
+        let block_align = self.channels * 2;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36u32.to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // pcm
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        self.file.write_all(&header).map_err(io_error)
+    }
+
+    /// interleaved samples when recording more than one channel
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), EmuError> {
+        for sample in samples {
+            self.file
+                .write_all(&sample.to_le_bytes())
+                .map_err(io_error)?;
+            self.hash = fnv(self.hash, *sample);
+        }
+        self.data_len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// hash of everything written so far, equal to `hash_samples` over the same data
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn finish(mut self) -> Result<(), EmuError> {
+        self.patch_sizes()
+    }
+
+    fn patch_sizes(&mut self) -> Result<(), EmuError> {
+        self.finished = true;
+        self.file.seek(SeekFrom::Start(4)).map_err(io_error)?;
+        self.file
+            .write_all(&(36 + self.data_len).to_le_bytes())
+            .map_err(io_error)?;
+        self.file.seek(SeekFrom::Start(40)).map_err(io_error)?;
+        self.file
+            .write_all(&self.data_len.to_le_bytes())
+            .map_err(io_error)?;
+        self.file.flush().map_err(io_error)
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.patch_sizes();
+        }
+    }
+}
+
+/// read a pcm wav file back as its sample rate and the samples of its first
+/// channel, 8-bit files are widened to 16
+pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<(u32, Vec<i16>), EmuError> {
+    let buf = std::fs::read(path).map_err(io_error)?;
+    let invalid = |reason: &str| EmuError::new(reason.to_string(), file!().to_string(), line!());
+    if buf.len() < 12 || &buf[0..4] != b"RIFF" || &buf[8..12] != b"WAVE" {
+        return Err(invalid("not a wav file"));
+    }
+
+    let mut format = None;
+    let mut rest = &buf[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let length = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let chunk = &rest[8..rest.len().min(8 + length)];
+        match id {
+            b"fmt " if chunk.len() >= 16 => {
+                let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+                let channels = u16::from_le_bytes([chunk[2], chunk[3]]).max(1) as usize;
+                let rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                let bits = u16::from_le_bytes([chunk[14], chunk[15]]);
+                if tag != 1 || (bits != 8 && bits != 16) {
+                    return Err(invalid("only 8 and 16-bit pcm wav files are supported"));
+                }
+                format = Some((channels, rate, bits));
+            }
+            b"data" => {
+                let (channels, rate, bits) =
+                    format.ok_or_else(|| invalid("wav data comes before its format"))?;
+                let samples = match bits {
+                    8 => chunk
+                        .chunks_exact(channels)
+                        .map(|frame| ((frame[0] as i16) - 0x80) << 8)
+                        .collect(),
+                    _ => chunk
+                        .chunks_exact(channels * 2)
+                        .map(|frame| i16::from_le_bytes([frame[0], frame[1]]))
+                        .collect(),
+                };
+                return Ok((rate, samples));
+            }
+            _ => {}
+        }
+        // chunks are padded to an even length
+        let next = 8 + length + (length & 1);
+        rest = rest.get(next..).unwrap_or_default();
+    }
+    Err(invalid("wav file has no data"))
+}
+
+#[test]
+fn test_write_wav() {
+    let path = std::env::temp_dir().join("nesrs_test_write_wav.wav");
+    let samples = [0i16, 1000, -1000, i16::MAX, i16::MIN];
+    let mut writer = WavWriter::create(&path, 44100, 1).unwrap();
+    writer.write_samples(&samples).unwrap();
+    assert_eq!(writer.hash(), hash_samples(&samples));
+    writer.finish().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(data.len(), 44 + samples.len() * 2);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 10);
+    assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 44100);
+    assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 10);
+    assert_eq!(i16::from_le_bytes([data[50], data[51]]), i16::MAX);
+}
+
+#[test]
+fn test_read_wav() {
+    let path = std::env::temp_dir().join("nesrs_test_read_wav.wav");
+    let samples = [0i16, 1000, -1000, i16::MAX, i16::MIN];
+    let mut writer = WavWriter::create(&path, 32000, 1).unwrap();
+    writer.write_samples(&samples).unwrap();
+    writer.finish().unwrap();
+
+    let (rate, read) = read_wav(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(rate, 32000);
+    assert_eq!(read, samples);
+}
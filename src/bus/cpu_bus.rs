@@ -1,36 +1,63 @@
-use std::cell::{RefCell, RefMut};
+use std::cell::{Cell, RefCell, RefMut};
 use std::rc::Rc;
 
+use crate::apu::APU;
+use crate::input::ControllerPorts;
 use crate::ram::CPURam;
 use crate::rom::Cartridge;
 
 pub struct CPUBus {
     cartridge_port: Rc<RefCell<Cartridge>>,
     ram_port: Rc<RefCell<CPURam>>,
+    apu_port: Rc<RefCell<APU>>,
+    input_port: Rc<RefCell<ControllerPorts>>,
+    /// page written to $4014, the emulator runs the copy once the write lands
+    oam_dma: Cell<Option<u8>>,
 }
 
 impl CPUBus {
-    pub fn connect(cartridge_port: Rc<RefCell<Cartridge>>, ram_port: Rc<RefCell<CPURam>>) -> Self {
+    pub fn connect(
+        cartridge_port: Rc<RefCell<Cartridge>>,
+        ram_port: Rc<RefCell<CPURam>>,
+        apu_port: Rc<RefCell<APU>>,
+        input_port: Rc<RefCell<ControllerPorts>>,
+    ) -> Self {
         Self {
             cartridge_port,
             ram_port,
+            apu_port,
+            input_port,
+            oam_dma: Cell::new(None),
         }
     }
 
     #[inline]
-    fn cartridge_port(&self) -> RefMut<Cartridge> {
+    fn cartridge_port(&self) -> RefMut<'_, Cartridge> {
         (*self.cartridge_port).borrow_mut()
     }
 
     #[inline]
-    fn ram_port(&self) -> RefMut<CPURam> {
+    fn ram_port(&self) -> RefMut<'_, CPURam> {
         (*self.ram_port).borrow_mut()
     }
 
+    #[inline]
+    fn apu_port(&self) -> RefMut<'_, APU> {
+        (*self.apu_port).borrow_mut()
+    }
+
+    #[inline]
+    fn input_port(&self) -> RefMut<'_, ControllerPorts> {
+        (*self.input_port).borrow_mut()
+    }
+
     pub fn read(&self, address: usize) -> u8 {
         match address {
-            0x0..0x6000 => self.ram_port().read(address),
-            0x6000..0x100000 => self.cartridge_port().read(address as u16),
+            0x4015 => self.apu_port().read_status(),
+            0x4016 => self.input_port().read(0),
+            0x4017 => self.input_port().read(1),
+            0x0..0x4020 => self.ram_port().read(address),
+            0x4020..0x100000 => self.cartridge_port().cpu_read(address as u16),
             _ => unreachable!(),
         }
     }
@@ -49,10 +76,24 @@ impl CPUBus {
         }
     }
 
+    pub fn take_oam_dma(&self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+
     pub fn write(&self, address: usize, val: u8) {
         match address {
-            0x0..0x6000 => self.ram_port().write(address, val),
-            0x6000..0x100000 => self.cartridge_port().write(address as u16, val),
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu_port().write(address, val),
+            0x4016 => self.input_port().write(val),
+            0x4014 => {
+                self.oam_dma.set(Some(val));
+                self.ram_port().write(address, val)
+            }
+            0x2000..0x4000 => {
+                self.cartridge_port().ppu_register_write(address as u16 & 0x2007, val);
+                self.ram_port().write(address, val)
+            }
+            0x0..0x4020 => self.ram_port().write(address, val),
+            0x4020..0x100000 => self.cartridge_port().cpu_write(address as u16, val),
             _ => unimplemented!(),
         }
     }
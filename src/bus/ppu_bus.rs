@@ -25,22 +25,35 @@ impl PPUBus {
     }
     
     #[inline]
-    fn vram_port(&self) -> RefMut<VRam> {
+    fn vram_port(&self) -> RefMut<'_, VRam> {
         (*self.vram_port).borrow_mut()
     }
     
     pub fn read(&self, address: usize) -> u8 {
         match address {
-            0x0..0x2000 => self.cartridge_port().read(address as u16),
-            0x2000..0x4000 => self.vram_port().read(address),
+            0x0..0x2000 => self.cartridge_port().ppu_read(address as u16),
+            0x2000..0x3f00 => {
+                let mut cartridge = self.cartridge_port();
+                match cartridge.nametable_read(address as u16) {
+                    Some(val) => val,
+                    None => self.vram_port().read_nametable(cartridge.nametable_offset(address as u16)),
+                }
+            }
+            0x3f00..0x4000 => self.vram_port().read_palette(address),
             _ => unimplemented!(),
         }
     }
     
     pub fn write(&self, address: usize, val: u8) {
         match address {
-            0x0..0x2000 => self.cartridge_port().write(address as u16, val),
-            0x2000..0x4000 => self.vram_port().write(address, val),
+            0x0..0x2000 => self.cartridge_port().ppu_write(address as u16, val),
+            0x2000..0x3f00 => {
+                let mut cartridge = self.cartridge_port();
+                if !cartridge.nametable_write(address as u16, val) {
+                    self.vram_port().write_nametable(cartridge.nametable_offset(address as u16), val)
+                }
+            }
+            0x3f00..0x4000 => self.vram_port().write_palette(address, val),
             _ => unimplemented!(),
         }
     }
@@ -7,8 +7,10 @@ use bitflags::bitflags;
 
 use crate::bus::CPUBus;
 use crate::cpu::opcode::{AddressingType, Inst, Opcode, INST_TABLE};
+use crate::error::EmuError;
 use crate::ram::CPURam;
 use crate::rom::Cartridge;
+use crate::state::{StateReader, StateWriter};
 
 bitflags! {
     pub struct Flags:u8  {
@@ -76,7 +78,8 @@ impl Debug for Regs {
 pub struct CPU {
     regs: Regs,
     bus_port: Rc<RefCell<CPUBus>>,
-    clock: (),
+    cycles: u64,
+    extra_cycles: usize,
 }
 
 impl Debug for CPU {
@@ -102,7 +105,9 @@ impl CPU {
         Self {
             regs,
             bus_port: port,
-            clock: (),
+            // the reset sequence takes 7 cycles before the first fetch
+            cycles: 7,
+            extra_cycles: 0,
         }
     }
 
@@ -110,8 +115,107 @@ impl CPU {
         self.regs
     }
 
+    pub fn set_regs(&mut self, regs: Regs) {
+        self.regs = regs;
+    }
+
+    /// enter the subroutine at `address` as if by a jsr whose rts lands on `return_address`
+    pub fn call(&mut self, address: u16, return_address: u16) {
+        let [low, high] = return_address.wrapping_sub(1).to_le_bytes();
+        self.push_stack(high);
+        self.push_stack(low);
+        self.regs.PC = address;
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// execute the next instruction and return the cycles it took
+    pub fn step(&mut self) -> usize {
+        let inst = self.get_next_inst();
+        self.extra_cycles = 0;
+        if inst.page_crossed_add && self.page_crossed(&inst.address_type) {
+            self.extra_cycles += 1;
+        }
+        self.exec_once(inst);
+        let cycles = inst.cycles + self.extra_cycles;
+        self.cycles += cycles as u64;
+        cycles
+    }
+
+    /// whether indexing moves the effective address onto another page
+    fn page_crossed(&self, address_type: &AddressingType) -> bool {
+        let (base, address) = match address_type {
+            AddressingType::AbsoluteX => (self.absolute(), self.absolute_x()),
+            AddressingType::AbsoluteY => (self.absolute(), self.absolute_y()),
+            AddressingType::IndirectY => {
+                let address = self.indirect_y();
+                (address.wrapping_sub(self.regs.Y as u16), address)
+            }
+            _ => return false,
+        };
+        base & 0xff00 != address & 0xff00
+    }
+
+    /// service the irq line, return the cycles taken or 0 when it is masked
+    pub fn irq(&mut self) -> usize {
+        if self.regs.P.contains(Flags::I) {
+            return 0;
+        }
+        let pc = self.regs.PC;
+        self.push_stack((pc >> 8) as u8);
+        self.push_stack(pc as u8);
+        let mut flag = self.regs.P;
+        flag.set(Flags::B, false);
+        flag.set(Flags::U, true);
+        self.push_stack(flag.bits());
+        self.regs.P.insert(Flags::I);
+        let vector = self.bus_port().read_u16(0xfffe);
+        self.regs.PC = vector;
+        self.cycles += 7;
+        7
+    }
+
+    /// the reset line: the stack pointer drops by three without any writes,
+    /// interrupts are masked and execution restarts at the reset vector
+    pub fn reset(&mut self) -> usize {
+        self.regs.SP = self.regs.SP.wrapping_sub(3);
+        self.regs.P.insert(Flags::I);
+        let vector = self.bus_port().read_u16(0xfffc);
+        self.regs.PC = vector;
+        self.cycles += 7;
+        7
+    }
+
+    /// cycles where the cpu is halted, e.g. while dma owns the bus
+    pub fn stall(&mut self, cycles: usize) {
+        self.cycles += cycles as u64;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.regs.A);
+        state.write_u8(self.regs.X);
+        state.write_u8(self.regs.Y);
+        state.write_u8(self.regs.SP);
+        state.write_u8(self.regs.P.bits());
+        state.write_u16(self.regs.PC);
+        state.write_u64(self.cycles);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.regs.A = state.read_u8()?;
+        self.regs.X = state.read_u8()?;
+        self.regs.Y = state.read_u8()?;
+        self.regs.SP = state.read_u8()?;
+        self.regs.P = Flags::from_bits_truncate(state.read_u8()?);
+        self.regs.PC = state.read_u16()?;
+        self.cycles = state.read_u64()?;
+        Ok(())
+    }
+
     #[inline]
-    fn bus_port(&self) -> RefMut<CPUBus> {
+    fn bus_port(&self) -> RefMut<'_, CPUBus> {
         (*self.bus_port).borrow_mut()
     }
 
@@ -146,7 +250,7 @@ impl CPU {
     }
 
     fn zero_page_x(&self) -> u8 {
-        (self.zero_page() as u8).wrapping_add(self.regs.X)
+        self.zero_page().wrapping_add(self.regs.X)
     }
 
     fn zero_page_y(&self) -> u8 {
@@ -155,11 +259,18 @@ impl CPU {
 
     fn realtive(&mut self) {
         let imm = self.bus_port().read(self.get_pc() + 1);
+        let next = self.regs.PC.wrapping_add(2);
         if imm & 0x80 != 0 {
             self.regs.PC -= (0x100 - imm as u16);
         } else {
             self.regs.PC += imm as u16;
         }
+
+        // taken branches cost one cycle, plus one more when landing on another page
+        self.extra_cycles += 1;
+        if next & 0xff00 != self.regs.PC.wrapping_add(2) & 0xff00 {
+            self.extra_cycles += 1;
+        }
     }
 
     fn absolute(&self) -> u16 {
@@ -184,7 +295,7 @@ impl CPU {
     fn indirect_x(&self) -> u16 {
         let address = self
             .bus_port()
-            .read((self.get_pc() + 1) as usize)
+            .read(self.get_pc() + 1)
             .wrapping_add(self.regs.X);
         self.bus_port().read_u16(address as usize)
     }
@@ -202,7 +313,7 @@ impl CPU {
     fn handle_mem_read(&mut self, address_type: &AddressingType) -> u16 {
         let handle_read = |address: usize| self.bus_port().read(address) as u16;
         match address_type {
-            AddressingType::Accumulator => self.accumulator() as u16,
+            AddressingType::Accumulator => self.accumulator(),
             AddressingType::Immediate => handle_read(self.immediate() as usize),
             AddressingType::ZeroPage => handle_read(self.zero_page() as usize),
             AddressingType::ZeroPageX => handle_read(self.zero_page_x() as usize),
@@ -243,6 +354,15 @@ impl CPU {
         }
     }
 
+    /// read-modify-write instructions write the unmodified value back a cycle
+    /// before the result, boards like the mmc1 see both writes
+    fn handle_rmw_write(&mut self, address_type: &AddressingType, old: u8, val: u8) {
+        if !matches!(address_type, AddressingType::Accumulator) {
+            self.handle_mem_write(address_type, old);
+        }
+        self.handle_mem_write(address_type, val);
+    }
+
     #[allow(unused)]
     pub fn handle_flag_update(&mut self, val: u8) {
         self.regs.P.set(Flags::Z, val == 0);
@@ -284,9 +404,10 @@ impl CPU {
             }
             Opcode::ASL => {
                 let mut imm = self.handle_mem_read(&inst.address_type);
+                let old = imm as u8;
                 self.regs.P.set(Flags::C, imm & 0x80 != 0);
                 imm *= 2;
-                self.handle_mem_write(&inst.address_type, imm as u8);
+                self.handle_rmw_write(&inst.address_type, old, imm as u8);
                 self.handle_flag_update(imm as u8);
             }
             Opcode::BCC => {
@@ -370,9 +491,10 @@ impl CPU {
                 self.handle_flag_update(val);
             }
             Opcode::DEC => {
-                let val = (self.handle_mem_read(&inst.address_type) as u8).wrapping_sub(1);
+                let old = self.handle_mem_read(&inst.address_type) as u8;
+                let val = old.wrapping_sub(1);
                 self.handle_flag_update(val);
-                self.handle_mem_write(&inst.address_type, val);
+                self.handle_rmw_write(&inst.address_type, old, val);
             }
             Opcode::DEX => {
                 self.regs.X = self.regs.X.wrapping_sub(1);
@@ -388,9 +510,10 @@ impl CPU {
                 self.handle_flag_update(self.regs.A);
             }
             Opcode::INC => {
-                let val = (self.handle_mem_read(&inst.address_type) as u8).wrapping_add(1);
+                let old = self.handle_mem_read(&inst.address_type) as u8;
+                let val = old.wrapping_add(1);
                 self.handle_flag_update(val);
-                self.handle_mem_write(&inst.address_type, val);
+                self.handle_rmw_write(&inst.address_type, old, val);
             }
             Opcode::INX => {
                 self.regs.X = self.regs.X.wrapping_add(1);
@@ -414,7 +537,7 @@ impl CPU {
                 let ret_address = self.get_pc() as u16 + inst.inst_len as u16 - 1;
                 self.push_stack((ret_address >> 8) as u8);
                 self.push_stack(ret_address as u8);
-                self.regs.PC = target_address as u16;
+                self.regs.PC = target_address;
                 return;
             }
             Opcode::LDA => {
@@ -436,7 +559,7 @@ impl CPU {
                 let val = self.handle_mem_read(&inst.address_type) as u8;
                 self.regs.P.set(Flags::C, val & 0x1 != 0);
                 let res = val / 2;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.handle_flag_update(res);
             }
             Opcode::NOP => {}
@@ -472,7 +595,7 @@ impl CPU {
                 };
                 self.regs.P.set(Flags::C, val & 0x80 != 0);
                 let res = (val << 1) | mask;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.regs.P.set(Flags::Z, self.regs.A == 0);
                 self.regs.P.set(Flags::N, res & 0x80 != 0);
             }
@@ -485,7 +608,7 @@ impl CPU {
                 };
                 self.regs.P.set(Flags::C, val & 0x1 != 0);
                 let res = (val >> 1) | mask;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.regs.P.set(Flags::Z, self.regs.A == 0);
                 self.regs.P.set(Flags::N, res & 0x80 != 0);
             }
@@ -574,18 +697,20 @@ impl CPU {
                 self.handle_mem_write(&inst.address_type, val);
             }
             Opcode::DCP => {
-                let val = (self.handle_mem_read(&inst.address_type) as u8).wrapping_sub(1);
+                let old = self.handle_mem_read(&inst.address_type) as u8;
+                let val = old.wrapping_sub(1);
                 self.handle_flag_update(val);
-                self.handle_mem_write(&inst.address_type, val);
+                self.handle_rmw_write(&inst.address_type, old, val);
                 let imm = self.handle_mem_read(&inst.address_type) as u8;
                 self.regs.P.set(Flags::C, self.regs.A >= imm);
                 let val = self.regs.A.wrapping_sub(imm);
                 self.handle_flag_update(val);
             }
             Opcode::ISC => {
-                let val = (self.handle_mem_read(&inst.address_type) as u8).wrapping_add(1);
+                let old = self.handle_mem_read(&inst.address_type) as u8;
+                let val = old.wrapping_add(1);
                 self.handle_flag_update(val);
-                self.handle_mem_write(&inst.address_type, val);
+                self.handle_rmw_write(&inst.address_type, old, val);
                 let val = self.handle_mem_read(&inst.address_type) as u8;
                 let res = self
                     .regs
@@ -605,9 +730,10 @@ impl CPU {
             }
             Opcode::SLO => {
                 let mut imm = self.handle_mem_read(&inst.address_type);
+                let old = imm as u8;
                 self.regs.P.set(Flags::C, imm & 0x80 != 0);
                 imm *= 2;
-                self.handle_mem_write(&inst.address_type, imm as u8);
+                self.handle_rmw_write(&inst.address_type, old, imm as u8);
                 self.handle_flag_update(imm as u8);
                 let imm = self.handle_mem_read(&inst.address_type) as u8;
                 self.regs.A |= imm;
@@ -622,7 +748,7 @@ impl CPU {
                 };
                 self.regs.P.set(Flags::C, val & 0x80 != 0);
                 let res = (val << 1) | mask;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.regs.P.set(Flags::Z, self.regs.A == 0);
                 self.regs.P.set(Flags::N, res & 0x80 != 0);
                 let imm = self.handle_mem_read(&inst.address_type);
@@ -633,7 +759,7 @@ impl CPU {
                 let val = self.handle_mem_read(&inst.address_type) as u8;
                 self.regs.P.set(Flags::C, val & 0x1 != 0);
                 let res = val / 2;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.handle_flag_update(res);
                 let imm = self.handle_mem_read(&inst.address_type) as u8;
                 self.regs.A ^= imm;
@@ -648,7 +774,7 @@ impl CPU {
                 };
                 self.regs.P.set(Flags::C, val & 0x1 != 0);
                 let res = (val >> 1) | mask;
-                self.handle_mem_write(&inst.address_type, res);
+                self.handle_rmw_write(&inst.address_type, val, res);
                 self.regs.P.set(Flags::Z, self.regs.A == 0);
                 self.regs.P.set(Flags::N, res & 0x80 != 0);
                 let val = self.handle_mem_read(&inst.address_type);
@@ -1,17 +1,695 @@
+use std::borrow::Cow;
+use std::cell::{RefCell, RefMut};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::apu::{dma_stall_cycles, to_i16, Mixer, Recorder, APU, DEFAULT_SAMPLE_RATE};
 use crate::bus::{CPUBus, PPUBus};
+use crate::cpu::{Flags, Regs, CPU};
+use crate::error::{handle_result, EmuError};
+use crate::input::{Buttons, Commands, ControllerPorts, Device, Movie, MovieFrame};
+use crate::ppu::FrameBuffer;
 use crate::ram::{CPURam, VRam};
-use crate::rom::Cartridge;
+use crate::region::Region;
+use crate::rom::{Cartridge, Timing};
+use crate::state::{StateReader, StateWriter};
+
+/// where subroutines started by `call` return to, never executed
+const CALL_RETURN: u16 = 0x4100;
+/// frames between writes of the .sav file, about five seconds
+const SAVE_INTERVAL: u64 = 300;
+/// leads every savestate, so states from other emulators are told apart
+const STATE_MAGIC: &[u8; 8] = b"NESRS\x1a\x00\x01";
+
+/// a movie being recorded or played back, `frame` is the next one to play
+struct MovieSession {
+    movie: Movie,
+    recording: bool,
+    frame: usize,
+}
 
 pub struct Emulator {
-    cpu: (),
-    ppu:(),
-    cpu_bus: CPUBus,
+    region: Region,
+    cpu: CPU,
+    ppu: (),
+    apu: Rc<RefCell<APU>>,
+    cpu_bus: Rc<RefCell<CPUBus>>,
     ppu_bus: PPUBus,
-    ram: CPURam,
-    vram: VRam,
-    cartridge: Cartridge,
+    ram: Rc<RefCell<CPURam>>,
+    vram: Rc<RefCell<VRam>>,
+    cartridge: Rc<RefCell<Cartridge>>,
+    input: Rc<RefCell<ControllerPorts>>,
+    frame: Rc<RefCell<FrameBuffer>>,
+    mixer: Mixer,
+    recorder: Option<Recorder>,
+    rom_name: String,
+    movie: Option<MovieSession>,
+    /// commands to go into the next recorded movie frame
+    commands: Commands,
+    frames: u64,
+    /// cpu cycles still owed to the current frame, frames are not a whole number of cycles
+    frame_cycles: f64,
+    /// cpu cycles run so far, dma only reads on even (get) cycles
+    cycles: u64,
+    oam_dma: bool,
+    /// the state the console came up in, a power cycle goes back to it
+    power_on: Vec<u8>,
+    /// nothing has run since power-on, so a movie needs no savestate
+    at_power_on: bool,
 }
 
 impl Emulator {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let name = path
+            .as_ref()
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let cartridge = Cartridge::new(path);
+        // dendy timing isn't emulated, those games get ntsc
+        let region = match cartridge.info().timing {
+            Timing::Pal => Region::Pal,
+            _ => Region::default(),
+        };
+        let mut emulator = Self::with_cartridge(cartridge, region);
+        emulator.rom_name = name;
+        emulator
+    }
+
+    pub fn with_cartridge(cartridge: Cartridge, region: Region) -> Self {
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let ram = Rc::new(RefCell::new(CPURam::default()));
+        let vram = Rc::new(RefCell::new(VRam::default()));
+        let apu = Rc::new(RefCell::new(APU::new(region)));
+        let frame = Rc::new(RefCell::new(FrameBuffer::default()));
+        let mut ports = ControllerPorts::new(frame.clone());
+        ports.plug_default(cartridge.borrow().info().expansion_device);
+        let input = Rc::new(RefCell::new(ports));
+        let cpu_bus = Rc::new(RefCell::new(CPUBus::connect(
+            cartridge.clone(),
+            ram.clone(),
+            apu.clone(),
+            input.clone(),
+        )));
+        let ppu_bus = PPUBus::connect(cartridge.clone(), vram.clone());
+
+        let reset_vector = cpu_bus.borrow().read_u16(0xfffc);
+        let cpu = CPU::new(cpu_bus.clone(), reset_vector as usize);
+
+        let mut emulator = Self {
+            region,
+            cpu,
+            ppu: (),
+            apu,
+            cpu_bus,
+            ppu_bus,
+            ram,
+            vram,
+            cartridge,
+            input,
+            frame,
+            mixer: Mixer::new(region.cpu_clock_rate(), DEFAULT_SAMPLE_RATE),
+            recorder: None,
+            rom_name: String::new(),
+            movie: None,
+            commands: Commands::empty(),
+            frames: 0,
+            cycles: 0,
+            oam_dma: false,
+            frame_cycles: 0.0,
+            power_on: vec![],
+            at_power_on: true,
+        };
+        emulator.power_on = emulator.save_state();
+        emulator
+    }
+
+    /// run until a frame worth of cpu cycles has passed and publish its audio
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        self.movie_input()?;
+        self.at_power_on = false;
+        self.frames += 1;
+        self.frame_cycles += self.region.cpu_cycles_per_frame();
+        while self.frame_cycles > 0.0 {
+            self.frame_cycles -= self.step() as f64;
+        }
+        self.end_frame();
+        // a crash shouldn't lose more than a few seconds of progress
+        if self.frames.is_multiple_of(SAVE_INTERVAL) {
+            self.write_save()?;
+        }
+        Ok(())
+    }
+
+    /// the battery backed ram as a .sav file would hold it, `None` without a battery
+    pub fn save_ram(&self) -> Option<Vec<u8>> {
+        self.cartridge.borrow().save_ram().map(Cow::into_owned)
+    }
+
+    /// replace the battery backed ram, like a .sav loaded at power-on
+    pub fn load_save_ram(&mut self, data: &[u8]) {
+        self.cartridge.borrow_mut().load_save_ram(data);
+    }
+
+    /// write the battery backed ram to `<rom>.sav` now rather than at the next
+    /// interval or on exit
+    pub fn write_save(&mut self) -> Result<(), EmuError> {
+        self.cartridge.borrow_mut().write_save()
+    }
+
+    /// press reset, a movie being recorded picks it up on the next frame
+    pub fn reset(&mut self) {
+        if self.movie.as_ref().is_some_and(|session| session.recording) {
+            self.commands.insert(Commands::SOFT_RESET);
+        }
+        self.soft_reset();
+    }
+
+    fn soft_reset(&mut self) {
+        let cycles = self.cpu.reset();
+        self.apu.borrow_mut().write(0x4015, 0);
+        self.frame_cycles -= self.clock_apu(cycles, 0) as f64;
+    }
+
+    /// switch the console off and on, a movie being recorded picks it up on the
+    /// next frame
+    pub fn hard_reset(&mut self) -> Result<(), EmuError> {
+        if self.movie.as_ref().is_some_and(|session| session.recording) {
+            self.commands.insert(Commands::HARD_RESET);
+        }
+        self.power_cycle()
+    }
+
+    /// everything goes back to how it came up except the ram a battery keeps
+    fn power_cycle(&mut self) -> Result<(), EmuError> {
+        let save_ram = self.save_ram();
+        let power_on = std::mem::take(&mut self.power_on);
+        let result = self.load_state(&power_on);
+        self.power_on = power_on;
+        result?;
+        if let Some(data) = save_ram {
+            self.load_save_ram(&data);
+        }
+        self.at_power_on = true;
+        Ok(())
+    }
+
+    /// cpu, ram, apu and cartridge state. the pads are left out, they are
+    /// strobed again every frame
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        self.cpu.save_state(&mut state);
+        self.ram.borrow().save_state(&mut state);
+        self.vram.borrow().save_state(&mut state);
+        self.apu.borrow().save_state(&mut state);
+        state.write_bytes(&self.cartridge.borrow().save_state());
+        state.write_u64(self.cycles);
+        state.write_u64(self.frame_cycles.to_bits());
+        [STATE_MAGIC.as_slice(), &state.into_inner()].concat()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        let Some(data) = data.strip_prefix(STATE_MAGIC.as_slice()) else {
+            return Err(EmuError::new(
+                "not a savestate of this emulator".to_string(),
+                file!().to_string(),
+                line!(),
+            ));
+        };
+        let mut state = StateReader::new(data);
+        self.cpu.load_state(&mut state)?;
+        self.ram.borrow_mut().load_state(&mut state)?;
+        self.vram.borrow_mut().load_state(&mut state)?;
+        self.apu.borrow_mut().load_state(&mut state)?;
+        self.cartridge.borrow_mut().load_state(&state.read_vec()?)?;
+        self.cycles = state.read_u64()?;
+        self.frame_cycles = f64::from_bits(state.read_u64()?);
+        self.at_power_on = false;
+        Ok(())
+    }
+
+    /// record input into a new movie, one started after power-on carries a
+    /// savestate to begin from
+    pub fn start_movie_recording(&mut self) -> Result<(), EmuError> {
+        let input = self.input.borrow();
+        let four_score = input.plugged(0) == Device::FourScore;
+        let mut movie = Movie::new(
+            &self.rom_name,
+            self.cartridge.borrow().checksum(),
+            self.region == Region::Pal,
+        );
+        movie.four_score = four_score;
+        if !self.at_power_on {
+            movie.savestate = Some(self.save_state());
+        }
+        for port in 0..2 {
+            movie.ports[port] = match input.plugged(port) {
+                Device::Joypad | Device::None if !four_score => input.plugged(port),
+                Device::FourScore if four_score => Device::Joypad,
+                _ => {
+                    return Err(EmuError::new(
+                        "movies can only record pads or a four score".to_string(),
+                        file!().to_string(),
+                        line!(),
+                    ))
+                }
+            };
+        }
+        drop(input);
+        self.commands = Commands::empty();
+        self.movie = Some(MovieSession {
+            movie,
+            recording: true,
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    /// replay a movie from its savestate or a power cycle, its input replaces
+    /// `set_buttons` until it ends
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), EmuError> {
+        let error = |reason: &str| EmuError::new(reason.to_string(), file!().to_string(), line!());
+        if movie.rom_checksum != self.cartridge.borrow().checksum() {
+            return Err(error("the movie was recorded on a different rom"));
+        }
+        if movie.pal != (self.region == Region::Pal) {
+            return Err(error("the movie was recorded in another region"));
+        }
+        match movie.savestate.as_ref() {
+            Some(state) => self.load_state(state)?,
+            None => self.power_cycle()?,
+        }
+        {
+            let mut input = self.input.borrow_mut();
+            for port in 0..2 {
+                let device = if movie.four_score {
+                    Device::FourScore
+                } else {
+                    movie.ports[port]
+                };
+                input.plug(port, device);
+            }
+        }
+        self.movie = Some(MovieSession {
+            movie,
+            recording: false,
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    /// whether a movie is still feeding input
+    pub fn movie_playing(&self) -> bool {
+        self.movie
+            .as_ref()
+            .is_some_and(|session| !session.recording && session.frame < session.movie.frames.len())
+    }
+
+    /// stop recording or playing, the recorded movie comes back to be saved
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|session| session.movie)
+    }
+
+    /// capture or replay the input for the frame about to run
+    fn movie_input(&mut self) -> Result<(), EmuError> {
+        let Some(session) = self.movie.as_mut() else {
+            return Ok(());
+        };
+        if session.recording {
+            let input = self.input.borrow();
+            let frame = MovieFrame {
+                commands: std::mem::take(&mut self.commands),
+                buttons: [0, 1, 2, 3].map(|player| input.buttons(player)),
+            };
+            session.movie.frames.push(frame);
+            return Ok(());
+        }
+
+        let Some(frame) = session.movie.frames.get(session.frame).copied() else {
+            return Ok(());
+        };
+        session.frame += 1;
+        if frame.commands.contains(Commands::HARD_RESET) {
+            self.power_cycle()?;
+        } else if frame.commands.contains(Commands::SOFT_RESET) {
+            self.soft_reset();
+        }
+        let mut input = self.input.borrow_mut();
+        for (player, buttons) in frame.buttons.iter().enumerate() {
+            input.set_buttons(player, *buttons);
+        }
+        Ok(())
+    }
+
+    /// hand the audio produced since the last call to the pull api and the recorder
+    pub fn end_frame(&mut self) {
+        self.mixer.end_frame();
+        if let Some(recorder) = self.recorder.as_mut() {
+            handle_result(recorder.end_frame());
+        }
+    }
+
+    /// record the mixed output to a wav file at the current sample rate, with
+    /// `stems` every apu channel also goes to `<stem>.<channel>.wav`
+    pub fn start_recording<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        stems: bool,
+    ) -> Result<(), EmuError> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(
+            path,
+            self.region.cpu_clock_rate(),
+            self.mixer.sample_rate(),
+            stems,
+        )?);
+        Ok(())
+    }
+
+    /// hash of the mixed samples recorded so far
+    pub fn recording_hash(&self) -> Option<u64> {
+        self.recorder.as_ref().map(|recorder| recorder.hash())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<(), EmuError> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// takes effect for recordings started afterwards
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.mixer.set_sample_rate(sample_rate);
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.mixer.samples_avail()
+    }
+
+    /// pull up to `out.len()` samples in -1.0..=1.0, return how many were written
+    pub fn read_samples_f32(&mut self, out: &mut [f32]) -> usize {
+        self.mixer.read_samples(out)
+    }
+
+    pub fn read_samples_i16(&mut self, out: &mut [i16]) -> usize {
+        let mut buf = vec![0f32; out.len()];
+        let count = self.mixer.read_samples(&mut buf);
+        for (sample, val) in out.iter_mut().zip(buf[..count].iter()) {
+            *sample = to_i16(*val);
+        }
+        count
+    }
+
+    /// buttons held on the pad in `port`, picked up at the game's next strobe.
+    /// ports 2 and 3 are players 3 and 4 on a multitap
+    pub fn set_buttons(&mut self, port: usize, state: Buttons) {
+        self.input.borrow_mut().set_buttons(port, state);
+    }
+
+    /// plug devices in and feed the ones set_buttons doesn't cover
+    pub fn input(&self) -> RefMut<'_, ControllerPorts> {
+        self.input.borrow_mut()
+    }
+
+    pub fn frame_buffer(&self) -> Rc<RefCell<FrameBuffer>> {
+        self.frame.clone()
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.cpu_bus.borrow().read(address as usize)
+    }
+
+    pub fn write(&mut self, address: u16, val: u8) {
+        self.cpu_bus.borrow().write(address as usize, val);
+    }
+
+    /// start the subroutine at `address` with A and X loaded and interrupts
+    /// masked, the way an nsf player calls INIT and PLAY. `step` then runs it
+    /// until `returned`
+    pub fn call(&mut self, address: u16, a: u8, x: u8) {
+        self.cpu.set_regs(Regs {
+            A: a,
+            X: x,
+            Y: 0,
+            SP: 0xfd,
+            P: Flags::new(),
+            PC: address,
+        });
+        self.cpu.call(address, CALL_RETURN);
+    }
+
+    pub fn returned(&self) -> bool {
+        self.cpu.get_regs().PC == CALL_RETURN
+    }
+
+    /// let the cpu sit idle, with everything else still running
+    pub fn idle(&mut self, cycles: usize) -> usize {
+        self.clock_apu(cycles, 0)
+    }
+
+    fn irq_line(&self) -> bool {
+        self.apu.borrow().irq_pending() || self.cartridge.borrow().irq_pending()
+    }
+
+    /// run one cpu instruction (or interrupt entry) and keep the apu in lockstep with it
+    pub fn step(&mut self) -> usize {
+        if self.irq_line() {
+            let cycles = self.cpu.irq();
+            if cycles > 0 {
+                return self.clock_apu(cycles, 0);
+            }
+        }
+
+        // register accesses land on the last cycle of an instruction, so bring
+        // the apu up to that cycle before executing it
+        let inst = self.cpu.get_next_inst();
+        let lead = inst.cycles - 1;
+        // bit n set when cycle n of the instruction is a write
+        let trailing = inst.trailing_writes();
+        let writes = ((1u16 << trailing) - 1) << (inst.cycles - trailing);
+        let mut cycles = self.clock_apu(lead, writes);
+        let inst_cycles = self.cpu.step();
+        cycles += self.clock_apu(inst_cycles - lead, writes >> lead);
+
+        let page = self.cpu_bus.borrow().take_oam_dma();
+        if let Some(page) = page {
+            cycles += self.run_oam_dma(page);
+        }
+        cycles
+    }
+
+    /// copy a page to $2004 with the cpu halted, 513 cycles or 514 when the
+    /// copy has to wait a cycle to start on a get cycle
+    fn run_oam_dma(&mut self, page: u8) -> usize {
+        {
+            let bus = self.cpu_bus.borrow();
+            for i in 0..0x100 {
+                let val = bus.read((page as usize) << 8 | i);
+                bus.write(0x2004, val);
+            }
+        }
+        let stall = 513 + (self.cycles & 0x1) as usize;
+        self.cpu.stall(stall);
+        self.oam_dma = true;
+        let cycles = self.clock_apu(stall, 0);
+        self.oam_dma = false;
+        cycles
+    }
+
+    /// clock the apu, return the elapsed cycles including dmc dma stalls.
+    /// bit n of `writes` is set when the cpu writes on the nth cycle
+    fn clock_apu(&mut self, mut cycles: usize, writes: u16) -> usize {
+        let mut elapsed = 0;
+        let mut stalled = 0;
+        while elapsed < cycles {
+            {
+                let expansion = self.cartridge.borrow_mut().clock();
+                self.input.borrow_mut().clock();
+                let mut apu = self.apu.borrow_mut();
+                apu.clock();
+                self.mixer.clock(apu.output() + expansion);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.clock(&apu, expansion);
+                }
+            }
+            elapsed += 1;
+            self.cycles += 1;
+
+            let request = self.apu.borrow().dmc_dma_request();
+            if let Some(address) = request {
+                let val = self.cpu_bus.borrow().read(address as usize);
+                self.apu.borrow_mut().dmc_dma_fill(val);
+                let ahead = writes.checked_shr((elapsed - stalled) as u32).unwrap_or(0);
+                let ahead = ahead.trailing_ones() as usize;
+                let stall = dma_stall_cycles(self.cycles, ahead, self.oam_dma);
+                self.cpu.stall(stall);
+                cycles += stall;
+                stalled += stall;
+            }
+        }
+        cycles
+    }
+}
+
+#[test]
+fn test_frame_samples() {
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    emulator.set_sample_rate(48000);
+    for _ in 0..60 {
+        emulator.run_frame().unwrap();
+    }
+    // 60 ntsc frames are just over one second
+    let mut out = vec![0i16; 48000 * 2];
+    let count = emulator.read_samples_i16(&mut out);
+    assert!(count.abs_diff(48000 * 60 * 29781 / 1_789_773) <= 2);
+    assert_eq!(emulator.samples_available(), 0);
+}
+
+#[test]
+fn test_recording() {
+    let path = std::env::temp_dir().join("nesrs_test_recording.wav");
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    emulator.start_recording(&path, true).unwrap();
+    for _ in 0..10 {
+        emulator.run_frame().unwrap();
+    }
+    let hash = emulator.recording_hash().unwrap();
+    emulator.stop_recording().unwrap();
+
+    // the recording sees the same samples as the pull api
+    let mut out = vec![0i16; emulator.samples_available()];
+    emulator.read_samples_i16(&mut out);
+    assert_eq!(hash, crate::apu::hash_samples(&out));
+
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(data.len(), 44 + out.len() * 2);
+    for channel in crate::apu::Source::CHANNELS {
+        let stem = path.with_file_name(format!("nesrs_test_recording.{}.wav", channel.name()));
+        assert!(stem.exists());
+        let _ = std::fs::remove_file(stem);
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_movie() {
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    emulator.start_movie_recording().unwrap();
+    let presses = [Buttons::START, Buttons::empty(), Buttons::A | Buttons::DOWN];
+    for buttons in presses {
+        emulator.set_buttons(0, buttons);
+        emulator.run_frame().unwrap();
+    }
+    emulator.reset();
+    emulator.run_frame().unwrap();
+    let movie = emulator.stop_movie().unwrap();
+    assert_eq!(movie.rom_filename, "nestest");
+    assert_eq!(movie.frames.len(), 4);
+    assert_eq!(movie.frames[2].buttons[0], Buttons::A | Buttons::DOWN);
+    assert_eq!(movie.frames[3].commands, Commands::SOFT_RESET);
+    let regs = emulator.cpu.get_regs();
+
+    let path = std::env::temp_dir().join("nesrs_test_movie.fm2");
+    movie.save(&path).unwrap();
+    let movie = Movie::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    emulator.play_movie(movie.clone()).unwrap();
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+    }
+    assert_eq!(emulator.input().buttons(0), Buttons::A | Buttons::DOWN);
+    assert!(emulator.movie_playing());
+    emulator.run_frame().unwrap();
+    assert!(!emulator.movie_playing());
+    // the replay ends up exactly where the recording did
+    assert_eq!(emulator.cpu.get_regs(), regs);
+    // playing again midway power cycles first
+    emulator.play_movie(movie).unwrap();
+    for _ in 0..4 {
+        emulator.run_frame().unwrap();
+    }
+    assert_eq!(emulator.cpu.get_regs(), regs);
+}
+
+#[test]
+fn test_movie_from_savestate() {
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    for _ in 0..5 {
+        emulator.run_frame().unwrap();
+    }
+    emulator.start_movie_recording().unwrap();
+    emulator.set_buttons(0, Buttons::SELECT);
+    emulator.run_frame().unwrap();
+    emulator.hard_reset().unwrap();
+    emulator.set_buttons(0, Buttons::START);
+    emulator.run_frame().unwrap();
+    emulator.run_frame().unwrap();
+    let movie = emulator.stop_movie().unwrap();
+    assert!(movie.savestate.is_some());
+    assert_eq!(movie.frames[1].commands, Commands::HARD_RESET);
+    let state = emulator.save_state();
+
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    emulator.play_movie(Movie::parse(&movie.to_fm2()).unwrap()).unwrap();
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+    }
+    assert_eq!(emulator.save_state(), state);
+}
+
+#[test]
+fn test_power_cycle() {
+    let mut emulator = Emulator::new("./test/nestest.nes");
+    let power_on = emulator.save_state();
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+    }
+    emulator.write(0x0010, 0x55);
+    assert_ne!(emulator.save_state(), power_on);
+    emulator.hard_reset().unwrap();
+    assert_eq!(emulator.save_state(), power_on);
+    assert!(emulator.load_state(b"FCSX").is_err());
+}
+
+#[test]
+fn test_rmw_dummy_write() {
+    let dir = std::env::temp_dir().join(format!("nesrs-rmw-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // mmc1 with four 16k banks, the last one $FF apart from the program
+    let mut image = b"NES\x1a\x04\x00\x10\x00".to_vec();
+    image.resize(16, 0);
+    for bank in 0..3 {
+        image.extend(std::iter::repeat_n(bank, 0x4000));
+    }
+    image.extend(std::iter::repeat_n(0xff, 0x4000));
+    let program = [
+        0xa9, 0x01, // lda #$01
+        0x8d, 0x00, 0xe0, // sta $e000, one bit into the shift register
+        0xee, 0x00, 0xff, // inc $ff00: $ff resets it, the $00 that follows is dropped
+        0xa9, 0x00, // lda #$00
+        0x8d, 0x00, 0xe0, // sta $e000 four times
+        0x8d, 0x00, 0xe0,
+        0x8d, 0x00, 0xe0,
+        0x8d, 0x00, 0xe0,
+    ];
+    let start = 16 + 0xc000;
+    image[start..start + program.len()].copy_from_slice(&program);
+    image[16 + 0xfffc..16 + 0xfffe].copy_from_slice(&[0x00, 0xc0]);
+    std::fs::write(&rom, &image).unwrap();
 
+    let mut emulator = Emulator::new(&rom);
+    for _ in 0..8 {
+        emulator.step();
+    }
+    // without the reset the fourth $00 would have finished a write of 1
+    assert_eq!(emulator.cartridge.borrow().peek(0x8000), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
 }
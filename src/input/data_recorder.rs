@@ -0,0 +1,235 @@
+use std::path::Path;
+
+use crate::apu::{read_wav, WavWriter};
+use crate::error::EmuError;
+use crate::input::InputDevice;
+use crate::region::Region;
+
+/// tapes are kept as 1-bit samples at this rate
+pub const TAPE_RATE: u32 = 32000;
+/// level of a high sample in saved audio, well clear of the zero crossing
+const AUDIO_LEVEL: i16 = 0x4000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeState {
+    Stopped,
+    Playing,
+    Recording,
+}
+
+/// the famicom data recorder: OUT2 of $4016 goes to the tape and the tape
+/// comes back on $4016 D1. it hangs off the family basic keyboard, or sits
+/// on the expansion port by itself
+pub struct DataRecorder {
+    tape: Vec<bool>,
+    position: usize,
+    state: TapeState,
+    out: bool,
+    /// cpu cycles per tape sample, the famicom only ever shipped as ntsc
+    period: f64,
+    cycles: f64,
+}
+
+impl Default for DataRecorder {
+    fn default() -> Self {
+        Self {
+            tape: vec![],
+            position: 0,
+            state: TapeState::Stopped,
+            out: false,
+            period: Region::Ntsc.cpu_clock_rate() / TAPE_RATE as f64,
+            cycles: 0.0,
+        }
+    }
+}
+
+fn io_error(e: std::io::Error) -> EmuError {
+    EmuError::new(e.to_string(), file!().to_string(), line!())
+}
+
+fn is_wav(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("wav"))
+        .unwrap_or(false)
+}
+
+impl DataRecorder {
+    pub fn state(&self) -> TapeState {
+        self.state
+    }
+
+    pub fn tape(&self) -> &[bool] {
+        &self.tape
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// play from the current position
+    pub fn play(&mut self) {
+        self.state = TapeState::Playing;
+    }
+
+    /// record over the tape from the current position on
+    pub fn record(&mut self) {
+        self.tape.truncate(self.position);
+        self.state = TapeState::Recording;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = TapeState::Stopped;
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    /// put in a tape, `.wav` files are audio and anything else a bit stream
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EmuError> {
+        let path = path.as_ref();
+        self.tape = if is_wav(path) {
+            let (rate, samples) = read_wav(path)?;
+            resample(&samples, rate)
+        } else {
+            let buf = std::fs::read(path).map_err(io_error)?;
+            buf.iter()
+                .flat_map(|byte| (0..8).map(move |n| (byte >> n) & 0x1 != 0))
+                .collect()
+        };
+        self.position = 0;
+        self.state = TapeState::Stopped;
+        Ok(())
+    }
+
+    /// write the tape out, as 16-bit audio at `TAPE_RATE` for `.wav` and
+    /// otherwise as a bit stream, eight samples a byte with the first in bit 0
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EmuError> {
+        let path = path.as_ref();
+        if is_wav(path) {
+            let samples = self
+                .tape
+                .iter()
+                .map(|&bit| if bit { AUDIO_LEVEL } else { -AUDIO_LEVEL })
+                .collect::<Vec<i16>>();
+            let mut writer = WavWriter::create(path, TAPE_RATE, 1)?;
+            writer.write_samples(&samples)?;
+            writer.finish()
+        } else {
+            let buf = self
+                .tape
+                .chunks(8)
+                .map(|bits| {
+                    bits.iter()
+                        .enumerate()
+                        .fold(0u8, |byte, (n, &bit)| byte | (bit as u8) << n)
+                })
+                .collect::<Vec<u8>>();
+            std::fs::write(path, buf).map_err(io_error)
+        }
+    }
+
+    fn input(&self) -> bool {
+        self.state == TapeState::Playing && self.tape.get(self.position) == Some(&true)
+    }
+
+    fn sample(&mut self) {
+        match self.state {
+            TapeState::Playing => {
+                self.position += 1;
+                if self.position >= self.tape.len() {
+                    self.state = TapeState::Stopped;
+                }
+            }
+            TapeState::Recording => {
+                self.tape.push(self.out);
+                self.position = self.tape.len();
+            }
+            TapeState::Stopped => {}
+        }
+    }
+}
+
+/// any rate in, `TAPE_RATE` out, the level is read off the sign of the signal
+fn resample(samples: &[i16], rate: u32) -> Vec<bool> {
+    let length = samples.len() as u64 * TAPE_RATE as u64 / rate.max(1) as u64;
+    (0..length)
+        .map(|n| samples[(n * rate as u64 / TAPE_RATE as u64) as usize] > 0)
+        .collect()
+}
+
+impl InputDevice for DataRecorder {
+    fn write(&mut self, val: u8) {
+        self.out = val & 0x4 != 0;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        match port {
+            0 => (self.input() as u8) << 1,
+            _ => 0,
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.state == TapeState::Stopped {
+            return;
+        }
+        self.cycles += 1.0;
+        if self.cycles >= self.period {
+            self.cycles -= self.period;
+            self.sample();
+        }
+    }
+
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+fn record_pattern(recorder: &mut DataRecorder, pattern: &[bool]) {
+    recorder.record();
+    for &bit in pattern {
+        recorder.write((bit as u8) << 2);
+        for _ in 0..56 {
+            recorder.clock();
+        }
+    }
+    recorder.stop();
+}
+
+#[test]
+fn test_record_and_play() {
+    let pattern = [true, false, true, true, false, false, true, false, true];
+    let mut recorder = DataRecorder::default();
+    record_pattern(&mut recorder, &pattern);
+    // 56 cycles is one tape sample, give or take the fraction
+    assert!(recorder.tape().len().abs_diff(pattern.len()) <= 1);
+
+    recorder.rewind();
+    recorder.play();
+    assert_eq!(recorder.read(0), 0x02);
+    for _ in 0..56 {
+        recorder.clock();
+    }
+    assert_eq!(recorder.read(0), 0x00);
+    assert_eq!(recorder.read(1), 0x00);
+}
+
+#[test]
+fn test_save_and_load() {
+    let pattern = (0..100).map(|n| n % 3 == 0).collect::<Vec<bool>>();
+    let mut recorder = DataRecorder::default();
+    record_pattern(&mut recorder, &pattern);
+    let tape = recorder.tape().to_vec();
+
+    for name in ["nesrs_test_tape.bin", "nesrs_test_tape.wav"] {
+        let path = std::env::temp_dir().join(name);
+        recorder.save(&path).unwrap();
+        let mut loaded = DataRecorder::default();
+        loaded.load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        // bit streams round up to whole bytes
+        assert_eq!(&loaded.tape()[..tape.len()], tape.as_slice());
+    }
+}
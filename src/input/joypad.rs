@@ -0,0 +1,105 @@
+use bitflags::bitflags;
+
+use crate::input::InputDevice;
+
+bitflags! {
+    /// in the order the pad shifts them out
+    #[derive(Default)]
+    pub struct Buttons: u8 {
+        const A = 1 << 0;
+        const B = 1 << 1;
+        const SELECT = 1 << 2;
+        const START = 1 << 3;
+        const UP = 1 << 4;
+        const DOWN = 1 << 5;
+        const LEFT = 1 << 6;
+        const RIGHT = 1 << 7;
+    }
+}
+
+/// the standard pad: a 4021 shift register that follows the buttons while the
+/// strobe is high, latches them as it falls and shifts out one bit per read
+#[derive(Default)]
+pub struct Joypad {
+    buttons: Buttons,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Joypad {
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    /// the next serial bit, official pads return 1 once all eight are out
+    pub fn shift_out(&mut self) -> u8 {
+        // the register keeps reloading while the strobe is high
+        if self.strobe {
+            return self.buttons.bits() & 0x1;
+        }
+        let bit = self.shift & 0x1;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
+
+impl InputDevice for Joypad {
+    fn write(&mut self, val: u8) {
+        let strobe = val & 0x1 != 0;
+        if self.strobe && !strobe {
+            self.shift = self.buttons.bits();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.shift_out()
+    }
+
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if slot == 0 {
+            self.buttons = buttons;
+        }
+    }
+}
+
+#[test]
+fn test_shift_register() {
+    let mut pad = Joypad::default();
+    pad.set_buttons(0, Buttons::B | Buttons::START | Buttons::LEFT);
+
+    // while strobed every read returns A
+    pad.write(1);
+    assert_eq!(pad.read(0), 0);
+    assert_eq!(pad.read(0), 0);
+
+    pad.write(0);
+    let bits = (0..10).map(|_| pad.read(0)).collect::<Vec<u8>>();
+    assert_eq!(bits, [0, 1, 0, 1, 0, 0, 1, 0, 1, 1]);
+
+    // buttons changed after the latch are not seen until the next strobe
+    pad.write(1);
+    pad.write(0);
+    pad.set_buttons(0, Buttons::A);
+    assert_eq!(pad.read(0), 0);
+}
+
+#[test]
+fn test_strobe_high() {
+    let mut pad = Joypad::default();
+    pad.write(1);
+    assert_eq!(pad.read(0), 0);
+    // A is read live while the strobe stays high
+    pad.set_buttons(0, Buttons::A | Buttons::B);
+    assert_eq!(pad.read(0), 1);
+    assert_eq!(pad.read(0), 1);
+    pad.set_buttons(0, Buttons::B);
+    assert_eq!(pad.read(0), 0);
+
+    // and the register takes whatever is held when it falls
+    pad.set_buttons(0, Buttons::A | Buttons::SELECT);
+    pad.write(1);
+    pad.write(0);
+    let bits = (0..3).map(|_| pad.read(0)).collect::<Vec<u8>>();
+    assert_eq!(bits, [1, 0, 1]);
+}
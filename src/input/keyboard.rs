@@ -0,0 +1,194 @@
+use crate::input::{DataRecorder, InputDevice};
+
+/// the keys of the family basic keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    Num0,
+    Minus,
+    Caret,
+    Yen,
+    Stop,
+    Escape,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    At,
+    LeftBracket,
+    Return,
+    Ctrl,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Colon,
+    RightBracket,
+    Kana,
+    LeftShift,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    Underscore,
+    RightShift,
+    Grph,
+    Space,
+    ClrHome,
+    Insert,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// nine rows of two columns, each column is four keys read out on D1-D4
+#[rustfmt::skip]
+const MATRIX: [[[Key; 4]; 2]; 9] = {
+    use Key::*;
+    [
+        [[RightBracket, LeftBracket, Return, F8], [Stop, Yen, RightShift, Kana]],
+        [[Semicolon, Colon, At, F7], [Caret, Minus, Slash, Underscore]],
+        [[K, L, O, F6], [Num0, P, Comma, Period]],
+        [[J, U, I, F5], [Num8, Num9, N, M]],
+        [[H, G, Y, F4], [Num6, Num7, V, B]],
+        [[D, R, T, F3], [Num4, Num5, C, F]],
+        [[A, S, W, F2], [Num3, E, Z, X]],
+        [[Ctrl, Q, Escape, F1], [Num2, Num1, Grph, LeftShift]],
+        [[Left, Right, Up, ClrHome], [Insert, Delete, Space, Down]],
+    ]
+};
+
+/// row, column and bit of a key in the matrix
+fn locate(key: Key) -> (usize, usize, usize) {
+    for (row, columns) in MATRIX.iter().enumerate() {
+        for (column, keys) in columns.iter().enumerate() {
+            if let Some(bit) = keys.iter().position(|&k| k == key) {
+                return (row, column, bit);
+            }
+        }
+    }
+    unreachable!("every key is in the matrix")
+}
+
+/// the family basic keyboard on the famicom expansion port. OUT0 goes back
+/// to the first row, OUT1 picks the column and moves to the next row when
+/// it drops, OUT2 enables the matrix. $4017 D1-D4 read the selected column
+/// with pressed keys low. the data recorder plugs into the keyboard
+#[derive(Default)]
+pub struct FamilyBasicKeyboard {
+    pressed: [[u8; 2]; 9],
+    row: usize,
+    column: usize,
+    enabled: bool,
+    recorder: DataRecorder,
+}
+
+impl InputDevice for FamilyBasicKeyboard {
+    fn write(&mut self, val: u8) {
+        let column = (val as usize >> 1) & 0x1;
+        self.enabled = val & 0x4 != 0;
+        if self.enabled {
+            if column == 0 && self.column == 1 {
+                self.row = (self.row + 1) % 10;
+            }
+            if val & 0x1 != 0 {
+                self.row = 0;
+            }
+        }
+        self.column = column;
+        self.recorder.write(val);
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        match port {
+            0 => self.recorder.read(port),
+            _ if !self.enabled => 0,
+            // past the last row reads as nothing pressed, which is how
+            // family basic finds the keyboard
+            _ => match self.pressed.get(self.row) {
+                Some(columns) => !columns[self.column] << 1 & 0x1e,
+                None => 0x1e,
+            },
+        }
+    }
+
+    fn set_key(&mut self, key: Key, pressed: bool) {
+        let (row, column, bit) = locate(key);
+        if pressed {
+            self.pressed[row][column] |= 1 << bit;
+        } else {
+            self.pressed[row][column] &= !(1 << bit);
+        }
+    }
+
+    fn clock(&mut self) {
+        self.recorder.clock();
+    }
+
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        Some(&mut self.recorder)
+    }
+}
+
+#[test]
+fn test_scan_matrix() {
+    let mut keyboard = FamilyBasicKeyboard::default();
+    keyboard.set_key(Key::A, true);
+    keyboard.set_key(Key::Down, true);
+
+    // the way family basic scans: reset, then column 0 and 1 of every row
+    keyboard.write(0x05);
+    let mut rows = vec![];
+    for _ in 0..9 {
+        keyboard.write(0x04);
+        let low = keyboard.read(1);
+        keyboard.write(0x06);
+        let high = keyboard.read(1);
+        rows.push((low, high));
+    }
+    assert_eq!(rows[6], (0x1c, 0x1e));
+    assert_eq!(rows[8], (0x1e, 0x0e));
+    assert!(rows[..6].iter().all(|&row| row == (0x1e, 0x1e)));
+
+    keyboard.write(0x04);
+    assert_eq!(keyboard.read(1), 0x1e);
+    keyboard.set_key(Key::A, false);
+    keyboard.write(0x00);
+    assert_eq!(keyboard.read(1), 0x00);
+}
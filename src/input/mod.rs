@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ppu::FrameBuffer;
+
+pub use data_recorder::{DataRecorder, TapeState, TAPE_RATE};
+pub use joypad::{Buttons, Joypad};
+pub use keyboard::{FamilyBasicKeyboard, Key};
+pub use movie::{Commands, Movie, MovieFrame};
+pub use multitap::{FourScore, HoriAdapter};
+pub use paddle::ArkanoidPaddle;
+pub use power_pad::PowerPad;
+pub use zapper::Zapper;
+
+mod data_recorder;
+mod joypad;
+mod keyboard;
+mod movie;
+mod multitap;
+mod paddle;
+mod power_pad;
+mod zapper;
+
+/// the bits of a $4016/$4017 read no device drives, left over from the high
+/// byte of the address on the data bus
+pub const OPEN_BUS: u8 = 0x40;
+
+/// anything plugged into a controller port or the famicom expansion port
+pub trait InputDevice {
+    /// $4016 write: OUT0 is the strobe every device sees, OUT1/OUT2 only reach
+    /// expansion port devices
+    fn write(&mut self, val: u8);
+    /// the data lines D0-D4 for a read of $4016 (`port` 0) or $4017 (1), a
+    /// device on a controller port only ever sees its own
+    fn read(&mut self, port: usize) -> u8;
+    /// pads and multitaps take button state, `slot` picks the pad on a multitap
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {}
+    /// light guns aim at a pixel, paddles use `x` as the knob
+    fn set_position(&mut self, x: i32, y: i32) {}
+    /// light gun trigger or paddle button
+    fn set_trigger(&mut self, pulled: bool) {}
+    /// power pad, bit n is pad n + 1
+    fn set_pads(&mut self, pressed: u16) {}
+    /// keyboards
+    fn set_key(&mut self, key: Key, pressed: bool) {}
+    /// once per cpu cycle, for devices that keep time of their own
+    fn clock(&mut self) {}
+    /// the data recorder, if the device is or carries one
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        None
+    }
+}
+
+/// what can go into one of the two controller ports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    None,
+    Joypad,
+    /// one half of a four score, put one in each port
+    FourScore,
+    Zapper,
+    ArkanoidPaddle,
+    PowerPad,
+}
+
+/// what can go into the famicom expansion port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpansionDevice {
+    None,
+    HoriAdapter,
+    ArkanoidPaddle,
+    /// with the data recorder plugged into it
+    FamilyBasicKeyboard,
+    DataRecorder,
+}
+
+/// the two controller ports and the expansion port as the cpu sees them
+/// through $4016 and $4017
+pub struct ControllerPorts {
+    ports: [Option<Box<dyn InputDevice>>; 2],
+    expansion: Option<Box<dyn InputDevice>>,
+    plugged: [Device; 2],
+    /// what each player was last given, movies record from here
+    buttons: [Buttons; 4],
+    frame: Rc<RefCell<FrameBuffer>>,
+}
+
+impl Default for ControllerPorts {
+    fn default() -> Self {
+        Self::new(Rc::new(RefCell::new(FrameBuffer::default())))
+    }
+}
+
+impl ControllerPorts {
+    /// `frame` is what light guns look at
+    pub fn new(frame: Rc<RefCell<FrameBuffer>>) -> Self {
+        Self {
+            ports: [
+                Some(Box::new(Joypad::default())),
+                Some(Box::new(Joypad::default())),
+            ],
+            expansion: None,
+            plugged: [Device::Joypad; 2],
+            buttons: [Buttons::empty(); 4],
+            frame,
+        }
+    }
+
+    pub fn plug(&mut self, port: usize, device: Device) {
+        self.ports[port] = match device {
+            Device::None => None,
+            Device::Joypad => Some(Box::new(Joypad::default())),
+            Device::FourScore => Some(Box::new(FourScore::new(port))),
+            Device::Zapper => Some(Box::new(Zapper::new(self.frame.clone()))),
+            Device::ArkanoidPaddle => Some(Box::new(ArkanoidPaddle::new(false))),
+            Device::PowerPad => Some(Box::new(PowerPad::default())),
+        };
+        self.plugged[port] = device;
+    }
+
+    pub fn plug_expansion(&mut self, device: ExpansionDevice) {
+        self.expansion = match device {
+            ExpansionDevice::None => None,
+            ExpansionDevice::HoriAdapter => Some(Box::new(HoriAdapter::default())),
+            ExpansionDevice::ArkanoidPaddle => Some(Box::new(ArkanoidPaddle::new(true))),
+            ExpansionDevice::FamilyBasicKeyboard => Some(Box::new(FamilyBasicKeyboard::default())),
+            ExpansionDevice::DataRecorder => Some(Box::new(DataRecorder::default())),
+        };
+    }
+
+    /// set up the devices an nes 2.0 header asks for, unknown or unspecified
+    /// ones leave the standard pads in place
+    pub fn plug_default(&mut self, expansion_device: u8) {
+        match expansion_device {
+            0x02 => {
+                self.plug(0, Device::FourScore);
+                self.plug(1, Device::FourScore);
+            }
+            0x03 => self.plug_expansion(ExpansionDevice::HoriAdapter),
+            0x08 => self.plug(1, Device::Zapper),
+            0x09 => {
+                self.plug(0, Device::Zapper);
+                self.plug(1, Device::Zapper);
+            }
+            0x0b | 0x0c => self.plug(1, Device::PowerPad),
+            0x0f => self.plug(1, Device::ArkanoidPaddle),
+            0x10 | 0x11 => self.plug_expansion(ExpansionDevice::ArkanoidPaddle),
+            0x20 => self.plug_expansion(ExpansionDevice::DataRecorder),
+            0x23 => self.plug_expansion(ExpansionDevice::FamilyBasicKeyboard),
+            _ => {}
+        }
+    }
+
+    /// what is in `port`
+    pub fn plugged(&self, port: usize) -> Device {
+        self.plugged[port]
+    }
+
+    pub fn device(&mut self, port: usize) -> Option<&mut (dyn InputDevice + 'static)> {
+        self.ports[port].as_deref_mut()
+    }
+
+    pub fn expansion(&mut self) -> Option<&mut (dyn InputDevice + 'static)> {
+        self.expansion.as_deref_mut()
+    }
+
+    /// buttons for players 1-4 (0-3), players 3 and 4 go to the second pad of
+    /// a four score or to a famicom multitap
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player] = buttons;
+        let port = player & 1;
+        let slot = player >> 1;
+        if let Some(device) = self.ports[port].as_mut() {
+            device.set_buttons(slot, buttons);
+        }
+        if slot > 0 {
+            if let Some(device) = self.expansion.as_mut() {
+                device.set_buttons(port, buttons);
+            }
+        }
+    }
+
+    pub fn buttons(&self, player: usize) -> Buttons {
+        self.buttons[player]
+    }
+
+    /// key presses go to a keyboard on the expansion port
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        if let Some(device) = self.expansion.as_mut() {
+            device.set_key(key, pressed);
+        }
+    }
+
+    /// the tape deck on the expansion port, on its own or behind a keyboard
+    pub fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        self.expansion.as_mut()?.data_recorder()
+    }
+
+    /// one cpu cycle
+    pub fn clock(&mut self) {
+        for device in self.ports.iter_mut().flatten() {
+            device.clock();
+        }
+        if let Some(device) = self.expansion.as_mut() {
+            device.clock();
+        }
+    }
+
+    /// $4016 write, the strobe and OUT lines reach every device
+    pub fn write(&mut self, val: u8) {
+        for device in self.ports.iter_mut().flatten() {
+            device.write(val);
+        }
+        if let Some(device) = self.expansion.as_mut() {
+            device.write(val);
+        }
+    }
+
+    /// $4016 (port 0) or $4017 (port 1) read
+    pub fn read(&mut self, port: usize) -> u8 {
+        let mut data = match self.ports[port].as_mut() {
+            Some(device) => device.read(port) & 0x1f,
+            None => 0,
+        };
+        // the expansion port has no D0 of its own
+        if let Some(device) = self.expansion.as_mut() {
+            data |= device.read(port) & 0x1e;
+        }
+        data | OPEN_BUS
+    }
+}
+
+#[test]
+fn test_ports() {
+    let mut ports = ControllerPorts::default();
+    ports.set_buttons(1, Buttons::A | Buttons::RIGHT);
+    ports.write(1);
+    ports.write(0);
+    let bits = (0..8).map(|_| ports.read(1)).collect::<Vec<u8>>();
+    assert_eq!(bits, [0x41, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x41]);
+    // nothing was latched on port 0
+    assert_eq!(ports.read(0), 0x40);
+
+    ports.plug(0, Device::None);
+    assert_eq!(ports.read(0), OPEN_BUS);
+}
+
+#[test]
+fn test_default_devices() {
+    let mut ports = ControllerPorts::default();
+    ports.plug_default(0x02);
+    // player 3 is the second pad on $4016
+    ports.set_buttons(2, Buttons::A);
+    ports.write(1);
+    ports.write(0);
+    let bits = (0..24).map(|_| ports.read(0) & 0x1).collect::<Vec<u8>>();
+    assert_eq!(bits[8], 1);
+    assert_eq!(bits[20], 1);
+
+    let mut ports = ControllerPorts::default();
+    ports.plug_default(0x08);
+    // a zapper aimed at a dark screen, no light and no trigger
+    assert_eq!(ports.read(1), OPEN_BUS | 0x08);
+}
+
+#[test]
+fn test_family_basic() {
+    let mut ports = ControllerPorts::default();
+    ports.plug_default(0x23);
+    ports.set_key(Key::Return, true);
+    // enable and go to row 0, return is on D3 of the first column
+    ports.write(0x05);
+    assert_eq!(ports.read(1), OPEN_BUS | 0x16);
+    assert!(ports.data_recorder().is_some());
+
+    ports.plug_expansion(ExpansionDevice::None);
+    assert!(ports.data_recorder().is_none());
+}
@@ -0,0 +1,403 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitflags::bitflags;
+
+use crate::error::EmuError;
+use crate::input::{Buttons, Device};
+
+bitflags! {
+    /// the command field at the start of every frame line
+    #[derive(Default)]
+    pub struct Commands: u8 {
+        const SOFT_RESET = 1 << 0;
+        const HARD_RESET = 1 << 1;
+        const FDS_INSERT = 1 << 2;
+        const FDS_SELECT = 1 << 3;
+        const VS_INSERT_COIN = 1 << 4;
+    }
+}
+
+/// fm2 gamepad fields, left to right, with the button each column holds
+const PAD_COLUMNS: [(char, Buttons); 8] = [
+    ('R', Buttons::RIGHT),
+    ('L', Buttons::LEFT),
+    ('D', Buttons::DOWN),
+    ('U', Buttons::UP),
+    ('T', Buttons::START),
+    ('S', Buttons::SELECT),
+    ('B', Buttons::B),
+    ('A', Buttons::A),
+];
+/// fceux writes the version of the format it speaks, this is the one we match
+const EMU_VERSION: u32 = 22020;
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// what was pressed on one frame and any commands issued before it ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MovieFrame {
+    pub commands: Commands,
+    /// players 1-4, 3 and 4 only with a four score
+    pub buttons: [Buttons; 4],
+}
+
+/// an fceux fm2 input movie. only text movies with pads or a four score are
+/// supported. a movie that doesn't start from power-on carries one of our own
+/// savestates, fceux's can't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub emu_version: u32,
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub rom_filename: String,
+    /// md5 of the rom the movie was made on
+    pub rom_checksum: [u8; 16],
+    pub guid: String,
+    pub four_score: bool,
+    /// `Device::Joypad` or `Device::None`, ignored with a four score
+    pub ports: [Device; 2],
+    pub comments: Vec<String>,
+    /// where the movie starts, `None` for power-on
+    pub savestate: Option<Vec<u8>>,
+    /// header lines we don't use, written back as they came
+    pub extra: Vec<(String, String)>,
+    pub frames: Vec<MovieFrame>,
+}
+
+fn error(reason: String, line: u32) -> EmuError {
+    EmuError::new(reason, file!().to_string(), line)
+}
+
+impl Movie {
+    pub fn new(rom_filename: &str, rom_checksum: [u8; 16], pal: bool) -> Self {
+        Self {
+            emu_version: EMU_VERSION,
+            rerecord_count: 0,
+            pal,
+            rom_filename: rom_filename.to_string(),
+            rom_checksum,
+            guid: new_guid(),
+            four_score: false,
+            ports: [Device::Joypad; 2],
+            comments: vec![],
+            savestate: None,
+            extra: vec![],
+            frames: vec![],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EmuError> {
+        let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string(), line!()))?;
+        Self::parse(&text)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EmuError> {
+        std::fs::write(path, self.to_fm2()).map_err(|e| error(e.to_string(), line!()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, EmuError> {
+        let mut movie = Self::new("", [0; 16], false);
+        movie.guid.clear();
+        let mut has_version = false;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                let frame = movie
+                    .parse_frame(line)
+                    .ok_or_else(|| error(format!("bad input on line {}", n + 1), line!()))?;
+                movie.frames.push(frame);
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| error(format!("bad {} on line {}", key, n + 1), line!()))
+            };
+            match key {
+                "version" => {
+                    if number()? != 3 {
+                        return Err(error("only fm2 version 3 is supported".into(), line!()));
+                    }
+                    has_version = true;
+                }
+                "emuVersion" => movie.emu_version = number()?,
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag" => movie.pal = number()? != 0,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    movie.rom_checksum = value
+                        .strip_prefix("base64:")
+                        .and_then(base64_decode)
+                        .and_then(|digest| digest.try_into().ok())
+                        .ok_or_else(|| error("bad romChecksum".into(), line!()))?;
+                }
+                "guid" => movie.guid = value.to_string(),
+                "fourscore" => movie.four_score = number()? != 0,
+                "port0" | "port1" => {
+                    let port = (key == "port1") as usize;
+                    movie.ports[port] = match number()? {
+                        0 => Device::None,
+                        1 => Device::Joypad,
+                        _ => {
+                            return Err(error(
+                                "only gamepads are supported in movies".into(),
+                                line!(),
+                            ))
+                        }
+                    };
+                }
+                "port2" if number()? != 0 => {
+                    return Err(error(
+                        "famicom expansion port devices are not supported in movies".into(),
+                        line!(),
+                    ));
+                }
+                "binary" if number()? != 0 => {
+                    return Err(error("binary fm2 input is not supported".into(), line!()));
+                }
+                "savestate" => {
+                    let state = value
+                        .strip_prefix("base64:")
+                        .and_then(base64_decode)
+                        .ok_or_else(|| error("bad savestate".into(), line!()))?;
+                    movie.savestate = Some(state);
+                }
+                "comment" => movie.comments.push(value.to_string()),
+                // recomputed from the frames on save
+                "port2" | "binary" | "length" => {}
+                _ => movie.extra.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        if !has_version {
+            return Err(error("not an fm2 movie".into(), line!()));
+        }
+        Ok(movie)
+    }
+
+    /// `|commands|port0|port1|port2|`, a four score takes four pad fields
+    fn parse_frame(&self, line: &str) -> Option<MovieFrame> {
+        let fields = line.split('|').collect::<Vec<&str>>();
+        let mut frame = MovieFrame {
+            commands: Commands::from_bits_truncate(fields.get(1)?.trim().parse().ok()?),
+            ..MovieFrame::default()
+        };
+        if self.four_score {
+            for player in 0..4 {
+                frame.buttons[player] = parse_pad(fields.get(2 + player)?)?;
+            }
+        } else {
+            for (port, device) in self.ports.iter().enumerate() {
+                if *device == Device::Joypad {
+                    frame.buttons[port] = parse_pad(fields.get(2 + port)?)?;
+                }
+            }
+        }
+        Some(frame)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        let port = |device: Device| (device == Device::Joypad || self.four_score) as u8;
+        let _ = writeln!(text, "version 3");
+        let _ = writeln!(text, "emuVersion {}", self.emu_version);
+        let _ = writeln!(text, "rerecordCount {}", self.rerecord_count);
+        let _ = writeln!(text, "palFlag {}", self.pal as u8);
+        let _ = writeln!(text, "romFilename {}", self.rom_filename);
+        let _ = writeln!(
+            text,
+            "romChecksum base64:{}",
+            base64_encode(&self.rom_checksum)
+        );
+        let _ = writeln!(text, "guid {}", self.guid);
+        let _ = writeln!(text, "fourscore {}", self.four_score as u8);
+        let _ = writeln!(text, "port0 {}", port(self.ports[0]));
+        let _ = writeln!(text, "port1 {}", port(self.ports[1]));
+        let _ = writeln!(text, "port2 0");
+        let _ = writeln!(text, "length {}", self.frames.len());
+        if let Some(state) = self.savestate.as_ref() {
+            let _ = writeln!(text, "savestate base64:{}", base64_encode(state));
+        }
+        for (key, value) in self.extra.iter() {
+            let _ = writeln!(text, "{} {}", key, value);
+        }
+        for comment in self.comments.iter() {
+            let _ = writeln!(text, "comment {}", comment);
+        }
+
+        for frame in self.frames.iter() {
+            let _ = write!(text, "|{}|", frame.commands.bits());
+            if self.four_score {
+                for buttons in frame.buttons {
+                    let _ = write!(text, "{}|", format_pad(buttons));
+                }
+            } else {
+                for (port, device) in self.ports.iter().enumerate() {
+                    if *device == Device::Joypad {
+                        text.push_str(&format_pad(frame.buttons[port]));
+                    }
+                    text.push('|');
+                }
+            }
+            // the empty famicom expansion port
+            text.push_str("|\n");
+        }
+        text
+    }
+}
+
+/// any character but a space or a dot is a held button
+fn parse_pad(field: &str) -> Option<Buttons> {
+    if field.chars().count() != PAD_COLUMNS.len() {
+        return None;
+    }
+    Some(
+        field
+            .chars()
+            .zip(PAD_COLUMNS.iter())
+            .filter(|(c, _)| *c != '.' && *c != ' ')
+            .fold(Buttons::empty(), |held, (_, (_, button))| held | *button),
+    )
+}
+
+fn format_pad(buttons: Buttons) -> String {
+    PAD_COLUMNS
+        .iter()
+        .map(|(c, button)| if buttons.contains(*button) { *c } else { '.' })
+        .collect()
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for n in 0..4 {
+            if n <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * n)) as usize & 0x3f] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = vec![];
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.trim().bytes().take_while(|&c| c != b'=') {
+        let val = BASE64.iter().position(|&b| b == c)? as u32;
+        bits = bits << 6 | val;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
+
+/// unique enough to tell movies apart, it only has to differ between recordings
+fn new_guid() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+    let mut state = nanos as u64 ^ (nanos >> 64) as u64 ^ 0x9e37_79b9_7f4a_7c15;
+    let mut bytes = [0u8; 16];
+    for byte in bytes.iter_mut() {
+        // xorshift
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *byte = state as u8;
+    }
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(base64_encode(b"nesrs"), "bmVzcnM=");
+    assert_eq!(base64_encode(b"fm"), "Zm0=");
+    assert_eq!(base64_decode("bmVzcnM=").unwrap(), b"nesrs");
+    let digest = [0xab; 16];
+    assert_eq!(base64_decode(&base64_encode(&digest)).unwrap(), digest);
+}
+
+#[test]
+fn test_parse_fm2() {
+    let text = "version 3\r\n\
+        emuVersion 20600\r\n\
+        rerecordCount 12\r\n\
+        palFlag 0\r\n\
+        romFilename Some Game\r\n\
+        romChecksum base64:q6urq6urq6urq6urq6urqw==\r\n\
+        guid 01234567-89AB-CDEF-0123-456789ABCDEF\r\n\
+        fourscore 0\r\n\
+        microphone 0\r\n\
+        port0 1\r\n\
+        port1 0\r\n\
+        port2 0\r\n\
+        comment author someone\r\n\
+        |0|........|||\r\n\
+        |1|R......A|||\r\n\
+        |0|...UT...|||\r\n";
+    let movie = Movie::parse(text).unwrap();
+    assert_eq!(movie.rerecord_count, 12);
+    assert_eq!(movie.rom_filename, "Some Game");
+    assert_eq!(movie.rom_checksum, [0xab; 16]);
+    assert_eq!(movie.ports, [Device::Joypad, Device::None]);
+    assert_eq!(movie.comments, ["author someone"]);
+    assert_eq!(movie.frames.len(), 3);
+    assert_eq!(movie.frames[1].commands, Commands::SOFT_RESET);
+    assert_eq!(movie.frames[1].buttons[0], Buttons::RIGHT | Buttons::A);
+    assert_eq!(movie.frames[2].buttons[0], Buttons::UP | Buttons::START);
+
+    // writing it back and parsing again loses nothing
+    assert_eq!(Movie::parse(&movie.to_fm2()).unwrap(), movie);
+    assert!(movie.to_fm2().contains("\n|1|R......A|||\n"));
+
+    let savestate = text.replace("comment", "savestate base64:AAECAw==\ncomment");
+    let movie = Movie::parse(&savestate).unwrap();
+    assert_eq!(movie.savestate, Some(vec![0, 1, 2, 3]));
+    assert_eq!(Movie::parse(&movie.to_fm2()).unwrap(), movie);
+    let hex = text.replace("comment", "savestate 0x00010203\ncomment");
+    assert!(Movie::parse(&hex).is_err());
+}
+
+#[test]
+fn test_four_score_frames() {
+    let mut movie = Movie::new("game.nes", [0; 16], false);
+    movie.four_score = true;
+    movie.frames.push(MovieFrame {
+        commands: Commands::empty(),
+        buttons: [Buttons::A, Buttons::B, Buttons::SELECT, Buttons::LEFT],
+    });
+    let text = movie.to_fm2();
+    assert!(text.contains("|0|.......A|......B.|.....S..|.L......||"));
+    assert_eq!(Movie::parse(&text).unwrap(), movie);
+}
@@ -0,0 +1,143 @@
+use crate::input::{Buttons, InputDevice, Joypad};
+
+/// the ids the adapters shift out after the two pads, per port
+const FOUR_SCORE_SIGNATURE: [u8; 2] = [0x10, 0x20];
+const HORI_SIGNATURE: [u8; 2] = [0x20, 0x10];
+
+/// one port's half of the nes four score: players 1 and 3 on $4016, 2 and 4
+/// on $4017, each read out as 24 bits of pad, pad, signature on D0
+pub struct FourScore {
+    pads: [Joypad; 2],
+    signature: u8,
+    shift: u32,
+    strobe: bool,
+}
+
+impl FourScore {
+    pub fn new(port: usize) -> Self {
+        Self {
+            pads: [Joypad::default(), Joypad::default()],
+            signature: FOUR_SCORE_SIGNATURE[port],
+            shift: 0,
+            strobe: false,
+        }
+    }
+
+    fn latch(&mut self) -> u32 {
+        let first = self.pads[0].buttons().bits() as u32;
+        let second = self.pads[1].buttons().bits() as u32;
+        first | second << 8 | (self.signature as u32) << 16
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, val: u8) {
+        let strobe = val & 0x1 != 0;
+        if self.strobe && !strobe {
+            self.shift = self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        if self.strobe {
+            return (self.latch() & 0x1) as u8;
+        }
+        let bit = (self.shift & 0x1) as u8;
+        self.shift = (self.shift >> 1) | 0x80_0000;
+        bit
+    }
+
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if let Some(pad) = self.pads.get_mut(slot) {
+            pad.set_buttons(0, buttons);
+        }
+    }
+}
+
+/// hori's famicom four player adapter on the expansion port: players 3 and 4
+/// come out of D1 of $4016 and $4017, followed by the adapter's signature
+#[derive(Default)]
+pub struct HoriAdapter {
+    pads: [Joypad; 2],
+    shift: [u32; 2],
+    strobe: bool,
+}
+
+impl HoriAdapter {
+    fn latch(&self, port: usize) -> u32 {
+        self.pads[port].buttons().bits() as u32 | (HORI_SIGNATURE[port] as u32) << 16
+    }
+}
+
+impl InputDevice for HoriAdapter {
+    fn write(&mut self, val: u8) {
+        let strobe = val & 0x1 != 0;
+        if self.strobe && !strobe {
+            self.shift = [self.latch(0), self.latch(1)];
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        if self.strobe {
+            return ((self.latch(port) & 0x1) << 1) as u8;
+        }
+        let bit = (self.shift[port] & 0x1) as u8;
+        self.shift[port] = (self.shift[port] >> 1) | 0x80_0000;
+        bit << 1
+    }
+
+    /// `slot` 0 is player 3, 1 is player 4
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if let Some(pad) = self.pads.get_mut(slot) {
+            pad.set_buttons(0, buttons);
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_bits(device: &mut dyn InputDevice, port: usize, bit: u8, count: usize) -> u32 {
+    (0..count).fold(0, |val, n| {
+        val | (((device.read(port) >> bit) & 0x1) as u32) << n
+    })
+}
+
+#[test]
+fn test_four_score_report() {
+    let mut tap = FourScore::new(1);
+    tap.set_buttons(0, Buttons::A);
+    tap.set_buttons(1, Buttons::START);
+    tap.write(1);
+    tap.write(0);
+    assert_eq!(read_bits(&mut tap, 1, 0, 24), 0x20_08_01);
+    assert_eq!(read_bits(&mut tap, 1, 0, 4), 0xf);
+}
+
+#[test]
+fn test_hori_report() {
+    let mut tap = HoriAdapter::default();
+    tap.set_buttons(1, Buttons::B);
+    tap.write(1);
+    tap.write(0);
+    assert_eq!(read_bits(&mut tap, 0, 1, 24), 0x20_00_00);
+    assert_eq!(read_bits(&mut tap, 1, 1, 24), 0x10_00_02);
+}
+
+#[test]
+fn test_latch_on_strobe_release() {
+    // buttons pressed while the strobe is high still make it into the report
+    let mut tap = FourScore::new(0);
+    tap.write(1);
+    tap.set_buttons(0, Buttons::A);
+    tap.write(0);
+    tap.set_buttons(0, Buttons::B);
+    assert_eq!(read_bits(&mut tap, 0, 0, 24), 0x10_00_01);
+
+    let mut tap = HoriAdapter::default();
+    tap.write(1);
+    tap.set_buttons(0, Buttons::A);
+    tap.write(0);
+    tap.set_buttons(0, Buttons::B);
+    assert_eq!(read_bits(&mut tap, 0, 1, 24), 0x20_00_01);
+}
@@ -0,0 +1,81 @@
+use crate::input::InputDevice;
+
+/// the arkanoid vaus controller: a knob read out as an inverted 8-bit value,
+/// msb first, and a fire button. the nes version sits on a controller port
+/// (D3 knob, D4 button), the famicom one on the expansion port ($4017 D1 knob,
+/// $4016 D1 button)
+pub struct ArkanoidPaddle {
+    famicom: bool,
+    position: u8,
+    fire: bool,
+    shift: u8,
+    strobe: bool,
+}
+
+impl ArkanoidPaddle {
+    pub fn new(famicom: bool) -> Self {
+        Self {
+            famicom,
+            // real knobs span roughly $62-$F2, start in the middle
+            position: 0xaa,
+            fire: false,
+            shift: 0,
+            strobe: false,
+        }
+    }
+
+    fn shift_out(&mut self) -> u8 {
+        if self.strobe {
+            return (!self.position >> 7) & 0x1;
+        }
+        let bit = (self.shift >> 7) & 0x1;
+        self.shift <<= 1;
+        bit
+    }
+}
+
+impl InputDevice for ArkanoidPaddle {
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x1 != 0;
+        if self.strobe {
+            self.shift = !self.position;
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let fire = self.fire as u8;
+        match (self.famicom, port) {
+            (false, _) => self.shift_out() << 3 | fire << 4,
+            (true, 0) => fire << 1,
+            (true, _) => self.shift_out() << 1,
+        }
+    }
+
+    /// the knob, only `x` is used
+    fn set_position(&mut self, x: i32, y: i32) {
+        self.position = x.clamp(0, 0xff) as u8;
+    }
+
+    fn set_trigger(&mut self, pulled: bool) {
+        self.fire = pulled;
+    }
+}
+
+#[test]
+fn test_knob_readout() {
+    let mut paddle = ArkanoidPaddle::new(false);
+    paddle.set_position(0x62, 0);
+    paddle.set_trigger(true);
+    paddle.write(1);
+    paddle.write(0);
+    let bits = (0..8)
+        .map(|_| paddle.read(1))
+        .fold(0u8, |val, bits| val << 1 | (bits >> 3) & 0x1);
+    assert_eq!(bits, !0x62);
+    assert_eq!(paddle.read(1) & 0x10, 0x10);
+
+    let mut paddle = ArkanoidPaddle::new(true);
+    paddle.set_trigger(true);
+    assert_eq!(paddle.read(0), 0x02);
+    assert_eq!(paddle.read(1) & 0x10, 0);
+}
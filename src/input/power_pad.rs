@@ -0,0 +1,78 @@
+use crate::input::InputDevice;
+
+/// pad numbers (1-12) in the order they come out of D3
+const D3_ORDER: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+/// and D4, which has only four before it reads back 1s
+const D4_ORDER: [u8; 4] = [4, 3, 12, 8];
+
+/// the power pad / family trainer mat: twelve pads shifted out in parallel
+/// on D3 and D4. side B is the same mat with the pads numbered differently,
+/// so both use side A numbering here
+#[derive(Default)]
+pub struct PowerPad {
+    pressed: u16,
+    shift_d3: u8,
+    shift_d4: u8,
+    strobe: bool,
+}
+
+impl PowerPad {
+    fn latch(&mut self) {
+        let bit = |pad: &u8| (self.pressed >> (pad - 1)) & 0x1 != 0;
+        self.shift_d3 = 0;
+        for (n, pad) in D3_ORDER.iter().enumerate() {
+            self.shift_d3 |= (bit(pad) as u8) << n;
+        }
+        // the d4 register is filled up with 1s behind the four pads
+        self.shift_d4 = 0xf0;
+        for (n, pad) in D4_ORDER.iter().enumerate() {
+            self.shift_d4 |= (bit(pad) as u8) << n;
+        }
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 0x1 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let val = (self.shift_d3 & 0x1) << 3 | (self.shift_d4 & 0x1) << 4;
+        if !self.strobe {
+            self.shift_d3 = (self.shift_d3 >> 1) | 0x80;
+            self.shift_d4 = (self.shift_d4 >> 1) | 0x80;
+        }
+        val
+    }
+
+    /// bit n is pad n + 1
+    fn set_pads(&mut self, pressed: u16) {
+        self.pressed = pressed & 0xfff;
+    }
+}
+
+#[test]
+fn test_pad_order() {
+    let mut pad = PowerPad::default();
+    // pads 1, 4 and 12
+    pad.set_pads(1 << 0 | 1 << 3 | 1 << 11);
+    pad.write(1);
+    pad.write(0);
+    let reads = (0..9).map(|_| pad.read(1)).collect::<Vec<u8>>();
+    let d3 = reads
+        .iter()
+        .map(|val| (val >> 3) & 0x1)
+        .collect::<Vec<u8>>();
+    let d4 = reads
+        .iter()
+        .map(|val| (val >> 4) & 0x1)
+        .collect::<Vec<u8>>();
+    assert_eq!(d3, [0, 1, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(d4, [1, 0, 1, 0, 1, 1, 1, 1, 1]);
+}
@@ -0,0 +1,84 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::input::InputDevice;
+use crate::ppu::frame::{FrameBuffer, HEIGHT, WIDTH};
+
+/// how far around the aim point the photodiode sees, in pixels
+const SENSE_RADIUS: i32 = 2;
+/// brightness that counts as light, the diode only reacts to near white
+const LIGHT_THRESHOLD: f32 = 0.75;
+
+/// the zapper light gun: D3 is low while the aimed spot of the frame buffer
+/// is bright, D4 is high while the trigger is held. nothing renders into the
+/// frame buffer until there is a ppu, so in the emulator the gun never sees
+/// light and only the trigger works; the light check is there for when a
+/// frame source gets connected
+pub struct Zapper {
+    frame: Rc<RefCell<FrameBuffer>>,
+    x: i32,
+    y: i32,
+    trigger: bool,
+}
+
+impl Zapper {
+    pub fn new(frame: Rc<RefCell<FrameBuffer>>) -> Self {
+        Self {
+            frame,
+            x: -1,
+            y: -1,
+            trigger: false,
+        }
+    }
+
+    fn sees_light(&self) -> bool {
+        let frame = self.frame.borrow();
+        for y in self.y - SENSE_RADIUS..=self.y + SENSE_RADIUS {
+            for x in self.x - SENSE_RADIUS..=self.x + SENSE_RADIUS {
+                if x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 {
+                    continue;
+                }
+                if frame.luminance(x as usize, y as usize) >= LIGHT_THRESHOLD {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, val: u8) {}
+
+    fn read(&mut self, port: usize) -> u8 {
+        let light = if self.sees_light() { 0 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0 };
+        light | trigger
+    }
+
+    /// aim at a pixel, anything off screen never sees light
+    fn set_position(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+}
+
+#[test]
+fn test_light_and_trigger() {
+    let frame = Rc::new(RefCell::new(FrameBuffer::default()));
+    let mut zapper = Zapper::new(frame.clone());
+    assert_eq!(zapper.read(1), 0x08);
+
+    frame.borrow_mut().set_pixel(100, 50, 0xffffff);
+    zapper.set_position(101, 51);
+    assert_eq!(zapper.read(1), 0x00);
+    zapper.set_position(110, 51);
+    assert_eq!(zapper.read(1), 0x08);
+
+    zapper.set_trigger(true);
+    assert_eq!(zapper.read(1), 0x18);
+}
@@ -1,25 +1,139 @@
-#![feature(associated_type_defaults)]
-#![feature(exclusive_range_pattern)]
 #![allow(unused)]
+#![allow(non_snake_case, non_upper_case_globals)]
+#![allow(clippy::upper_case_acronyms, clippy::module_inception)]
 
 use crate::bus::CPUBus;
 use crate::cpu::CPU;
+use crate::emulator::Emulator;
+use crate::error::{handle_result, EmuError};
+use crate::input::Movie;
+use crate::player::NsfPlayer;
 use crate::ram::CPURam;
 use crate::rom::Cartridge;
 use std::cell::RefCell;
 use std::env::current_dir;
 use std::rc::Rc;
 
+mod apu;
 mod bus;
 mod cpu;
 mod emulator;
 mod error;
+mod input;
+mod player;
 //mod log;
 mod ram;
+mod region;
 mod rom;
+mod state;
 mod trace;
 mod ppu;
 
+const USAGE: &str = "usage:
+    nesrs                                          run the nestest cpu trace
+    nesrs record <rom> <out.wav> [frames] [--stems] [--rate <hz>] [--movie <file.fm2>]
+    nesrs nsf <file.nsf|file.nsfe> <out.wav> [track] [--seconds <s>] [--rate <hz>]";
+
 fn main() {
-    trace::trace("./test/nestest.log");
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    match args.first().map(|arg| arg.as_str()) {
+        None => trace::trace("./test/nestest.log"),
+        Some("record") => record(&args[1..]),
+        Some("nsf") => nsf(&args[1..]),
+        Some(_) => println!("{}", USAGE),
+    }
+}
+
+/// render a rom headlessly and write its audio to wav
+fn record(args: &[String]) {
+    let mut positional = vec![];
+    let mut stems = false;
+    let mut movie = None;
+    let mut rate = apu::DEFAULT_SAMPLE_RATE;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--stems" => stems = true,
+            "--movie" => movie = Some(parse_arg::<String>(iter.next(), "--movie")),
+            "--rate" => rate = parse_arg(iter.next(), "--rate"),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() < 2 {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut emulator = Emulator::new(positional[0]);
+    // a movie plays to its end unless told otherwise
+    let mut length = 600;
+    if let Some(path) = movie {
+        let movie = handle_result(Movie::load(path));
+        length = movie.frames.len();
+        handle_result(emulator.play_movie(movie));
+    }
+    let frames = positional
+        .get(2)
+        .map(|arg| parse_arg(Some(arg), "frames"))
+        .unwrap_or(length);
+
+    emulator.set_sample_rate(rate);
+    handle_result(emulator.start_recording(positional[1], stems));
+    for _ in 0..frames {
+        handle_result(emulator.run_frame());
+    }
+    let hash = emulator.recording_hash().unwrap_or_default();
+    handle_result(emulator.stop_recording());
+    println!(
+        "recorded {} frames to {}, hash {:016x}",
+        frames, positional[1], hash
+    );
+}
+
+/// render one track of an nsf/nsfe to wav, tracks count from 1 like players show them
+fn nsf(args: &[String]) {
+    let mut positional = vec![];
+    let mut seconds = None;
+    let mut rate = apu::DEFAULT_SAMPLE_RATE;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seconds" => seconds = Some(parse_arg(iter.next(), "--seconds")),
+            "--rate" => rate = parse_arg(iter.next(), "--rate"),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() < 2 {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let mut player = handle_result(NsfPlayer::load(positional[0]));
+    player.set_sample_rate(rate);
+    let track = positional
+        .get(2)
+        .map(|arg| parse_arg::<u8>(Some(arg), "track").saturating_sub(1))
+        .unwrap_or(player.nsf().start_track);
+    let seconds = seconds.unwrap_or_else(|| player.track_seconds(track));
+
+    let nsf = player.nsf();
+    println!("{} - {} ({})", nsf.title, nsf.artist, nsf.copyright);
+    match nsf.track_label(track) {
+        Some(label) => println!("track {}/{}: {}", track + 1, nsf.tracks, label),
+        None => println!("track {}/{}", track + 1, nsf.tracks),
+    }
+    let hash = handle_result(player.render_track(track, positional[1], seconds));
+    println!(
+        "rendered {:.1}s to {}, hash {:016x}",
+        seconds, positional[1], hash
+    );
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<&String>, name: &str) -> T {
+    handle_result(arg.and_then(|arg| arg.parse().ok()).ok_or_else(|| {
+        EmuError::new(
+            format!("invalid value for {}", name),
+            file!().to_string(),
+            line!(),
+        )
+    }))
 }
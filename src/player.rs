@@ -0,0 +1,191 @@
+use std::path::Path;
+
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::emulator::Emulator;
+use crate::error::EmuError;
+use crate::region::Region;
+use crate::rom::{Cartridge, Nsf};
+
+/// how long INIT may run before play starts regardless, some never return
+const INIT_FRAMES: f64 = 60.0;
+/// used when neither the caller nor the file gives a track length
+pub const DEFAULT_TRACK_LENGTH: f64 = 150.0;
+
+/// plays nsf/nsfe files on the emulator's cpu and apu: INIT once per track,
+/// then PLAY at the rate the file asks for
+pub struct NsfPlayer {
+    nsf: Nsf,
+    emulator: Emulator,
+    sample_rate: u32,
+    /// cpu cycles between PLAY calls
+    play_period: f64,
+    play_cycles: f64,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Self {
+        let region = nsf.region;
+        let emulator = Emulator::with_cartridge(Cartridge::from_nsf(&nsf), region);
+        let speed = nsf.play_speed(region) as f64;
+        let play_period = speed * region.cpu_clock_rate() / 1_000_000.0;
+        Self {
+            nsf,
+            emulator,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            play_period,
+            play_cycles: 0.0,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EmuError> {
+        Ok(Self::new(Nsf::load(path)?))
+    }
+
+    /// title, tracks and the rest of the file's metadata
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    pub fn emulator(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+
+    /// applies from the next `start_track`
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// reset the player hardware and run INIT for a 0-based track
+    pub fn start_track(&mut self, track: u8) -> Result<(), EmuError> {
+        if track >= self.nsf.tracks.max(1) {
+            return Err(EmuError::new(
+                format!(
+                    "track {} out of range, the file has {}",
+                    track + 1,
+                    self.nsf.tracks
+                ),
+                file!().to_string(),
+                line!(),
+            ));
+        }
+        let region = self.nsf.region;
+        self.emulator = Emulator::with_cartridge(Cartridge::from_nsf(&self.nsf), region);
+        self.emulator.set_sample_rate(self.sample_rate);
+        self.play_cycles = 0.0;
+
+        for address in 0x4000..=0x4013 {
+            self.emulator.write(address, 0);
+        }
+        self.emulator.write(0x4015, 0x0f);
+        self.emulator.write(0x4017, 0x40);
+
+        let pal = (region == Region::Pal) as u8;
+        self.emulator.call(self.nsf.init_address, track, pal);
+        let mut budget = region.cpu_cycles_per_frame() * INIT_FRAMES;
+        while !self.emulator.returned() && budget > 0.0 {
+            budget -= self.emulator.step() as f64;
+        }
+        self.emulator.end_frame();
+        Ok(())
+    }
+
+    /// run one PLAY period and publish its audio. a PLAY that overruns its
+    /// period simply continues into the next one
+    pub fn play(&mut self) {
+        self.play_cycles += self.play_period;
+        if self.emulator.returned() {
+            self.emulator.call(self.nsf.play_address, 0, 0);
+        }
+        while self.play_cycles > 0.0 {
+            let cycles = if self.emulator.returned() {
+                self.emulator.idle(self.play_cycles.ceil() as usize)
+            } else {
+                self.emulator.step()
+            };
+            self.play_cycles -= cycles as f64;
+        }
+        self.emulator.end_frame();
+    }
+
+    /// play `seconds` of a track into a wav file and return the hash of its samples
+    pub fn render_track<P: AsRef<Path>>(
+        &mut self,
+        track: u8,
+        path: P,
+        seconds: f64,
+    ) -> Result<u64, EmuError> {
+        self.start_track(track)?;
+        self.emulator.start_recording(path, false)?;
+        let calls = (seconds * 1_000_000.0 / self.nsf.play_speed(self.nsf.region) as f64) as usize;
+        for _ in 0..calls {
+            self.play();
+        }
+        let hash = self.emulator.recording_hash().unwrap_or_default();
+        self.emulator.stop_recording()?;
+        Ok(hash)
+    }
+
+    /// seconds to render for a track, from the file when it knows
+    pub fn track_seconds(&self, track: u8) -> f64 {
+        self.nsf
+            .track_length(track)
+            .map(|ms| ms as f64 / 1000.0)
+            .unwrap_or(DEFAULT_TRACK_LENGTH)
+    }
+}
+
+#[cfg(test)]
+fn test_nsf() -> Nsf {
+    use crate::rom::ExpansionChips;
+
+    // INIT: a 440 Hz square on pulse 1, PLAY: count calls in $00
+    #[rustfmt::skip]
+    let code = vec![
+        0xa9, 0xbf, 0x8d, 0x00, 0x40, // lda #$bf, sta $4000
+        0xa9, 0xfd, 0x8d, 0x02, 0x40, // lda #$fd, sta $4002
+        0xa9, 0x00, 0x8d, 0x03, 0x40, // lda #$00, sta $4003
+        0x60,                         // rts
+        0xe6, 0x00,                   // inc $00
+        0x60,                         // rts
+    ];
+    Nsf {
+        tracks: 2,
+        load_address: 0x8000,
+        init_address: 0x8000,
+        play_address: 0x8010,
+        ntsc_speed: 16639,
+        pal_speed: 19997,
+        chips: ExpansionChips::empty(),
+        data: code,
+        ..Nsf::default()
+    }
+}
+
+#[test]
+fn test_play_rate() {
+    let mut player = NsfPlayer::new(test_nsf());
+    player.start_track(1).unwrap();
+    for _ in 0..60 {
+        player.play();
+    }
+    let ram = player.emulator().read(0x0000);
+    assert_eq!(ram, 60);
+    assert!(player.start_track(2).is_err());
+}
+
+#[test]
+fn test_render_track() {
+    let path = std::env::temp_dir().join("nesrs_test_nsf.wav");
+    let mut player = NsfPlayer::new(test_nsf());
+    player.set_sample_rate(48000);
+    player.render_track(0, &path, 1.0).unwrap();
+    // the square wave made it into the file
+    let wav = std::fs::read(&path).unwrap();
+    let samples = wav[44..]
+        .chunks_exact(2)
+        .map(|val| i16::from_le_bytes([val[0], val[1]]))
+        .collect::<Vec<i16>>();
+    assert!(samples.len().abs_diff(48000) < 100);
+    assert!(samples.iter().any(|&val| val > 3000));
+    std::fs::remove_file(path).unwrap();
+}
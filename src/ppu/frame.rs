@@ -0,0 +1,39 @@
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+/// the picture the ppu is drawing, as 0xRRGGBB per pixel. the ppu isn't
+/// emulated yet, so the emulator's buffer stays black
+pub struct FrameBuffer {
+    pixels: Vec<u32>,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self {
+            pixels: vec![0; WIDTH * HEIGHT],
+        }
+    }
+}
+
+impl FrameBuffer {
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * WIDTH + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: u32) {
+        self.pixels[y * WIDTH + x] = rgb;
+    }
+
+    /// perceived brightness of a pixel in 0.0..=1.0
+    pub fn luminance(&self, x: usize, y: usize) -> f32 {
+        let rgb = self.pixel(x, y);
+        let r = ((rgb >> 16) & 0xff) as f32;
+        let g = ((rgb >> 8) & 0xff) as f32;
+        let b = (rgb & 0xff) as f32;
+        (0.299 * r + 0.587 * g + 0.114 * b) / 255.0
+    }
+}
@@ -1,4 +1,7 @@
+pub use frame::FrameBuffer;
+
 mod controller;
+pub mod frame;
 mod ppu;
 mod mask;
 mod status;
@@ -1,3 +1,8 @@
+use crate::error::EmuError;
+#[cfg(test)]
+use crate::rom::MirrorType;
+use crate::state::{StateReader, StateWriter};
+
 pub struct CPURam {
     ram: Vec<u8>,
     io_registers: Vec<u8>,
@@ -28,8 +33,20 @@ impl CPURam {
             _ => unimplemented!(),
         }
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bytes(&self.io_registers);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.ram)?;
+        state.read_bytes(&mut self.io_registers)
+    }
 }
 
+/// the console's 2k of nametable ram, 4k for four screen boards which bring
+/// the other half themselves, and the palettes
 pub struct VRam {
     name_tables: Vec<u8>,
     palettes: Vec<u8>,
@@ -38,26 +55,63 @@ pub struct VRam {
 impl Default for VRam {
     fn default() -> Self {
         Self {
-            name_tables: vec![0; 0x1f00],
-            palettes: vec![0; 0x0100],
+            name_tables: vec![0; 0x1000],
+            palettes: vec![0; 0x20],
         }
     }
 }
 
+/// $3F10/$3F14/$3F18/$3F1C are the same entries as $3F00/$3F04/$3F08/$3F0C
+fn palette_index(address: usize) -> usize {
+    let index = address & 0x1f;
+    if index & 0x13 == 0x10 {
+        index & 0x0f
+    } else {
+        index
+    }
+}
+
 impl VRam {
-    pub fn read(&self, address: usize) -> u8 {
-        match address {
-            0x2000..0x3f00 => self.name_tables[address],
-            0x3f00..0x4000 => self.palettes[address],
-            _ => unimplemented!(),
-        }
+    /// `offset` is where the cartridge wires the nametable address into 4k
+    pub fn read_nametable(&self, offset: usize) -> u8 {
+        self.name_tables[offset]
     }
-    
-    pub fn write(&mut self, address: usize, val: u8) {
-        match address {
-            0x2000..0x3f00 => self.name_tables[address] = val,
-            0x3f00..0x4000 => self.palettes[address] = val,
-            _ => unimplemented!(),
-        }
+
+    pub fn write_nametable(&mut self, offset: usize, val: u8) {
+        self.name_tables[offset] = val;
+    }
+
+    pub fn read_palette(&self, address: usize) -> u8 {
+        self.palettes[palette_index(address)]
+    }
+
+    pub fn write_palette(&mut self, address: usize, val: u8) {
+        self.palettes[palette_index(address)] = val;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.name_tables);
+        state.write_bytes(&self.palettes);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.name_tables)?;
+        state.read_bytes(&mut self.palettes)
+    }
+}
+
+#[test]
+fn test_vram_mirroring() {
+    let mut vram = VRam::default();
+    let offset = |address, mirroring: MirrorType| mirroring.nametable_offset(address);
+    vram.write_nametable(offset(0x2005, MirrorType::Horizontal), 1);
+    assert_eq!(vram.read_nametable(offset(0x2405, MirrorType::Horizontal)), 1);
+    assert_eq!(vram.read_nametable(offset(0x2805, MirrorType::Horizontal)), 0);
+    assert_eq!(vram.read_nametable(offset(0x2805, MirrorType::Vertical)), 1);
+    // $3000-$3EFF mirrors the nametables
+    assert_eq!(vram.read_nametable(offset(0x3005, MirrorType::SingleScreenLower)), 1);
+
+    vram.write_palette(0x3f10, 0x20);
+    assert_eq!(vram.read_palette(0x3f00), 0x20);
+    assert_eq!(vram.read_palette(0x3f30), 0x20);
 }
@@ -0,0 +1,24 @@
+/// console timing, selects the clock rate and the region specific period tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0 / 12.0,
+            Region::Pal => 26_601_712.5 / 16.0,
+        }
+    }
+
+    /// a frame is 341 x 262 (ntsc) or 341 x 312 (pal) ppu dots
+    pub fn cpu_cycles_per_frame(&self) -> f64 {
+        match self {
+            Region::Ntsc => 341.0 * 262.0 / 3.0 - 0.5 / 3.0,
+            Region::Pal => 341.0 * 312.0 / 3.2,
+        }
+    }
+}
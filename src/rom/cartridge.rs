@@ -1,19 +1,59 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fs::File;
 use std::os::unix::fs::FileExt;
-use std::path::Path;
-use std::process::exit;
+use std::path::{Path, PathBuf};
 
 use crate::error::{EmuError, handle_result};
-use crate::rom::mapper::NRom;
+use crate::rom::mapper::{
+    AxRom, BandaiFcg, BnRom, Camerica, CnRom, ColorDreams, Fme7, GtRom, GxRom, IrqRevision,
+    MagicFloor, Mmc1, Mmc2, Mmc3, Mmc3Board, Mmc5, NRom, Namco163, NsfMapper, Unrom512, UxRom,
+    Vrc4, Vrc6, Vrc7,
+};
+use crate::rom::md5::md5;
+use crate::rom::nsf::Nsf;
+use crate::state::{StateReader, StateWriter};
 
-use super::mapper::{Mapper, NROM};
+use super::mapper::{
+    Mapper, AXROM, BANDAI_24C01, BANDAI_FCG, BANDAI_SRAM, BNROM, CAMERICA, CNROM, COLOR_DREAMS,
+    DATACH, FME7, GTROM, GXROM, MAGIC_FLOOR, MMC1, MMC2, MMC3, MMC4, MMC5, NAMCO_108, NAMCO_163,
+    NAMCO_3425, NAMCO_3433, NAMCO_3446, NAMCO_3453, NROM, NSF, TQROM, TXSROM, UNROM512, UXROM,
+    VRC2A, VRC4AC, VRC4BD, VRC4EF, VRC6A, VRC6B, VRC7,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MirrorType {
     Horizontal,
     Vertical,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+impl MirrorType {
+    /// inverse of `as u8`, for savestates
+    pub fn from_u8(val: u8) -> Self {
+        match val {
+            0 => MirrorType::Horizontal,
+            1 => MirrorType::Vertical,
+            2 => MirrorType::FourScreen,
+            3 => MirrorType::SingleScreenLower,
+            _ => MirrorType::SingleScreenUpper,
+        }
+    }
+
+    /// where a $2000-$3EFF nametable address lands in 4k of vram, only four
+    /// screen boards use more than the first 2k
+    pub fn nametable_offset(&self, address: u16) -> usize {
+        let table = match self {
+            MirrorType::Horizontal => (address >> 11) & 0x1,
+            MirrorType::Vertical => (address >> 10) & 0x1,
+            MirrorType::FourScreen => (address >> 10) & 0x3,
+            MirrorType::SingleScreenLower => 0,
+            MirrorType::SingleScreenUpper => 1,
+        };
+        table as usize * 0x400 + (address as usize & 0x3ff)
+    }
 }
 
 impl From<MirrorType> for bool {
@@ -32,17 +72,87 @@ impl From<bool> for MirrorType {
     }
 }
 
+/// cpu/ppu timing from a nes 2.0 header, ines 1.0 headers are taken as ntsc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    /// runs on either
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    /// with the ppu and hardware type nibbles of byte 13
+    VsSystem { ppu: u8, hardware: u8 },
+    PlayChoice,
+    /// famiclones and the like, byte 13's low nibble
+    Extended(u8),
+}
+
 pub struct CartridgeInfo {
+    /// nes 2.0 header, the fields it adds are left at their ines 1.0 defaults otherwise
+    pub nes2: bool,
+    /// prg rom size in bytes
     pub prg: usize,
+    /// chr rom size in bytes, 0 for boards with chr ram
     pub chr: usize,
     pub mapper: usize,
+    /// nes 2.0 submapper, 0 for ines 1.0 headers
+    pub submapper: u8,
     pub mirror_type: MirrorType,
+    /// byte 6 bits 0 and 3 as they are, some boards give them their own meaning
+    pub mirror_bits: u8,
     pub has_backed: bool,
     pub data_start: usize,
+    /// prg ram size in bytes, ines 1.0 headers don't say so boards get 8k
+    pub prg_ram: usize,
+    /// battery backed prg ram (or eeprom) in bytes, nes 2.0 only
+    pub prg_nvram: usize,
+    /// chr ram size in bytes, nes 2.0 only
+    pub chr_ram: usize,
+    pub chr_nvram: usize,
+    pub timing: Timing,
+    pub console_type: ConsoleType,
+    /// roms after the chr rom, like playchoice hints or a vs. dual system's second half
+    pub misc_roms: u8,
+    /// nes 2.0 default expansion device, 0 when unspecified
+    pub expansion_device: u8,
+}
+
+/// nes 2.0 rom sizes: a 12-bit count of `unit` sized banks, or when the upper
+/// nibble is $F, 2^E * (MM * 2 + 1) bytes out of the low byte
+fn rom_size(low: u8, high: u8, unit: usize) -> Result<usize, EmuError> {
+    let size = if high == 0xf {
+        1usize
+            .checked_shl((low >> 2) as u32)
+            .and_then(|size| size.checked_mul((low & 0x3) as usize * 2 + 1))
+    } else {
+        ((high as usize) << 8 | low as usize).checked_mul(unit)
+    };
+    size.ok_or_else(|| {
+        EmuError::new(
+            "rom size in the nes 2.0 header overflows".to_string(),
+            file!().to_string(),
+            line!(),
+        )
+    })
+}
+
+/// nes 2.0 ram sizes are a shift count, 64 << n bytes or nothing for 0
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
 }
 
 impl CartridgeInfo {
-    pub fn parse_header(buf: [u8; 16]) -> Result<Self, EmuError> {
+    /// `file_len` is the whole image's size, header included
+    pub fn parse_header(buf: [u8; 16], file_len: usize) -> Result<Self, EmuError> {
         let mut magic = [0u8; 4];
         magic.copy_from_slice(&buf[0..4]);
         if magic != [0x4e, 0x45, 0x53, 0x1a] {
@@ -53,8 +163,6 @@ impl CartridgeInfo {
             ));
         }
         
-        let prg = buf[4] as usize;
-        let chr = buf[5] as usize;
         let mirror = if buf[6] & 0b1 != 0 {
             MirrorType::Vertical
         } else if buf[6] & 0b1000 != 0 {
@@ -63,65 +171,343 @@ impl CartridgeInfo {
             MirrorType::Horizontal
         };
         let backed = buf[6] & 0b10 != 0;
-        let mapper = buf[7] & 0xf0 | ((buf[6] & 0xf0) >> 4);
         let data_start = if buf[6] & 0b100 != 0 { 16 + 512 } else { 16 };
-        
-        Ok(Self {
-            prg,
-            chr,
-            mapper: mapper as usize,
+        let nes2 = buf[7] & 0b1100 == 0b1000;
+        let mut info = Self {
+            nes2,
+            prg: buf[4] as usize * 0x4000,
+            chr: buf[5] as usize * 0x2000,
+            mapper: (buf[6] >> 4) as usize,
+            submapper: 0,
             mirror_type: mirror,
+            mirror_bits: buf[6] & 0b1001,
             has_backed: backed,
             data_start,
-        })
+            prg_ram: 0x2000,
+            prg_nvram: 0,
+            chr_ram: 0,
+            chr_nvram: 0,
+            timing: Timing::Ntsc,
+            console_type: ConsoleType::Nes,
+            misc_roms: 0,
+            expansion_device: 0,
+        };
+        if !nes2 {
+            // old dumps carry junk like "DiskDude!" from byte 7 on, byte 7 is
+            // only trusted when the tail of the header is clean
+            if buf[12..16] == [0; 4] {
+                info.mapper |= (buf[7] & 0xf0) as usize;
+            }
+            return Ok(info);
+        }
+
+        info.mapper |= (buf[7] & 0xf0) as usize | ((buf[8] & 0x0f) as usize) << 8;
+        info.submapper = buf[8] >> 4;
+        info.prg = rom_size(buf[4], buf[9] & 0x0f, 0x4000)?;
+        info.chr = rom_size(buf[5], buf[9] >> 4, 0x2000)?;
+        // the exponent form reaches sizes no allocation survives, so unlike ines 1.0
+        // a short file is an error instead of zero padding
+        let end = data_start.checked_add(info.prg).and_then(|end| end.checked_add(info.chr));
+        if end.is_none_or(|end| end > file_len) {
+            return Err(EmuError::new(
+                "the file is shorter than its nes 2.0 header says".to_string(),
+                file!().to_string(),
+                line!(),
+            ));
+        }
+        info.prg_ram = ram_size(buf[10] & 0x0f);
+        info.prg_nvram = ram_size(buf[10] >> 4);
+        info.chr_ram = ram_size(buf[11] & 0x0f);
+        info.chr_nvram = ram_size(buf[11] >> 4);
+        info.timing = match buf[12] & 0x3 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        };
+        info.console_type = match buf[7] & 0x3 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: buf[13] & 0x0f,
+                hardware: buf[13] >> 4,
+            },
+            2 => ConsoleType::PlayChoice,
+            _ => ConsoleType::Extended(buf[13] & 0x0f),
+        };
+        info.misc_roms = buf[14] & 0x3;
+        info.expansion_device = buf[15] & 0x3f;
+        Ok(info)
+    }
+
+    /// all the prg ram the board has, battery backed or not
+    pub fn prg_ram_size(&self) -> usize {
+        self.prg_ram + self.prg_nvram
+    }
+
+    /// chr ram for boards without chr rom, never less than the 8k the ppu sees
+    pub fn chr_ram_size(&self) -> usize {
+        (self.chr_ram + self.chr_nvram).max(0x2000)
     }
 }
 
 pub struct Cartridge {
     mapper: Box<dyn Mapper>,
     info: CartridgeInfo,
+    /// md5 of the prg and chr rom, the header left out
+    checksum: [u8; 16],
+    /// `<rom>.sav` next to the rom, for boards with a battery
+    save_path: Option<PathBuf>,
+    /// the save ram as it was last read from or written to `save_path`
+    saved: Vec<u8>,
 }
 
 impl Cartridge {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let image_file = handle_result(
-            File::open(path)
-                .map_err(|e| EmuError::new(e.to_string(), file!().to_string(), line!())),
-        );
+        handle_result(Self::load(path))
+    }
+
+    /// `new` without exiting on a bad image
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EmuError> {
+        let image_file = File::open(path.as_ref())
+            .map_err(|e| EmuError::new(e.to_string(), file!().to_string(), line!()))?;
         
         let mut buf = [0u8; 16];
         let _ = image_file.read_at(&mut buf, 0).unwrap();
-        let info = handle_result(CartridgeInfo::parse_header(buf));
+        let file_len = image_file.metadata().map_or(0, |metadata| metadata.len() as usize);
+        let info = CartridgeInfo::parse_header(buf, file_len)?;
         
         //read prg and chr data from ines
-        let mut prg = vec![0u8; info.prg * 0x4000];
-        let mut chr = vec![0u8; info.chr * 0x2000];
+        let mut prg = vec![0u8; info.prg];
+        let mut chr = vec![0u8; info.chr];
         let mut ptr = info.data_start;
         let _ = image_file.read_at(&mut prg, ptr as u64);
         ptr += prg.len();
         let _ = image_file.read_at(&mut chr, ptr as u64);
+        let checksum = md5(&[prg.as_slice(), chr.as_slice()].concat());
         
-        match info.mapper {
-            NROM => {
-                let nrom = NRom::new(prg, chr, info.mirror_type.into());
-                Self {
-                    mapper: Box::new(nrom),
-                    info,
-                }
+        let mirroring = info.mirror_type;
+        let chr_ram = info.chr_ram_size();
+        let mapper: Box<dyn Mapper> = match info.mapper {
+            NROM => Box::new(NRom::new(prg, chr, chr_ram, mirroring)),
+            MMC1 => Box::new(Mmc1::new(prg, chr, chr_ram, info.prg_ram_size())),
+            UXROM => Box::new(UxRom::new(prg, chr, chr_ram, mirroring, true)),
+            CNROM => Box::new(CnRom::new(prg, chr, chr_ram, mirroring, true)),
+            MMC3 => {
+                let board = match info.submapper {
+                    1 => Mmc3Board::Mmc6,
+                    4 => Mmc3Board::Mmc3(IrqRevision::Nec),
+                    _ => Mmc3Board::Mmc3(IrqRevision::Sharp),
+                };
+                Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, board))
+            }
+            // ines 1.0 can't say how much ram an mmc5 board has, give it all 64k
+            MMC5 => {
+                let prg_ram = if info.nes2 { info.prg_ram_size() } else { 0x10000 };
+                Box::new(Mmc5::new(prg, chr, chr_ram, prg_ram))
+            }
+            // nes 2.0 submapper 2 is amrom, the only board with bus conflicts
+            AXROM => Box::new(AxRom::new(prg, chr, chr_ram, info.submapper == 2)),
+            MMC2 => Box::new(Mmc2::new(prg, chr, chr_ram, mirroring, false)),
+            MMC4 => Box::new(Mmc2::new(prg, chr, chr_ram, mirroring, true)),
+            COLOR_DREAMS => Box::new(ColorDreams::new(prg, chr, chr_ram, mirroring)),
+            BANDAI_FCG => Box::new(BandaiFcg::new(prg, chr, chr_ram, info.mapper, info.submapper)),
+            NAMCO_163 => Box::new(Namco163::new(prg, chr, chr_ram)),
+            VRC4AC | VRC2A | VRC4EF | VRC4BD => {
+                Box::new(Vrc4::new(prg, chr, chr_ram, info.mapper, info.submapper))
+            }
+            VRC6A | VRC6B => Box::new(Vrc6::new(prg, chr, chr_ram, info.mapper)),
+            // a battery bit on unrom 512 means the game rewrites its own flash
+            UNROM512 => {
+                Box::new(Unrom512::new(prg, info.mirror_bits, info.has_backed, info.submapper))
+            }
+            BNROM => Box::new(BnRom::new(prg, chr, chr_ram, mirroring)),
+            GXROM => Box::new(GxRom::new(prg, chr, chr_ram, mirroring)),
+            FME7 => Box::new(Fme7::new(prg, chr, chr_ram, info.prg_ram_size())),
+            CAMERICA => Box::new(Camerica::new(prg, chr, chr_ram, mirroring)),
+            NAMCO_3446 => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::Namco3446)),
+            VRC7 => Box::new(Vrc7::new(prg, chr, chr_ram)),
+            NAMCO_3433 => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::Namco3433)),
+            NAMCO_3425 => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::Namco3425)),
+            GTROM => Box::new(GtRom::new(prg)),
+            TXSROM => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::TxSRom)),
+            TQROM => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::TqRom)),
+            BANDAI_SRAM => Box::new(BandaiFcg::new(prg, chr, chr_ram, info.mapper, info.submapper)),
+            NAMCO_3453 => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::Namco3453)),
+            DATACH | BANDAI_24C01 => {
+                Box::new(BandaiFcg::new(prg, chr, chr_ram, info.mapper, info.submapper))
             }
+            NAMCO_108 => Box::new(Mmc3::new(prg, chr, chr_ram, mirroring, Mmc3Board::Namco108)),
+            MAGIC_FLOOR => Box::new(MagicFloor::new(prg, info.mirror_bits)),
             _ => {
-                println!("unsupported mapper type {}", info.mapper);
-                exit(0)
+                return Err(EmuError::new(
+                    format!("unsupported mapper type {}", info.mapper),
+                    file!().to_string(),
+                    line!(),
+                ))
             }
+        };
+        let mut cartridge = Self {
+            mapper,
+            info,
+            checksum,
+            save_path: None,
+            saved: vec![],
+        };
+        if cartridge.persistent() {
+            let save_path = path.as_ref().with_extension("sav");
+            // no .sav yet is a new game
+            if let Ok(data) = std::fs::read(&save_path) {
+                cartridge.load_save_ram(&data);
+            }
+            cartridge.saved = cartridge.save_ram().map(Cow::into_owned).unwrap_or_default();
+            cartridge.save_path = Some(save_path);
         }
+        Ok(cartridge)
     }
     
-    pub fn read(&self, address: u16) -> u8 {
-        self.mapper.read(address)
+    /// an nsf player's hardware in place of a cartridge
+    pub fn from_nsf(nsf: &Nsf) -> Self {
+        Self {
+            mapper: Box::new(NsfMapper::new(nsf)),
+            info: CartridgeInfo {
+                nes2: false,
+                prg: nsf.data.len(),
+                chr: 0,
+                mapper: NSF,
+                submapper: 0,
+                mirror_type: MirrorType::Vertical,
+                mirror_bits: 0b0001,
+                has_backed: false,
+                data_start: 0,
+                prg_ram: 0x2000,
+                prg_nvram: 0,
+                chr_ram: 0,
+                chr_nvram: 0,
+                timing: Timing::Ntsc,
+                console_type: ConsoleType::Nes,
+                misc_roms: 0,
+                expansion_device: 0,
+            },
+            checksum: md5(&nsf.data),
+            save_path: None,
+            saved: vec![],
+        }
+    }
+
+    pub fn info(&self) -> &CartridgeInfo {
+        &self.info
+    }
+
+    pub fn checksum(&self) -> [u8; 16] {
+        self.checksum
+    }
+
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        self.mapper.cpu_read(address)
     }
     
-    pub fn write(&mut self, address: u16, data: u8) {
-        self.mapper.write(address, data)
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        self.mapper.cpu_write(address, data)
+    }
+
+    pub fn ppu_read(&mut self, address: u16) -> u8 {
+        self.mapper.ppu_read(address)
+    }
+
+    pub fn ppu_write(&mut self, address: u16, data: u8) {
+        self.mapper.ppu_write(address, data)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        self.mapper.peek(address)
+    }
+
+    pub fn mirroring(&self) -> MirrorType {
+        self.mapper.mirroring()
+    }
+
+    pub fn nametable_offset(&self, address: u16) -> usize {
+        self.mapper.nametable_offset(address)
+    }
+
+    pub fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        self.mapper.nametable_read(address)
+    }
+
+    pub fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        self.mapper.nametable_write(address, data)
+    }
+
+    pub fn ppu_register_write(&mut self, address: u16, data: u8) {
+        self.mapper.ppu_register_write(address, data)
+    }
+
+    /// whether the save ram outlives power-off, through a battery, nes 2.0
+    /// nvram or a board that needs none
+    fn persistent(&self) -> bool {
+        self.info.has_backed || self.info.prg_nvram > 0 || self.mapper.nonvolatile()
+    }
+
+    /// the board's ram when it is battery backed or otherwise kept
+    pub fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        if self.persistent() {
+            self.mapper.save_ram()
+        } else {
+            None
+        }
+    }
+
+    pub fn load_save_ram(&mut self, data: &[u8]) {
+        if self.persistent() {
+            self.mapper.load_save_ram(data);
+        }
+    }
+
+    /// write the save ram back to `<rom>.sav` if it changed since the last time
+    pub fn write_save(&mut self) -> Result<(), EmuError> {
+        let (Some(path), Some(data)) = (self.save_path.as_ref(), self.save_ram()) else {
+            return Ok(());
+        };
+        if *data == *self.saved {
+            return Ok(());
+        }
+        let data = data.into_owned();
+        std::fs::write(path, &data)
+            .map_err(|e| EmuError::new(e.to_string(), file!().to_string(), line!()))?;
+        self.saved = data;
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        self.mapper.save_state(&mut state);
+        state.into_inner()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        self.mapper.load_state(&mut StateReader::new(state))
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
+    /// clock the board for one cpu cycle and return the level of its sound chip
+    pub fn clock(&mut self) -> f32 {
+        self.mapper.cpu_clock();
+        match self.mapper.expansion_audio() {
+            Some(audio) => {
+                audio.clock();
+                audio.output()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        let _ = self.write_save();
     }
 }
 
@@ -130,3 +516,145 @@ fn test() {
     let cart = Cartridge::new("./test/nestest.nes");
     println!("trest");
 }
+
+#[test]
+fn test_parse_header() {
+    let mut header = *b"NES\x1a\x02\x01\x12DiskDude!";
+    let info = CartridgeInfo::parse_header(header, 0x10000).unwrap();
+    assert!(!info.nes2);
+    assert_eq!((info.prg, info.chr), (0x8000, 0x2000));
+    // junk in the tail of the header leaves byte 7 out of the mapper
+    assert_eq!(info.mapper, 1);
+    assert_eq!(info.mirror_type, MirrorType::Horizontal);
+    assert!(info.has_backed);
+
+    header[7..16].copy_from_slice(&[0x49, 0x51, 0x0f, 0x70, 0x07, 0x01, 0x03, 0x01, 0x2a]);
+    header[4] = 0x0a;
+    let info = CartridgeInfo::parse_header(header, 0x10000).unwrap();
+    assert!(info.nes2);
+    assert_eq!((info.mapper, info.submapper), (0x141, 5));
+    // 2^2 * 5 bytes of prg in the exponent form
+    assert_eq!((info.prg, info.chr), (20, 0x2000));
+    assert_eq!((info.prg_ram, info.prg_nvram), (0, 0x2000));
+    assert_eq!((info.chr_ram, info.chr_nvram), (0x2000, 0));
+    assert_eq!(info.timing, Timing::Pal);
+    assert_eq!(info.console_type, ConsoleType::VsSystem { ppu: 3, hardware: 0 });
+    assert_eq!(info.misc_roms, 1);
+    assert_eq!(info.expansion_device, 0x2a);
+
+    // 2^60 bytes of prg can't be in the file, 2^63 * 7 doesn't fit at all
+    header[4] = 0xf0;
+    assert!(CartridgeInfo::parse_header(header, 0x10000).is_err());
+    header[4] = 0xff;
+    assert!(CartridgeInfo::parse_header(header, 0x10000).is_err());
+
+    // 32k of chr ram and nothing in chr rom
+    header[4..6].copy_from_slice(&[0x02, 0x00]);
+    header[9] = 0;
+    header[11] = 0x09;
+    let info = CartridgeInfo::parse_header(header, 0x10000).unwrap();
+    assert_eq!((info.prg, info.chr), (0x8000, 0));
+    assert_eq!(info.chr_ram_size(), 0x8000);
+}
+
+#[test]
+fn test_truncated_file() {
+    let dir = std::env::temp_dir().join(format!("nesrs-truncated-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // uxrom claiming 128k of prg with only $100 bytes behind the header
+    let mut image = b"NES\x1a\x08\x00\x20\x08".to_vec();
+    image.resize(16 + 0x100, 0);
+    std::fs::write(&rom, &image).unwrap();
+    assert!(Cartridge::load(&rom).is_err());
+
+    // ines 1.0 zero pads what's missing
+    image[7] = 0;
+    std::fs::write(&rom, &image).unwrap();
+    let mut cartridge = Cartridge::load(&rom).unwrap();
+    assert_eq!(cartridge.cpu_read(0xc000), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_save_file() {
+    let dir = std::env::temp_dir().join(format!("nesrs-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // nrom with a battery, 16k prg and 8k chr
+    let mut image = b"NES\x1a\x01\x01\x02\x00".to_vec();
+    image.resize(16 + 0x4000 + 0x2000, 0);
+    std::fs::write(&rom, &image).unwrap();
+
+    let mut cartridge = Cartridge::new(&rom);
+    cartridge.cpu_write(0x6000, 0x42);
+    drop(cartridge);
+    assert_eq!(std::fs::read(dir.join("game.sav")).unwrap()[0], 0x42);
+
+    let mut cartridge = Cartridge::new(&rom);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+    cartridge.load_save_ram(&[0x24]);
+    cartridge.write_save().unwrap();
+    assert_eq!(std::fs::read(dir.join("game.sav")).unwrap()[0], 0x24);
+    drop(cartridge);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_nvram_save() {
+    let dir = std::env::temp_dir().join(format!("nesrs-nvram-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // nes 2.0 nrom with 8k of prg nvram but no battery bit
+    let mut image = b"NES\x1a\x01\x01\x00\x08\x00\x00\x70".to_vec();
+    image.resize(16 + 0x4000 + 0x2000, 0);
+    std::fs::write(&rom, &image).unwrap();
+
+    let mut cartridge = Cartridge::new(&rom);
+    cartridge.cpu_write(0x6000, 0x42);
+    drop(cartridge);
+    assert_eq!(std::fs::read(dir.join("game.sav")).unwrap()[0], 0x42);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_eeprom_save() {
+    let dir = std::env::temp_dir().join(format!("nesrs-eeprom-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // bandai fcg without the battery bit, the 24c02 keeps the save anyway
+    let mut image = b"NES\x1a\x01\x01\x00\x10".to_vec();
+    image.resize(16 + 0x4000 + 0x2000, 0);
+    std::fs::write(&rom, &image).unwrap();
+
+    let mut cartridge = Cartridge::new(&rom);
+    assert_eq!(cartridge.save_ram().map(|ram| ram.len()), Some(0x100));
+    cartridge.load_save_ram(&[0x5a]);
+    drop(cartridge);
+    assert_eq!(std::fs::read(dir.join("game.sav")).unwrap()[0], 0x5a);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_flash_save() {
+    let dir = std::env::temp_dir().join(format!("nesrs-flash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.nes");
+    // gtrom with 32k of erased flash and no battery bit
+    let mut image = b"NES\x1a\x02\x00\xf0\x60".to_vec();
+    image.resize(16 + 0x8000, 0xff);
+    image[8..16].fill(0);
+    std::fs::write(&rom, &image).unwrap();
+
+    let mut cartridge = Cartridge::new(&rom);
+    for (address, data) in [(0xd555, 0xaa), (0xaaaa, 0x55), (0xd555, 0xa0), (0x8010, 0x12)] {
+        cartridge.cpu_write(address, data);
+    }
+    drop(cartridge);
+    assert_eq!(std::fs::read(dir.join("game.sav")).unwrap()[0x10], 0x12);
+
+    let mut cartridge = Cartridge::new(&rom);
+    assert_eq!(cartridge.cpu_read(0x8010), 0x12);
+    drop(cartridge);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
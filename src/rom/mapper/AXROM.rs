@@ -0,0 +1,109 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/// axrom (mapper 7): a switchable 32k prg bank, 8k of chr ram and one screen
+/// mirroring picked by bit 4 of the bank register
+pub struct AxRom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl AxRom {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        bus_conflicts: bool,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            bus_conflicts,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for AxRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, mut data: u8) {
+        if address >= 0x8000 {
+            if self.bus_conflicts {
+                data &= self.peek(address);
+            }
+            self.bank = data;
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize & 0x1fff]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[address as usize & 0x1fff] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => {
+                let bank = (self.bank & 0x7) as usize % (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                self.prg_rom
+                    [(bank * PRG_BANK_SIZE + (address as usize & 0x7fff)) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        if self.bank & 0x10 != 0 {
+            MirrorType::SingleScreenUpper
+        } else {
+            MirrorType::SingleScreenLower
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_axrom() {
+    let prg = (0..8)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect();
+    let mut mapper = AxRom::new(prg, vec![], 0x2000, false);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenLower);
+    mapper.cpu_write(0x8000, 0x15);
+    assert_eq!(mapper.cpu_read(0x8000), 5);
+    assert_eq!(mapper.cpu_read(0xffff), 5);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenUpper);
+}
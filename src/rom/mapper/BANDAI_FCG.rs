@@ -0,0 +1,331 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::eeprom::{Eeprom, EepromChip};
+use crate::rom::mapper::{load_ram, Mapper, BANDAI_24C01, BANDAI_SRAM, DATACH};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// bandai fcg-1/2 and lz93d50 (mappers 16, 153, 157 and 159): eight 1k chr
+/// banks, a 16k prg bank at $8000 with the last bank fixed at $C000 and a
+/// 16-bit irq counter running every cpu cycle. most boards keep their saves in
+/// a serial eeprom read back through bit 4 of $6000, mapper 153 has 8k of
+/// battery ram instead
+pub struct BandaiFcg {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    /// mapper 153 only
+    prg_ram: Vec<u8>,
+    eeprom: Option<Eeprom>,
+    /// the fcg-1/2 decodes its registers at $6000, the lz93d50 at $8000
+    registers: (bool, bool),
+    /// the lz93d50 reloads the counter from a latch, the fcg writes it directly
+    latched: bool,
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    mirroring: MirrorType,
+    ram_enabled: bool,
+    irq_enabled: bool,
+    irq_latch: u16,
+    irq_counter: u16,
+    irq_pending: bool,
+}
+
+impl BandaiFcg {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mapper: usize,
+        submapper: u8,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        // ines 1.0 mapper 16 could be either chip, so decode both ranges
+        let (chip, registers, latched) = match (mapper, submapper) {
+            (BANDAI_24C01, _) => (Some(EepromChip::X24C01), (false, true), true),
+            (BANDAI_SRAM, _) => (None, (false, true), true),
+            // the datach's own 24c01 and barcode reader aren't emulated
+            (DATACH, _) => (Some(EepromChip::C24C02), (false, true), true),
+            (_, 4) => (None, (true, false), false),
+            (_, 5) => (Some(EepromChip::C24C02), (false, true), true),
+            _ => (Some(EepromChip::C24C02), (true, true), false),
+        };
+        let prg_ram_size = if mapper == BANDAI_SRAM { 0x2000 } else { 0 };
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: vec![0; prg_ram_size],
+            eeprom: chip.map(Eeprom::new),
+            registers,
+            latched,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring: MirrorType::Vertical,
+            ram_enabled: false,
+            irq_enabled: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_pending: false,
+        }
+    }
+
+    /// mapper 153 uses bit 0 of the chr registers as a 256k outer prg bank
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let outer = if self.prg_ram.is_empty() {
+            0
+        } else {
+            (self.chr_banks.iter().fold(0, |outer, bank| outer | bank) & 0x1) as usize
+        };
+        let bank = match address {
+            0x8000..=0xbfff => self.prg_bank as usize & 0xf,
+            _ => 0xf,
+        };
+        ((outer << 4 | bank) % bank_count) * PRG_BANK_SIZE
+            + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = if self.chr_ram {
+            address as usize / CHR_BANK_SIZE
+        } else {
+            self.chr_banks[address as usize / CHR_BANK_SIZE] as usize
+        };
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        match address & 0xf {
+            reg @ 0x0..=0x7 => self.chr_banks[reg as usize] = data,
+            0x8 => self.prg_bank = data,
+            0x9 => {
+                self.mirroring = match data & 0x3 {
+                    0 => MirrorType::Vertical,
+                    1 => MirrorType::Horizontal,
+                    2 => MirrorType::SingleScreenLower,
+                    _ => MirrorType::SingleScreenUpper,
+                };
+            }
+            0xa => {
+                self.irq_enabled = data & 0x1 != 0;
+                self.irq_pending = false;
+                if self.latched {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xb => {
+                self.irq_latch = (self.irq_latch & 0xff00) | data as u16;
+                if !self.latched {
+                    self.irq_counter = (self.irq_counter & 0xff00) | data as u16;
+                }
+            }
+            0xc => {
+                self.irq_latch = (self.irq_latch & 0x00ff) | (data as u16) << 8;
+                if !self.latched {
+                    self.irq_counter = (self.irq_counter & 0x00ff) | (data as u16) << 8;
+                }
+            }
+            _ => {
+                self.ram_enabled = data & 0x20 != 0;
+                // bit 5 is scl and bit 6 sda, bit 7 lets the chip drive sda
+                if let Some(eeprom) = self.eeprom.as_mut() {
+                    eeprom.write(data & 0x20 != 0, data & 0x40 != 0 || data & 0x80 != 0);
+                }
+            }
+        }
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram[address as usize - 0x6000] = data;
+            }
+            0x6000..=0x7fff if self.registers.0 => self.write_register(address, data),
+            0x8000..=0xffff if self.registers.1 => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram[address as usize - 0x6000]
+            }
+            0x6000..=0x7fff => match &self.eeprom {
+                Some(eeprom) => (eeprom.read() as u8) << 4,
+                None => 0,
+            },
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        match &self.eeprom {
+            Some(eeprom) => Some(Cow::Borrowed(eeprom.data())),
+            None if !self.prg_ram.is_empty() => Some(Cow::Borrowed(&self.prg_ram)),
+            None => None,
+        }
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        match self.eeprom.as_mut() {
+            Some(eeprom) => load_ram(eeprom.data_mut(), data),
+            None => load_ram(&mut self.prg_ram, data),
+        }
+    }
+
+    fn nonvolatile(&self) -> bool {
+        self.eeprom.is_some()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if let Some(eeprom) = &self.eeprom {
+            eeprom.save_state(state);
+        }
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.chr_banks);
+        state.write_u8(self.prg_bank);
+        state.write_u8(self.mirroring as u8);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.irq_enabled);
+        state.write_u16(self.irq_latch);
+        state.write_u16(self.irq_counter);
+        state.write_bool(self.irq_pending);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if let Some(eeprom) = self.eeprom.as_mut() {
+            eeprom.load_state(state)?;
+        }
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.chr_banks)?;
+        self.prg_bank = state.read_u8()?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.ram_enabled = state.read_bool()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_latch = state.read_u16()?;
+        self.irq_counter = state.read_u16()?;
+        self.irq_pending = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_bandai_fcg() {
+    use crate::rom::mapper::BANDAI_FCG;
+
+    let mut mapper = BandaiFcg::new(
+        banked(16, PRG_BANK_SIZE),
+        banked(128, CHR_BANK_SIZE),
+        0x2000,
+        BANDAI_FCG,
+        5,
+    );
+    mapper.cpu_write(0x8008, 3);
+    mapper.cpu_write(0x8005, 70);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+    assert_eq!(mapper.ppu_read(0x1400), 70);
+    // the lz93d50 ignores $6000
+    mapper.cpu_write(0x6009, 1);
+    assert_eq!(mapper.mirroring(), MirrorType::Vertical);
+
+    // the counter only loads from the latch when $x00A is written
+    mapper.cpu_write(0x800b, 1);
+    mapper.cpu_write(0x800c, 0);
+    mapper.cpu_write(0x800a, 1);
+    mapper.cpu_clock();
+    assert!(!mapper.irq_pending());
+    mapper.cpu_clock();
+    assert!(mapper.irq_pending());
+    mapper.cpu_write(0x800a, 0);
+    assert!(!mapper.irq_pending());
+
+    // start condition, then $A0 clocked in with sda read back on the ack
+    let mut i2c = |scl: u8, sda: u8| mapper.cpu_write(0x800d, scl << 5 | sda << 6);
+    i2c(1, 1);
+    i2c(1, 0);
+    i2c(0, 0);
+    for n in (0..8).rev() {
+        let bit = 0xa0 >> n & 0x1;
+        i2c(0, bit);
+        i2c(1, bit);
+        i2c(0, bit);
+    }
+    mapper.cpu_write(0x800d, 0x80);
+    mapper.cpu_write(0x800d, 0xa0);
+    assert_eq!(mapper.cpu_read(0x6000) & 0x10, 0);
+}
+
+#[test]
+fn test_bandai_sram() {
+    let mut mapper = BandaiFcg::new(banked(32, PRG_BANK_SIZE), vec![], 0x2000, BANDAI_SRAM, 0);
+    mapper.cpu_write(0x8008, 2);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+    mapper.cpu_write(0x8000, 1);
+    assert_eq!(mapper.cpu_read(0x8000), 18);
+    assert_eq!(mapper.cpu_read(0xc000), 31);
+
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+    mapper.cpu_write(0x800d, 0x20);
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.save_ram().unwrap()[0], 0x55);
+}
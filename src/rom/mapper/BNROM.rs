@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// mapper 34, two unrelated boards. bnrom switches 32k of prg through
+/// $8000-$FFFF (with bus conflicts) over 8k of chr ram. nina-001 has prg ram
+/// at $6000 and its registers on top of it: $7FFD prg, $7FFE/$7FFF the two
+/// 4k chr banks. boards with more than 8k of chr are taken for nina-001
+pub struct BnRom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    nina: bool,
+    mirroring: MirrorType,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+}
+
+impl BnRom {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+    ) -> Self {
+        let nina = chr.len() > 0x2000;
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            nina,
+            mirroring,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+}
+
+impl Mapper for BnRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.nina => {
+                self.prg_ram[address as usize - 0x6000] = data;
+                match address {
+                    0x7ffd => self.prg_bank = data & 0x1,
+                    0x7ffe => self.chr_banks[0] = data & 0xf,
+                    0x7fff => self.chr_banks[1] = data & 0xf,
+                    _ => {}
+                }
+            }
+            0x8000..=0xffff if !self.nina => self.prg_bank = data & self.peek(address),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 12) as usize & 0x1] as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        self.chr[(bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[address as usize & 0x1fff] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.nina => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => {
+                let bank = self.prg_bank as usize % (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                self.prg_rom
+                    [(bank * PRG_BANK_SIZE + (address as usize & 0x7fff)) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        self.nina.then(|| Cow::Borrowed(&self.prg_ram[..]))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.prg_bank);
+        state.write_bytes(&self.chr_banks);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.prg_bank = state.read_u8()?;
+        state.read_bytes(&mut self.chr_banks)
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_bnrom() {
+    let mut prg = banked(4, PRG_BANK_SIZE);
+    prg[0x7000] = 0xff;
+    let mut mapper = BnRom::new(prg, vec![], 0x2000, MirrorType::Vertical);
+    mapper.cpu_write(0xf000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    mapper.ppu_write(0x1234, 0x55);
+    assert_eq!(mapper.ppu_read(0x1234), 0x55);
+    assert!(mapper.save_ram().is_none());
+}
+
+#[test]
+fn test_nina_001() {
+    let mut mapper = BnRom::new(
+        banked(2, PRG_BANK_SIZE),
+        banked(16, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Horizontal,
+    );
+    mapper.cpu_write(0x7ffd, 1);
+    mapper.cpu_write(0x7ffe, 9);
+    mapper.cpu_write(0x7fff, 4);
+    assert_eq!(mapper.cpu_read(0x8000), 1);
+    assert_eq!(mapper.ppu_read(0x0000), 9);
+    assert_eq!(mapper.ppu_read(0x1000), 4);
+    // writes to rom do nothing on this board
+    mapper.cpu_write(0x8000, 0);
+    assert_eq!(mapper.cpu_read(0x8000), 1);
+    assert_eq!(mapper.cpu_read(0x7ffe), 9);
+}
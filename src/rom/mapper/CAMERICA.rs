@@ -0,0 +1,114 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// camerica/codemasters bf909x (mapper 71): uxrom like banking through
+/// $C000-$FFFF, 8k of chr ram, and on the bf9097 (fire hawk) one screen
+/// mirroring through bit 4 of $8000-$9FFF, taken over once it is written
+pub struct Camerica {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    mirroring: MirrorType,
+    bank: u8,
+}
+
+impl Camerica {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            mirroring,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x8000..=0x9fff => {
+                self.mirroring = if data & 0x10 != 0 {
+                    MirrorType::SingleScreenUpper
+                } else {
+                    MirrorType::SingleScreenLower
+                };
+            }
+            0xc000..=0xffff => self.bank = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize & 0x1fff]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[address as usize & 0x1fff] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0xbfff => self.bank as usize % bank_count,
+            0xc000..=0xffff => bank_count - 1,
+            _ => return 0,
+        };
+        self.prg_rom
+            [(bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()]
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.bank);
+        state.write_u8(self.mirroring as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.bank = state.read_u8()?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_camerica() {
+    let prg = (0..16)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect();
+    let mut mapper = Camerica::new(prg, vec![], 0x2000, MirrorType::Horizontal);
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+    mapper.cpu_write(0xc000, 6);
+    assert_eq!(mapper.cpu_read(0x8000), 6);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+    mapper.cpu_write(0x9000, 0x10);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenUpper);
+}
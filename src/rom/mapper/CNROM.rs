@@ -0,0 +1,96 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// cnrom (mapper 3): fixed prg, 16k mirrored like nrom, and a switchable 8k
+/// chr bank
+pub struct CnRom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: MirrorType,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl CnRom {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+        bus_conflicts: bool,
+    ) -> Self {
+        if chr.is_empty() {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            mirroring,
+            bus_conflicts,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for CnRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, mut data: u8) {
+        if address >= 0x8000 {
+            if self.bus_conflicts {
+                data &= self.peek(address);
+            }
+            self.bank = data;
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank = self.bank as usize % (self.chr.len() / CHR_BANK_SIZE);
+        self.chr[bank * CHR_BANK_SIZE + (address as usize & 0x1fff)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.prg_rom[(address as usize - 0x8000) % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_cnrom() {
+    let chr = (0..4)
+        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+        .collect();
+    let mut mapper = CnRom::new(
+        vec![0xff; 0x4000],
+        chr,
+        0x2000,
+        MirrorType::Horizontal,
+        true,
+    );
+    mapper.cpu_write(0x8000, 2);
+    assert_eq!(mapper.ppu_read(0x1000), 2);
+    assert_eq!(mapper.cpu_read(0xc000), 0xff);
+}
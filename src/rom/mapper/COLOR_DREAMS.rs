@@ -0,0 +1,97 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// color dreams (mapper 11): one register, `CCCC --PP`, switching 32k of prg
+/// and 8k of chr
+pub struct ColorDreams {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: MirrorType,
+    bank: u8,
+}
+
+impl ColorDreams {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+    ) -> Self {
+        if chr.is_empty() {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            mirroring,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        if address >= 0x8000 {
+            // the board has bus conflicts
+            self.bank = data & self.peek(address);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank = (self.bank >> 4) as usize % (self.chr.len() / CHR_BANK_SIZE);
+        self.chr[bank * CHR_BANK_SIZE + (address as usize & 0x1fff)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => {
+                let bank = (self.bank & 0x3) as usize % (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                self.prg_rom
+                    [(bank * PRG_BANK_SIZE + (address as usize & 0x7fff)) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_color_dreams() {
+    let prg = (0..4)
+        .flat_map(|bank| vec![0xff ^ bank as u8; PRG_BANK_SIZE])
+        .collect();
+    let chr = (0..16)
+        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+        .collect();
+    let mut mapper = ColorDreams::new(prg, chr, 0x2000, MirrorType::Vertical);
+    mapper.cpu_write(0x8000, 0x52);
+    assert_eq!(mapper.cpu_read(0x8000), 0xfd);
+    assert_eq!(mapper.ppu_read(0x0000), 5);
+    // bank 2 holds $FD, so bit 1 of the write is lost
+    mapper.cpu_write(0x8000, 0x37);
+    assert_eq!(mapper.ppu_read(0x0000), 3);
+    assert_eq!(mapper.cpu_read(0x8000), 0xfe);
+}
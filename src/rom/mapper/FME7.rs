@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+
+use crate::apu::expansion::{ExpansionAudio, Sunsoft5BAudio};
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// sunsoft fme-7 and 5a/5b (mapper 69): a command register at $8000 and its
+/// parameter at $A000 select eight 1k chr banks, three 8k prg banks, rom or
+/// ram at $6000, mirroring and a 16-bit irq counter running every cpu cycle.
+/// the 5b adds the ym2149 derived sound chip at $C000/$E000
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    command: u8,
+    chr_banks: [u8; 8],
+    /// $6000 then $8000, $A000 and $C000
+    prg_banks: [u8; 4],
+    mirroring: MirrorType,
+    irq_enabled: bool,
+    counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5BAudio,
+}
+
+impl Fme7 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        prg_ram_size: usize,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: vec![0; prg_ram_size.max(PRG_BANK_SIZE)],
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: MirrorType::Vertical,
+            irq_enabled: false,
+            counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5BAudio::default(),
+        }
+    }
+
+    fn prg_rom_offset(&self, bank: u8, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        (bank as usize % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn prg_ram_offset(&self, address: u16) -> usize {
+        let bank = (self.prg_banks[0] & 0x3f) as usize;
+        (bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))) % self.prg_ram.len()
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize / CHR_BANK_SIZE] as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data,
+            0x8 => self.prg_banks[0] = data,
+            0x9..=0xb => self.prg_banks[self.command as usize - 0x8] = data & 0x3f,
+            0xc => {
+                self.mirroring = match data & 0x3 {
+                    0 => MirrorType::Vertical,
+                    1 => MirrorType::Horizontal,
+                    2 => MirrorType::SingleScreenLower,
+                    _ => MirrorType::SingleScreenUpper,
+                };
+            }
+            0xd => {
+                self.irq_enabled = data & 0x1 != 0;
+                self.counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xe => self.irq_counter = (self.irq_counter & 0xff00) | data as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00ff) | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            // $6000 bit 6 picks ram, bit 7 enables it
+            0x6000..=0x7fff if self.prg_banks[0] & 0xc0 == 0xc0 => {
+                let offset = self.prg_ram_offset(address);
+                self.prg_ram[offset] = data;
+            }
+            0x8000..=0x9fff => self.command = data & 0xf,
+            0xa000..=0xbfff => self.write_parameter(data),
+            0xc000..=0xffff => self.audio.write(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => match self.prg_banks[0] & 0xc0 {
+                0xc0 => self.prg_ram[self.prg_ram_offset(address)],
+                0x40 => 0,
+                _ => self.prg_rom[self.prg_rom_offset(self.prg_banks[0] & 0x3f, address)],
+            },
+            0xe000..=0xffff => self.prg_rom[self.prg_rom_offset(0xff, address)],
+            0x8000..=0xdfff => {
+                let bank = self.prg_banks[1 + (address as usize - 0x8000) / PRG_BANK_SIZE];
+                self.prg_rom[self.prg_rom_offset(bank, address)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// fires when the counter wraps from 0 to $FFFF
+    fn cpu_clock(&mut self) {
+        if !self.counter_enabled {
+            return;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0xffff && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.command);
+        state.write_bytes(&self.chr_banks);
+        state.write_bytes(&self.prg_banks);
+        state.write_u8(self.mirroring as u8);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.counter_enabled);
+        state.write_u16(self.irq_counter);
+        state.write_bool(self.irq_pending);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.command = state.read_u8()?;
+        state.read_bytes(&mut self.chr_banks)?;
+        state.read_bytes(&mut self.prg_banks)?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.irq_enabled = state.read_bool()?;
+        self.counter_enabled = state.read_bool()?;
+        self.irq_counter = state.read_u16()?;
+        self.irq_pending = state.read_bool()?;
+        self.audio.load_state(state)
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_fme7() {
+    let mut mapper = Fme7::new(
+        banked(32, PRG_BANK_SIZE),
+        banked(64, CHR_BANK_SIZE),
+        0x2000,
+        0x2000,
+    );
+    let write = |mapper: &mut Fme7, command: u8, data: u8| {
+        mapper.cpu_write(0x8000, command);
+        mapper.cpu_write(0xa000, data);
+    };
+    for (command, bank) in [(0x9, 4), (0xa, 5), (0xb, 6), (0x3, 40)] {
+        write(&mut mapper, command, bank);
+    }
+    let prg = [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.cpu_read(address));
+    assert_eq!(prg, [4, 5, 6, 31]);
+    assert_eq!(mapper.ppu_read(0x0c00), 40);
+
+    // $6000 as rom, then as ram
+    write(&mut mapper, 0x8, 7);
+    assert_eq!(mapper.cpu_read(0x6000), 7);
+    write(&mut mapper, 0x8, 0xc0);
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0x55);
+    write(&mut mapper, 0x8, 0x40);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+
+    write(&mut mapper, 0xe, 2);
+    write(&mut mapper, 0xf, 0);
+    write(&mut mapper, 0xd, 0x81);
+    for _ in 0..2 {
+        mapper.cpu_clock();
+    }
+    assert!(!mapper.irq_pending());
+    mapper.cpu_clock();
+    assert!(mapper.irq_pending());
+    write(&mut mapper, 0xd, 0x81);
+    assert!(!mapper.irq_pending());
+}
@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::flash::Flash;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+const NAMETABLE_BANK_SIZE: usize = 0x2000;
+
+/// gtrom / cheapocabra (mapper 111): 32k prg banks out of self-writable
+/// flash, two 8k banks of chr ram and two 8k banks of four screen nametable
+/// ram, all picked by one register at $5000-$5FFF and $7000-$7FFF
+pub struct GtRom {
+    flash: Flash,
+    chr_ram: Vec<u8>,
+    nametables: Vec<u8>,
+    /// bits 0-3 prg, 4 chr, 5 nametables, 6 and 7 the red and green leds,
+    /// which only live on in savestates
+    register: u8,
+}
+
+impl GtRom {
+    pub fn new(prg_rom: Vec<u8>) -> Self {
+        Self {
+            flash: Flash::new(prg_rom),
+            chr_ram: vec![0; CHR_BANK_SIZE * 2],
+            nametables: vec![0; NAMETABLE_BANK_SIZE * 2],
+            register: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.flash.data().len() / PRG_BANK_SIZE;
+        let bank = (self.register & 0xf) as usize % bank_count;
+        bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        ((self.register as usize >> 4) & 0x1) * CHR_BANK_SIZE + (address as usize & 0x1fff)
+    }
+
+    fn nametable_ram_offset(&self, address: u16) -> usize {
+        let bank = (self.register as usize >> 5) & 0x1;
+        bank * NAMETABLE_BANK_SIZE + (address as usize & 0x1fff)
+    }
+}
+
+impl Mapper for GtRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x5000..=0x5fff | 0x7000..=0x7fff => self.register = data,
+            0x8000..=0xffff => {
+                let offset = self.prg_offset(address);
+                self.flash.write(offset, data);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr_ram[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        let offset = self.chr_offset(address);
+        self.chr_ram[offset] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.flash.read(self.prg_offset(address)),
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        MirrorType::FourScreen
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        Some(self.nametables[self.nametable_ram_offset(address)])
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        let offset = self.nametable_ram_offset(address);
+        self.nametables[offset] = data;
+        true
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(self.flash.data()))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(self.flash.data_mut(), data);
+    }
+
+    /// the prg flash is always writable, no battery bit needed
+    fn nonvolatile(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.flash.save_state(state);
+        state.write_bytes(&self.chr_ram);
+        state.write_bytes(&self.nametables);
+        state.write_u8(self.register);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.flash.load_state(state)?;
+        state.read_bytes(&mut self.chr_ram)?;
+        state.read_bytes(&mut self.nametables)?;
+        self.register = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_gtrom() {
+    let prg = (0..16)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect::<Vec<u8>>();
+    let mut mapper = GtRom::new(prg);
+    mapper.cpu_write(0x5000, 0x03);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xffff), 3);
+
+    mapper.ppu_write(0x0000, 0x11);
+    mapper.nametable_write(0x2000, 0x22);
+    mapper.cpu_write(0x7000, 0xb3);
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+    assert_eq!(mapper.nametable_read(0x2000), Some(0));
+    assert_eq!(mapper.register >> 6, 0x2);
+    mapper.cpu_write(0x5000, 0x03);
+    assert_eq!(mapper.ppu_read(0x0000), 0x11);
+    assert_eq!(mapper.nametable_read(0x2000), Some(0x22));
+}
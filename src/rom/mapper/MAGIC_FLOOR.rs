@@ -0,0 +1,112 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+/// mapper 218 (magic floor): no chr chip at all, the pattern tables come out
+/// of the console's 2k of ciram. the header's mirroring bits pick which ppu
+/// address line drives ciram a10, so the pattern tables and nametables share
+/// the same two 1k pages
+pub struct MagicFloor {
+    prg_rom: Vec<u8>,
+    ciram: [u8; 0x800],
+    /// header byte 6 bits 0 and 3
+    mirror_bits: u8,
+}
+
+impl MagicFloor {
+    pub fn new(prg_rom: Vec<u8>, mirror_bits: u8) -> Self {
+        Self {
+            prg_rom,
+            ciram: [0; 0x800],
+            mirror_bits,
+        }
+    }
+
+    fn ciram_offset(&self, address: u16) -> usize {
+        let line = match self.mirror_bits {
+            0b0000 => 11,
+            0b0001 => 10,
+            0b1000 => 13,
+            // a14 is always low below $4000, so everything sits on page 0
+            _ => 14,
+        };
+        ((address as usize >> line) & 0x1) * 0x400 + (address as usize & 0x3ff)
+    }
+}
+
+impl Mapper for MagicFloor {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, _address: u16, _data: u8) {}
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.ciram[self.ciram_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        let offset = self.ciram_offset(address);
+        self.ciram[offset] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.prg_rom[(address as usize - 0x8000) % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        match self.mirror_bits {
+            0b0000 => MirrorType::Horizontal,
+            0b0001 => MirrorType::Vertical,
+            // pattern tables sit on page 0, $2000-$3EFF sees page 1
+            0b1000 => MirrorType::SingleScreenUpper,
+            _ => MirrorType::SingleScreenLower,
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        Some(self.ciram[self.ciram_offset(address)])
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        let offset = self.ciram_offset(address);
+        self.ciram[offset] = data;
+        true
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ciram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.ciram)
+    }
+}
+
+#[test]
+fn test_magic_floor() {
+    // vertical: patterns at $0000 and $0800 share a page
+    let mut mapper = MagicFloor::new(vec![0; 0x4000], 0b0001);
+    mapper.ppu_write(0x0005, 0x11);
+    assert_eq!(mapper.ppu_read(0x0805), 0x11);
+    assert_eq!(mapper.nametable_read(0x2005), Some(0x11));
+    assert_eq!(mapper.ppu_read(0x0405), 0);
+
+    // one screen a: patterns on page 0, nametables on page 1
+    let mut mapper = MagicFloor::new(vec![0; 0x4000], 0b1000);
+    mapper.ppu_write(0x1405, 0x22);
+    assert_eq!(mapper.ppu_read(0x0005), 0x22);
+    assert_eq!(mapper.nametable_read(0x2005), Some(0));
+    mapper.nametable_write(0x2c05, 0x33);
+    assert_eq!(mapper.nametable_read(0x2005), Some(0x33));
+
+    // one screen b: patterns and nametables all on page 0
+    let mut mapper = MagicFloor::new(vec![0; 0x4000], 0b1001);
+    mapper.ppu_write(0x1405, 0x44);
+    assert_eq!(mapper.ppu_read(0x0005), 0x44);
+    assert_eq!(mapper.nametable_read(0x2c05), Some(0x44));
+}
@@ -0,0 +1,327 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+
+/// the boards with 8k of chr put the spare chr bank bits to other uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Board {
+    /// chr rom banking only
+    Standard,
+    /// bit 4 disables prg ram
+    Snrom,
+    /// bit 3 picks one of two 8k prg ram banks
+    Sorom,
+    /// bit 4 picks the 256k half of 512k prg
+    Surom,
+    /// SUROM plus bits 2-3 picking one of four 8k prg ram banks
+    Sxrom,
+}
+
+/// nintendo mmc1 (mapper 1): registers are loaded through a 5-bit serial
+/// shift register, writes with bit 7 set reset it. the second of two writes
+/// on consecutive cycles (read-modify-write instructions) is ignored
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    board: Board,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+    cycles_since_write: u8,
+}
+
+impl Mmc1 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        prg_ram_size: usize,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        let board = if chr.len() > 0x2000 {
+            Board::Standard
+        } else if prg_ram_size >= 0x8000 {
+            Board::Sxrom
+        } else if prg_ram_size >= 0x4000 {
+            Board::Sorom
+        } else if prg_rom.len() > 0x40000 {
+            Board::Surom
+        } else {
+            Board::Snrom
+        };
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: vec![0; prg_ram_size.max(PRG_RAM_BANK_SIZE)],
+            board,
+            shift: 0,
+            shift_count: 0,
+            // powers on with the last bank fixed at $C000
+            control: 0x0c,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+            cycles_since_write: u8::MAX,
+        }
+    }
+
+    fn write_serial(&mut self, address: u16, data: u8) {
+        let consecutive = self.cycles_since_write == 0;
+        self.cycles_since_write = 0;
+        if consecutive {
+            return;
+        }
+        if data & 0x80 != 0 {
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= 0x0c;
+            return;
+        }
+        self.shift |= (data & 0x1) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count < 5 {
+            return;
+        }
+        let val = self.shift;
+        self.shift = 0;
+        self.shift_count = 0;
+        match address {
+            0x8000..=0x9fff => self.control = val,
+            0xa000..=0xbfff => self.chr_banks[0] = val,
+            0xc000..=0xdfff => self.chr_banks[1] = val,
+            _ => self.prg_bank = val,
+        }
+    }
+
+    fn ram_enabled(&self) -> bool {
+        let disabled = self.prg_bank & 0x10 != 0
+            || (self.board == Board::Snrom && self.chr_banks[0] & 0x10 != 0);
+        !disabled
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let outer = match self.board {
+            Board::Surom | Board::Sxrom => (self.chr_banks[0] & 0x10) as usize,
+            _ => 0,
+        };
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let high = address >= 0xc000;
+        let bank = match (self.control >> 2) & 0x3 {
+            0 | 1 => (bank & 0x0e) | high as usize,
+            2 if high => bank,
+            2 => 0,
+            _ if high => 0x0f,
+            _ => bank,
+        };
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        ((outer | bank) % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn prg_ram_offset(&self, address: u16) -> usize {
+        let bank = match self.board {
+            Board::Sorom => (self.chr_banks[0] >> 3) & 0x1,
+            Board::Sxrom => (self.chr_banks[0] >> 2) & 0x3,
+            _ => 0,
+        } as usize;
+        let bank_count = self.prg_ram.len() / PRG_RAM_BANK_SIZE;
+        (bank % bank_count) * PRG_RAM_BANK_SIZE + (address as usize & (PRG_RAM_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let high = address >= 0x1000;
+        let bank = if self.control & 0x10 != 0 {
+            self.chr_banks[high as usize]
+        } else {
+            (self.chr_banks[0] & 0x1e) | high as u8
+        } as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled() => {
+                let offset = self.prg_ram_offset(address);
+                self.prg_ram[offset] = data;
+            }
+            0x8000..=0xffff => self.write_serial(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled() => self.prg_ram[self.prg_ram_offset(address)],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        match self.control & 0x3 {
+            0 => MirrorType::SingleScreenLower,
+            1 => MirrorType::SingleScreenUpper,
+            2 => MirrorType::Vertical,
+            _ => MirrorType::Horizontal,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.cycles_since_write = self.cycles_since_write.saturating_add(1);
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.shift);
+        state.write_u8(self.shift_count);
+        state.write_u8(self.control);
+        state.write_bytes(&self.chr_banks);
+        state.write_u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.shift = state.read_u8()?;
+        self.shift_count = state.read_u8()?;
+        self.control = state.read_u8()?;
+        state.read_bytes(&mut self.chr_banks)?;
+        self.prg_bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+/// five serial writes, a cycle apart like separate sta instructions
+#[cfg(test)]
+fn write_register(mapper: &mut Mmc1, address: u16, val: u8) {
+    for n in 0..5 {
+        mapper.cpu_write(address, (val >> n) & 0x1);
+        mapper.cpu_clock();
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_prg_modes() {
+    let mut mapper = Mmc1::new(
+        banked(16, PRG_BANK_SIZE),
+        banked(16, CHR_BANK_SIZE),
+        0x2000,
+        0x2000,
+    );
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+    write_register(&mut mapper, 0xe000, 5);
+    assert_eq!(mapper.cpu_read(0x8000), 5);
+
+    // fix the first bank, switch $C000
+    write_register(&mut mapper, 0x8000, 0b01000);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.cpu_read(0xc000), 5);
+    // 32k mode drops the low bit
+    write_register(&mut mapper, 0x8000, 0b00010);
+    assert_eq!(mapper.cpu_read(0x8000), 4);
+    assert_eq!(mapper.cpu_read(0xc000), 5);
+    assert_eq!(mapper.mirroring(), MirrorType::Vertical);
+
+    // a reset write puts the last bank back at $C000
+    mapper.cpu_write(0x8000, 0x80);
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+}
+
+#[test]
+fn test_chr_modes() {
+    let mut mapper = Mmc1::new(
+        banked(2, PRG_BANK_SIZE),
+        banked(16, CHR_BANK_SIZE),
+        0x2000,
+        0x2000,
+    );
+    write_register(&mut mapper, 0xa000, 7);
+    write_register(&mut mapper, 0xc000, 9);
+    assert_eq!(mapper.ppu_read(0x0000), 6);
+    assert_eq!(mapper.ppu_read(0x1000), 7);
+    write_register(&mut mapper, 0x8000, 0b11100);
+    assert_eq!(mapper.ppu_read(0x0000), 7);
+    assert_eq!(mapper.ppu_read(0x1000), 9);
+}
+
+#[test]
+fn test_consecutive_writes() {
+    let mut mapper = Mmc1::new(banked(16, PRG_BANK_SIZE), vec![], 0x2000, 0x2000);
+    // an inc on a register writes twice in a row, only the first counts
+    for _ in 0..5 {
+        mapper.cpu_write(0xe000, 1);
+        mapper.cpu_write(0xe000, 0);
+        mapper.cpu_clock();
+    }
+    write_register(&mut mapper, 0x8000, 0b01000);
+    assert_eq!(mapper.cpu_read(0xc000), 15);
+}
+
+#[test]
+fn test_sxrom() {
+    let mut mapper = Mmc1::new(banked(32, PRG_BANK_SIZE), vec![], 0x2000, 0x8000);
+    // chr bank bit 4 selects the upper 256k, bits 2-3 the ram bank
+    write_register(&mut mapper, 0xa000, 0b11000);
+    assert_eq!(mapper.cpu_read(0xc000), 31);
+    write_register(&mut mapper, 0xe000, 1);
+    assert_eq!(mapper.cpu_read(0x8000), 17);
+    mapper.cpu_write(0x6000, 0xaa);
+    write_register(&mut mapper, 0xa000, 0b10000);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+    write_register(&mut mapper, 0xa000, 0b11000);
+    assert_eq!(mapper.cpu_read(0x6000), 0xaa);
+    assert_eq!(mapper.save_ram().unwrap()[0x4000], 0xaa);
+
+    // prg bank bit 4 disables the ram
+    write_register(&mut mapper, 0xe000, 0x11);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+}
@@ -0,0 +1,238 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const CHR_BANK_SIZE: usize = 0x1000;
+const LATCH_FD: u8 = 0xfd;
+const LATCH_FE: u8 = 0xfe;
+
+/// nintendo mmc2 (mapper 9) and mmc4 (mapper 10): each 4k half of the
+/// pattern tables has an $FD and an $FE bank, and a latch flips between them
+/// when the ppu fetches tile $FD or $FE from that half. the mmc2 switches 8k
+/// of prg, the mmc4 16k and adds 8k of prg ram
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    mmc4: bool,
+    prg_bank: u8,
+    /// [$FD, $FE] banks for each half
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    mirroring: MirrorType,
+}
+
+impl Mmc2 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+        mmc4: bool,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            mmc4,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE; 2],
+            mirroring,
+        }
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        if self.mmc4 {
+            0x4000
+        } else {
+            0x2000
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let size = self.prg_bank_size();
+        let bank_count = self.prg_rom.len() / size;
+        let slot = (address as usize - 0x8000) / size;
+        // only the first window switches, the rest hold the last banks
+        let bank = match slot {
+            0 => self.prg_bank as usize,
+            _ => bank_count - (0x8000 / size - slot),
+        };
+        (bank % bank_count) * size + (address as usize & (size - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let half = (address as usize >> 12) & 0x1;
+        let latch = (self.latches[half] - LATCH_FD) as usize;
+        let bank = self.chr_banks[half][latch] as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    /// the latch flips after the fetch, so the tile itself still comes from
+    /// the old bank
+    fn update_latch(&mut self, address: u16) {
+        match address & 0x1ff8 {
+            0x0fd8 if self.mmc4 || address == 0x0fd8 => self.latches[0] = LATCH_FD,
+            0x0fe8 if self.mmc4 || address == 0x0fe8 => self.latches[0] = LATCH_FE,
+            0x1fd8 => self.latches[1] = LATCH_FD,
+            0x1fe8 => self.latches[1] = LATCH_FE,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.mmc4 => self.prg_ram[address as usize - 0x6000] = data,
+            0xa000..=0xafff => self.prg_bank = data & 0x0f,
+            0xb000..=0xefff => {
+                let reg = (address as usize - 0xb000) >> 12;
+                self.chr_banks[reg >> 1][reg & 0x1] = data & 0x1f;
+            }
+            0xf000..=0xffff => {
+                self.mirroring = if data & 0x1 != 0 {
+                    MirrorType::Horizontal
+                } else {
+                    MirrorType::Vertical
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let val = self.chr[self.chr_offset(address)];
+        self.update_latch(address);
+        val
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.mmc4 => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        self.mmc4.then(|| Cow::Borrowed(&self.prg_ram[..]))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.prg_bank);
+        state.write_bytes(&self.chr_banks.concat());
+        state.write_bytes(&self.latches);
+        state.write_u8(self.mirroring as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.prg_bank = state.read_u8()?;
+        let mut chr_banks = [0u8; 4];
+        state.read_bytes(&mut chr_banks)?;
+        self.chr_banks = [[chr_banks[0], chr_banks[1]], [chr_banks[2], chr_banks[3]]];
+        state.read_bytes(&mut self.latches)?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_mmc2() {
+    let mut mapper = Mmc2::new(
+        banked(16, 0x2000),
+        banked(32, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        false,
+    );
+    mapper.cpu_write(0xa000, 5);
+    let prg = [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.cpu_read(address));
+    assert_eq!(prg, [5, 13, 14, 15]);
+
+    for (reg, bank) in [1, 2, 3, 4].iter().enumerate() {
+        mapper.cpu_write(0xb000 + 0x1000 * reg as u16, *bank);
+    }
+    // both latches power on at $FE
+    assert_eq!(mapper.ppu_read(0x0000), 2);
+    assert_eq!(mapper.ppu_read(0x1000), 4);
+
+    // tile $FD switches after the fetch that hit it
+    assert_eq!(mapper.ppu_read(0x0fd8), 2);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    // the mmc2 only latches on the exact address in the first half
+    mapper.ppu_read(0x0fe9);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    mapper.ppu_read(0x1fdd);
+    assert_eq!(mapper.ppu_read(0x1000), 3);
+
+    mapper.cpu_write(0xf000, 1);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+}
+
+#[test]
+fn test_mmc4() {
+    let mut mapper = Mmc2::new(
+        banked(8, 0x4000),
+        banked(32, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        true,
+    );
+    mapper.cpu_write(0xa000, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xc000), 7);
+
+    mapper.cpu_write(0xb000, 1);
+    mapper.cpu_write(0xc000, 2);
+    mapper.ppu_read(0x0fdf);
+    assert_eq!(mapper.ppu_read(0x0000), 1);
+    mapper.ppu_read(0x0fef);
+    assert_eq!(mapper.ppu_read(0x0000), 2);
+
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.save_ram().unwrap()[0], 0x55);
+}
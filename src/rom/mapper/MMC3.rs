@@ -1,17 +1,601 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
 const LAST_BANK: i8 = -1;
 const SECOND_LAST_BANK: i8 = -2;
 
+/// which register (or fixed bank) each 8k prg window uses, per prg mode
 const PRG_BANK_VALUE: [[i8; 4]; 2] = [
-    [6, 7, -2, -1],
-    [-2, 7, 6, -1]
+    [6, 7, SECOND_LAST_BANK, LAST_BANK],
+    [SECOND_LAST_BANK, 7, 6, LAST_BANK],
 ];
 
-const CHR_BANK_VALUE: [[i8; 8]; 2] = [
-    [0, 0, 1, 1, 2, 3, 4, 5],
-    [2, 3, 4, 5, 0, 0, 1, 1]
-];
+/// which register each 1k chr window uses, per chr inversion. R0 and R1
+/// are 2k banks and cover two windows each
+const CHR_BANK_VALUE: [[i8; 8]; 2] = [[0, 0, 1, 1, 2, 3, 4, 5], [2, 3, 4, 5, 0, 0, 1, 1]];
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+/// cpu cycles A12 has to stay low before a rise counts as a new scanline
+const A12_FILTER: u8 = 3;
+
+/// the two ways the irq counter treats a reload to 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqRevision {
+    /// mmc3b/c: fires every time the counter is 0 after being clocked
+    Sharp,
+    /// mmc3a: only fires when the counter got to 0 by counting down or by
+    /// an explicit reload
+    Nec,
+}
+
+/// boards built around the mmc3 banking core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Board {
+    Mmc3(IrqRevision),
+    /// 1k of ram inside the mapper at $7000-$7FFF, each 512 byte half with
+    /// its own read and write enable
+    Mmc6,
+    /// TKSROM/TLSROM (mapper 118): chr bank bit 7 drives ciram a10
+    TxSRom,
+    /// mapper 119: chr bank bit 6 picks 8k of chr ram over the chr rom
+    TqRom,
+    /// namco 108 / DxROM (mapper 206): $8000/$8001 only, no inversion, irq,
+    /// mirroring control or prg ram
+    Namco108,
+    /// mapper 76: four 2k chr banks from R2-R5
+    Namco3446,
+    /// mapper 88: R0/R1 bank the first 64k of chr, R2-R5 the second
+    Namco3433,
+    /// mapper 95: R0/R1 bit 5 drives ciram a10
+    Namco3425,
+    /// mapper 154: mapper 88 plus single screen mirroring from bit 6 of any
+    /// $8000-$FFFF write
+    Namco3453,
+}
+
+impl Board {
+    fn namco(&self) -> bool {
+        matches!(
+            self,
+            Board::Namco108
+                | Board::Namco3446
+                | Board::Namco3433
+                | Board::Namco3425
+                | Board::Namco3453
+        )
+    }
+}
+
+/// nintendo mmc3 (mapper 4): eight bank registers behind $8000/$8001, prg
+/// and chr inversion, mirroring and prg ram protect, and a scanline counter
+/// clocked by rising edges on ppu A12
+pub struct Mmc3 {
+    board: Board,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: Vec<u8>,
+    prg_ram: Vec<u8>,
+    regs: [u8; 8],
+    bank_select: u8,
+    mirroring: MirrorType,
+    four_screen: bool,
+    ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12: bool,
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+        board: Board,
+    ) -> Self {
+        let chr_ram = if chr_rom.is_empty() || board == Board::TqRom {
+            vec![0; chr_ram_size]
+        } else {
+            vec![]
+        };
+        let prg_ram = match board {
+            Board::Mmc6 => vec![0; 0x400],
+            _ if board.namco() => vec![],
+            _ => vec![0; 0x2000],
+        };
+        Self {
+            board,
+            prg_rom,
+            chr_rom,
+            chr_ram,
+            prg_ram,
+            regs: [0, 2, 4, 5, 6, 7, 0, 1],
+            bank_select: 0,
+            mirroring,
+            four_screen: mirroring == MirrorType::FourScreen,
+            ram_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let slot = (address as usize - 0x8000) / PRG_BANK_SIZE;
+        let bank = match PRG_BANK_VALUE[(self.bank_select >> 6) as usize & 0x1][slot] {
+            LAST_BANK => bank_count - 1,
+            SECOND_LAST_BANK => bank_count - 2,
+            reg => self.regs[reg as usize] as usize & 0x3f,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    /// the 1k bank in a ppu $0000-$1FFF window, with the bits some boards
+    /// use for other things still in it
+    fn chr_bank(&self, slot: usize) -> usize {
+        if self.board == Board::Namco3446 {
+            return ((self.regs[2 + slot / 2] as usize) << 1) | (slot & 0x1);
+        }
+        let reg = CHR_BANK_VALUE[(self.bank_select >> 7) as usize][slot] as usize;
+        let bank = if reg < 2 {
+            (self.regs[reg] & 0xfe) as usize | (slot & 0x1)
+        } else {
+            self.regs[reg] as usize
+        };
+        match self.board {
+            Board::Namco3433 | Board::Namco3453 => (bank & 0x3f) | (slot & 0x4) << 4,
+            _ => bank,
+        }
+    }
+
+    /// whether a ppu address lands in chr ram, and where
+    fn chr_offset(&self, address: u16) -> (bool, usize) {
+        let bank = self.chr_bank((address as usize & 0x1fff) / CHR_BANK_SIZE);
+        let ram = self.chr_rom.is_empty() || (self.board == Board::TqRom && bank & 0x40 != 0);
+        let bank_count = if ram {
+            self.chr_ram.len()
+        } else {
+            self.chr_rom.len()
+        } / CHR_BANK_SIZE;
+        let offset = (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1));
+        (ram, offset)
+    }
+
+    fn ram_readable(&self) -> bool {
+        self.ram_protect & 0x80 != 0
+    }
+
+    fn ram_writable(&self) -> bool {
+        self.ram_protect & 0xc0 == 0x80
+    }
+
+    /// mmc6 $A001 has read (bits 4/6) and write (bits 5/7) enables for each
+    /// half, writes also need the half readable
+    fn mmc6_access(&self, address: u16) -> (bool, bool) {
+        let half = if address & 0x200 != 0 { 6 } else { 4 };
+        let readable = self.ram_protect & (1 << half) != 0;
+        let writable = readable && self.ram_protect & (2 << half) != 0;
+        (readable, writable)
+    }
+
+    fn mmc6_enabled(&self) -> bool {
+        self.bank_select & 0x20 != 0
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.board {
+            // neither half readable is open bus, only the other half reads 0
+            Board::Mmc6 if address >= 0x7000 && self.mmc6_enabled() => {
+                match self.mmc6_access(address) {
+                    (true, _) => self.prg_ram[address as usize & 0x3ff],
+                    _ => 0,
+                }
+            }
+            Board::Mmc6 => 0,
+            _ if self.ram_readable() && !self.prg_ram.is_empty() => {
+                self.prg_ram[address as usize - 0x6000]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, data: u8) {
+        let writable = match self.board {
+            Board::Mmc6 => address >= 0x7000 && self.mmc6_enabled() && self.mmc6_access(address).1,
+            _ => self.ram_writable() && !self.prg_ram.is_empty(),
+        };
+        if writable {
+            let offset = (address as usize - 0x6000) % self.prg_ram.len();
+            self.prg_ram[offset] = data;
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        if self.board == Board::Namco3453 {
+            self.mirroring = if data & 0x40 != 0 {
+                MirrorType::SingleScreenUpper
+            } else {
+                MirrorType::SingleScreenLower
+            };
+        }
+        match address & 0xe001 {
+            0x8000 if self.board.namco() => self.bank_select = data & 0x7,
+            0x8000 => {
+                // turning the mmc6 ram off also drops its protect bits
+                if self.board == Board::Mmc6 && data & 0x20 == 0 {
+                    self.ram_protect = 0;
+                }
+                self.bank_select = data;
+            }
+            0x8001 => self.regs[self.bank_select as usize & 0x7] = data,
+            _ if self.board.namco() => {}
+            0xa000 if !self.four_screen => {
+                self.mirroring = if data & 0x1 != 0 {
+                    MirrorType::Horizontal
+                } else {
+                    MirrorType::Vertical
+                };
+            }
+            0xa001 if self.board == Board::Mmc6 && !self.mmc6_enabled() => {}
+            0xa001 => self.ram_protect = data,
+            0xc000 => self.irq_latch = data,
+            0xc001 => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xe000 => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xe001 => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    /// every ppu access goes past here, a rise on A12 after it was low long
+    /// enough clocks the counter
+    fn watch_a12(&mut self, address: u16) {
+        let a12 = address & 0x1000 != 0;
+        if a12 && !self.a12 && self.a12_low_cycles >= A12_FILTER {
+            self.clock_counter();
+        }
+        if a12 {
+            self.a12_low_cycles = 0;
+        }
+        self.a12 = a12;
+    }
+
+    fn clock_counter(&mut self) {
+        let previous = self.irq_counter;
+        let reloaded = self.irq_counter == 0 || self.irq_reload;
+        if reloaded {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let fire = match self.board {
+            Board::Mmc3(IrqRevision::Nec) => {
+                self.irq_counter == 0 && (previous != 0 || self.irq_reload)
+            }
+            _ => self.irq_counter == 0,
+        };
+        self.irq_reload = false;
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => self.write_ram(address, data),
+            0x8000..=0xffff => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.watch_a12(address);
+        match self.chr_offset(address) {
+            (true, offset) => self.chr_ram[offset],
+            (false, offset) => self.chr_rom[offset],
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        self.watch_a12(address);
+        if let (true, offset) = self.chr_offset(address) {
+            self.chr_ram[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => self.read_ram(address),
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn nametable_offset(&self, address: u16) -> usize {
+        let table = (address as usize >> 10) & 0x3;
+        let page = match self.board {
+            Board::TxSRom => self.chr_bank(table) >> 7,
+            Board::Namco3425 => (self.chr_bank(table) >> 5) & 0x1,
+            _ => return self.mirroring.nametable_offset(address),
+        };
+        page * 0x400 + (address as usize & 0x3ff)
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        if self.prg_ram.is_empty() {
+            None
+        } else {
+            Some(Cow::Borrowed(&self.prg_ram))
+        }
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        state.write_bytes(&self.chr_ram);
+        state.write_bytes(&self.regs);
+        state.write_u8(self.bank_select);
+        state.write_u8(self.mirroring as u8);
+        state.write_u8(self.ram_protect);
+        state.write_u8(self.irq_latch);
+        state.write_u8(self.irq_counter);
+        state.write_bool(self.irq_reload);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+        state.write_bool(self.a12);
+        state.write_u8(self.a12_low_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        state.read_bytes(&mut self.chr_ram)?;
+        state.read_bytes(&mut self.regs)?;
+        self.bank_select = state.read_u8()?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.ram_protect = state.read_u8()?;
+        self.irq_latch = state.read_u8()?;
+        self.irq_counter = state.read_u8()?;
+        self.irq_reload = state.read_bool()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        self.a12 = state.read_bool()?;
+        self.a12_low_cycles = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+/// what the ppu does to A12 over one rendered scanline with background
+/// tiles from $0000 and sprites from $1000
+#[cfg(test)]
+fn scanline(mapper: &mut Mmc3) {
+    mapper.ppu_read(0x0000);
+    for _ in 0..100 {
+        mapper.cpu_clock();
+    }
+    mapper.ppu_read(0x1000);
+    mapper.ppu_read(0x1008);
+}
+
+#[test]
+fn test_banking() {
+    let mut mapper = Mmc3::new(
+        banked(16, PRG_BANK_SIZE),
+        banked(64, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        Board::Mmc3(IrqRevision::Sharp),
+    );
+    for (reg, bank) in [9, 20, 30, 31, 32, 33, 3, 4].iter().enumerate() {
+        mapper.cpu_write(0x8000, reg as u8);
+        mapper.cpu_write(0x8001, *bank);
+    }
+    let prg = [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.cpu_read(address));
+    assert_eq!(prg, [3, 4, 14, 15]);
+    let chr = [0, 1, 2, 3, 4, 5, 6, 7].map(|slot| mapper.ppu_read(slot * 0x400));
+    assert_eq!(chr, [8, 9, 20, 21, 30, 31, 32, 33]);
+
+    // both inversions
+    mapper.cpu_write(0x8000, 0xc0);
+    let prg = [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.cpu_read(address));
+    assert_eq!(prg, [14, 4, 3, 15]);
+    let chr = [0, 1, 2, 3, 4, 5, 6, 7].map(|slot| mapper.ppu_read(slot * 0x400));
+    assert_eq!(chr, [30, 31, 32, 33, 8, 9, 20, 21]);
+
+    mapper.cpu_write(0xa000, 1);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+
+    // prg ram is off until enabled, and can be write protected
+    mapper.cpu_write(0x6000, 0x55);
+    mapper.cpu_write(0xa001, 0x80);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+    mapper.cpu_write(0x6000, 0x55);
+    mapper.cpu_write(0xa001, 0xc0);
+    mapper.cpu_write(0x6000, 0xaa);
+    assert_eq!(mapper.cpu_read(0x6000), 0x55);
+}
+
+#[test]
+fn test_scanline_irq() {
+    for revision in [IrqRevision::Sharp, IrqRevision::Nec] {
+        let mut mapper = Mmc3::new(
+            banked(4, PRG_BANK_SIZE),
+            vec![],
+            0x2000,
+            MirrorType::Vertical,
+            Board::Mmc3(revision),
+        );
+        mapper.cpu_write(0xc000, 3);
+        mapper.cpu_write(0xc001, 0);
+        mapper.cpu_write(0xe001, 0);
+        // reload to 3, then 2, 1, 0
+        for _ in 0..3 {
+            scanline(&mut mapper);
+            assert!(!mapper.irq_pending());
+        }
+        scanline(&mut mapper);
+        assert!(mapper.irq_pending());
+        mapper.cpu_write(0xe000, 0);
+        assert!(!mapper.irq_pending());
+
+        // sprite fetches right after each other are one edge
+        mapper.ppu_read(0x1000);
+        mapper.ppu_read(0x0000);
+        mapper.ppu_read(0x1000);
+        mapper.cpu_write(0xe001, 0);
+        // a latch of 0 fires every line on sharp chips and never on nec ones
+        mapper.cpu_write(0xc000, 0);
+        mapper.cpu_write(0xc001, 0);
+        scanline(&mut mapper);
+        mapper.cpu_write(0xe000, 0);
+        mapper.cpu_write(0xe001, 0);
+        scanline(&mut mapper);
+        assert_eq!(mapper.irq_pending(), revision == IrqRevision::Sharp);
+    }
+}
+
+#[test]
+fn test_mmc6_ram() {
+    let mut mapper = Mmc3::new(
+        banked(4, PRG_BANK_SIZE),
+        banked(8, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        Board::Mmc6,
+    );
+    // protect bits can't be set while the ram is off
+    mapper.cpu_write(0xa001, 0xf0);
+    mapper.cpu_write(0x7000, 0x11);
+    assert_eq!(mapper.cpu_read(0x7000), 0);
+
+    mapper.cpu_write(0x8000, 0x20);
+    mapper.cpu_write(0xa001, 0xf0);
+    mapper.cpu_write(0x7000, 0x11);
+    mapper.cpu_write(0x7200, 0x22);
+    // 1k mirrored through $7000-$7FFF
+    assert_eq!(mapper.cpu_read(0x7c00), 0x11);
+    assert_eq!(mapper.cpu_read(0x7e00), 0x22);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+
+    // upper half readable only, the lower reads back 0
+    mapper.cpu_write(0xa001, 0x40);
+    mapper.cpu_write(0x7200, 0x33);
+    assert_eq!(mapper.cpu_read(0x7000), 0);
+    assert_eq!(mapper.cpu_read(0x7200), 0x22);
+}
+
+#[test]
+fn test_txsrom_tqrom() {
+    let mut mapper = Mmc3::new(
+        banked(4, PRG_BANK_SIZE),
+        banked(128, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        Board::TxSRom,
+    );
+    // R0 bit 7 for $2000/$2400, R1 for $2800/$2C00
+    mapper.cpu_write(0x8000, 0);
+    mapper.cpu_write(0x8001, 0x80);
+    mapper.cpu_write(0x8000, 1);
+    mapper.cpu_write(0x8001, 0x02);
+    mapper.cpu_write(0xa000, 1);
+    let pages = [0x2000, 0x2400, 0x2800, 0x2c00].map(|address| mapper.nametable_offset(address));
+    assert_eq!(pages, [0x400, 0x400, 0, 0]);
+
+    let mut mapper = Mmc3::new(
+        banked(4, PRG_BANK_SIZE),
+        banked(64, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Vertical,
+        Board::TqRom,
+    );
+    mapper.cpu_write(0x8000, 2);
+    mapper.cpu_write(0x8001, 0x05);
+    mapper.cpu_write(0x8000, 3);
+    mapper.cpu_write(0x8001, 0x41);
+    mapper.ppu_write(0x1000, 0xaa);
+    mapper.ppu_write(0x1400, 0xbb);
+    assert_eq!(mapper.ppu_read(0x1000), 5);
+    assert_eq!(mapper.ppu_read(0x1400), 0xbb);
+}
+
+#[test]
+fn test_namco() {
+    let mut mapper = Mmc3::new(
+        banked(8, PRG_BANK_SIZE),
+        banked(64, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Horizontal,
+        Board::Namco3446,
+    );
+    // no inversion, and $A000-$FFFF does nothing
+    mapper.cpu_write(0x8000, 0xc2);
+    mapper.cpu_write(0x8001, 3);
+    mapper.cpu_write(0xa000, 0);
+    mapper.cpu_write(0xa001, 0x80);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.ppu_read(0x0000), 6);
+    assert_eq!(mapper.ppu_read(0x0400), 7);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+    assert!(mapper.save_ram().is_none());
 
-pub struct MMC3 {
-    regs: [i8; 8],
-    
-}
\ No newline at end of file
+    let mut mapper = Mmc3::new(
+        banked(8, PRG_BANK_SIZE),
+        banked(128, CHR_BANK_SIZE),
+        0x2000,
+        MirrorType::Horizontal,
+        Board::Namco3453,
+    );
+    mapper.cpu_write(0x8000, 0x42);
+    mapper.cpu_write(0x8001, 0x43);
+    assert_eq!(mapper.ppu_read(0x1000), 0x43);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenUpper);
+    mapper.cpu_write(0xe000, 0);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenLower);
+}
@@ -0,0 +1,691 @@
+use std::borrow::Cow;
+
+use crate::apu::expansion::{ExpansionAudio, Mmc5Audio};
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+/// ppu reads in a rendered scanline, counted from the first background
+/// nametable fetch: 32 tiles, then 8 sprites, then 2 tiles for the next line
+const SPRITE_FETCHES: std::ops::Range<u16> = 128..160;
+const PREFETCHES: std::ops::Range<u16> = 160..168;
+/// cpu cycles without a ppu read before the mmc5 decides rendering stopped
+const IDLE_CYCLES: u8 = 3;
+
+/// nintendo mmc5 (mapper 5): prg in 8k to 32k banks with ram mappable into
+/// rom space, separate sprite and background chr banks for 8x16 sprites,
+/// 1k of exram used as a nametable, extended attributes or plain ram,
+/// fill mode, a vertical split, a scanline irq worked out from the ppu's
+/// fetch pattern, a multiplier and a sound chip
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    exram: [u8; 0x400],
+    audio: Mmc5Audio,
+    prg_mode: u8,
+    chr_mode: u8,
+    ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametables: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// $5113-$5117
+    prg_banks: [u8; 5],
+    /// $5120-$5127 for sprites, then $5128-$512B for backgrounds
+    chr_banks: [u16; 12],
+    chr_upper: u8,
+    /// which set $2007 goes through while the ppu isn't rendering
+    last_chr_background: bool,
+    split: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    multiplicand: u8,
+    multiplier: u8,
+    tall_sprites: bool,
+    in_frame: bool,
+    scanline: u8,
+    idle_cycles: u8,
+    last_fetch: u16,
+    same_fetches: u8,
+    fetches: u16,
+    in_split: bool,
+    split_tile: u16,
+    ext_attribute: u8,
+}
+
+impl Mmc5 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        prg_ram_size: usize,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: vec![0; prg_ram_size.max(PRG_BANK_SIZE)],
+            exram: [0; 0x400],
+            audio: Mmc5Audio::default(),
+            prg_mode: 3,
+            chr_mode: 0,
+            ram_protect: [0; 2],
+            exram_mode: 0,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xff],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            last_chr_background: false,
+            split: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            tall_sprites: false,
+            in_frame: false,
+            scanline: 0,
+            idle_cycles: 0,
+            last_fetch: 0,
+            same_fetches: 0,
+            fetches: u16::MAX,
+            in_split: false,
+            split_tile: 0,
+            ext_attribute: 0,
+        }
+    }
+
+    /// the register behind a $6000-$FFFF window in the current prg mode, and
+    /// the 8k bank it picks. bit 7 of the bank set means rom
+    fn prg_bank(&self, address: u16) -> u8 {
+        let slot = (address as usize - 0x6000) / PRG_BANK_SIZE;
+        if slot == 0 {
+            return self.prg_banks[0] & 0x7f;
+        }
+        let slot = slot - 1;
+        let (reg, size) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+            (1 | 2, 0 | 1) => (2, 2),
+            (1, _) => (4, 2),
+            _ => (slot + 1, 1),
+        };
+        let bank = self.prg_banks[reg];
+        // $5117 is always rom
+        let rom = if reg == 4 { 0x80 } else { bank & 0x80 };
+        rom | ((bank & 0x7f & !(size as u8 - 1)) | (slot as u8 & (size as u8 - 1)))
+    }
+
+    fn prg_ram_offset(&self, bank: u8, address: u16) -> usize {
+        ((bank as usize & 0x7) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+            % self.prg_ram.len()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.ram_protect[0] & 0x3 == 0x2 && self.ram_protect[1] & 0x3 == 0x1
+    }
+
+    fn read_prg(&self, address: u16) -> u8 {
+        let bank = self.prg_bank(address);
+        if address >= 0x8000 && bank & 0x80 != 0 {
+            let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+            let bank = (bank & 0x7f) as usize % bank_count;
+            self.prg_rom[bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))]
+        } else {
+            self.prg_ram[self.prg_ram_offset(bank, address)]
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, data: u8) {
+        let bank = self.prg_bank(address);
+        if (address < 0x8000 || bank & 0x80 == 0) && self.prg_ram_writable() {
+            let offset = self.prg_ram_offset(bank, address);
+            self.prg_ram[offset] = data;
+        }
+    }
+
+    /// the ppu is in the sprite part of a rendered scanline
+    fn sprite_fetch(&self) -> bool {
+        self.in_frame && SPRITE_FETCHES.contains(&self.fetches)
+    }
+
+    /// which background tile of which line the current fetch is for
+    fn background_tile(&self) -> Option<(u16, u8)> {
+        if !self.in_frame {
+            None
+        } else if self.fetches < SPRITE_FETCHES.start {
+            Some((self.fetches / 4 + 2, self.scanline))
+        } else if PREFETCHES.contains(&self.fetches) {
+            Some((
+                (self.fetches - PREFETCHES.start) / 4,
+                self.scanline.wrapping_add(1),
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn split_y(&self) -> usize {
+        let line = self
+            .background_tile()
+            .map_or(self.scanline, |(_, line)| line);
+        (self.split_scroll as usize + line as usize) % 240
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let background = if !self.tall_sprites {
+            false
+        } else if self.in_frame {
+            !self.sprite_fetch()
+        } else {
+            self.last_chr_background
+        };
+        let size = 0x2000 >> self.chr_mode;
+        let address = if background && self.chr_mode != 0 {
+            address as usize & 0xfff
+        } else {
+            address as usize & 0x1fff
+        };
+        let reg = (address / size + 1) * (8 >> self.chr_mode) - 1;
+        let bank = if background {
+            self.chr_banks[8 + (reg & 0x3)]
+        } else {
+            self.chr_banks[reg]
+        } as usize;
+        (bank * size + (address % size)) % self.chr.len()
+    }
+
+    fn watch_fetch(&mut self, address: u16) {
+        self.idle_cycles = IDLE_CYCLES;
+        if address & 0x2000 != 0 && address == self.last_fetch {
+            self.same_fetches += 1;
+        } else {
+            self.same_fetches = 0;
+        }
+        self.last_fetch = address;
+        self.fetches = self.fetches.saturating_add(1);
+        // the ppu reads the same nametable byte three times around dot 0
+        if self.same_fetches == 2 {
+            self.fetches = 0;
+            if self.in_frame {
+                self.scanline = self.scanline.wrapping_add(1);
+                if self.scanline == self.irq_compare {
+                    self.irq_pending = true;
+                }
+            } else {
+                self.in_frame = true;
+                self.scanline = 0;
+            }
+        }
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.same_fetches = 0;
+        self.fetches = u16::MAX;
+    }
+
+    /// vertical split applies to this background tile
+    fn split_active(&self) -> bool {
+        if self.exram_mode > 1 || self.split & 0x80 == 0 {
+            return false;
+        }
+        match self.background_tile() {
+            Some((tile, _)) if tile < 32 => {
+                let delimiter = (self.split & 0x1f) as u16;
+                if self.split & 0x40 != 0 {
+                    tile >= delimiter
+                } else {
+                    tile < delimiter
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn read_register(&mut self, address: u16) -> u8 {
+        match address {
+            0x5204 => {
+                let val = self.peek(address);
+                self.irq_pending = false;
+                val
+            }
+            0x5010 | 0x5015 => self.audio.read(address).unwrap_or(0),
+            _ => self.peek(address),
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x5000..=0x5015 => self.audio.write(address, data),
+            0x5100 => self.prg_mode = data & 0x3,
+            0x5101 => self.chr_mode = data & 0x3,
+            0x5102 | 0x5103 => self.ram_protect[address as usize - 0x5102] = data,
+            0x5104 => self.exram_mode = data & 0x3,
+            0x5105 => self.nametables = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0x3,
+            0x5113..=0x5117 => self.prg_banks[address as usize - 0x5113] = data,
+            0x5120..=0x512b => {
+                let reg = address as usize - 0x5120;
+                self.chr_banks[reg] = data as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_background = reg >= 8;
+            }
+            0x5130 => self.chr_upper = data & 0x3,
+            0x5200 => self.split = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            // modes 0 and 1 only take writes while rendering
+            0x5c00..=0x5fff if self.exram_mode < 2 => {
+                self.exram[address as usize - 0x5c00] = if self.in_frame { data } else { 0 };
+            }
+            0x5c00..=0x5fff if self.exram_mode == 2 => self.exram[address as usize - 0x5c00] = data,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x5000..=0x5fff => self.read_register(address),
+            0x8000..=0xbfff => {
+                let val = self.read_prg(address);
+                self.audio.pcm_read(val);
+                val
+            }
+            _ => self.peek(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x5000..=0x5fff => self.write_register(address, data),
+            0x6000..=0xffff => self.write_prg(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.watch_fetch(address);
+        if self.in_split && self.background_tile().is_some() {
+            let bank_count = self.chr.len() / 0x1000;
+            let row = (address as usize & 0xff8) | (self.split_y() & 0x7);
+            return self.chr[(self.split_bank as usize % bank_count) * 0x1000 + row];
+        }
+        if self.exram_mode == 1 && self.background_tile().is_some() {
+            let bank = (self.ext_attribute & 0x3f) as usize | (self.chr_upper as usize) << 6;
+            let bank_count = self.chr.len() / 0x1000;
+            return self.chr[(bank % bank_count) * 0x1000 + (address as usize & 0xfff)];
+        }
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5c00..=0x5fff if self.exram_mode >= 2 => self.exram[address as usize - 0x5c00],
+            0x6000..=0xffff => self.read_prg(address),
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        match self.nametables {
+            0x44 => MirrorType::Vertical,
+            0x50 => MirrorType::Horizontal,
+            0x55 => MirrorType::SingleScreenUpper,
+            _ => MirrorType::SingleScreenLower,
+        }
+    }
+
+    fn nametable_offset(&self, address: u16) -> usize {
+        let table = (address as usize >> 10) & 0x3;
+        let page = (self.nametables as usize >> (table * 2)) & 0x1;
+        page * 0x400 + (address as usize & 0x3ff)
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        let address = 0x2000 | (address & 0xfff);
+        self.watch_fetch(address);
+        let offset = address as usize & 0x3ff;
+        let attribute = offset >= 0x3c0;
+        if !attribute {
+            self.in_split = self.split_active();
+            if self.in_split {
+                let (tile, _) = self.background_tile().unwrap_or_default();
+                self.split_tile = (self.split_y() as u16 / 8) * 32 + tile;
+                return Some(self.exram[self.split_tile as usize]);
+            }
+            if self.exram_mode == 1 && self.background_tile().is_some() {
+                self.ext_attribute = self.exram[offset];
+            }
+        } else if self.in_split {
+            let tile = self.split_tile as usize;
+            return Some(self.exram[0x3c0 | ((tile >> 4) & 0x38) | ((tile & 0x1f) >> 2)]);
+        } else if self.exram_mode == 1 && self.background_tile().is_some() {
+            return Some((self.ext_attribute >> 6) * 0x55);
+        }
+        let table = (address >> 10) & 0x3;
+        match (self.nametables >> (table * 2)) & 0x3 {
+            2 if self.exram_mode < 2 => Some(self.exram[offset]),
+            2 => Some(0),
+            3 if attribute => Some(self.fill_attribute * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        let table = (address >> 10) & 0x3;
+        match (self.nametables >> (table * 2)) & 0x3 {
+            2 => {
+                if self.exram_mode < 2 {
+                    self.exram[address as usize & 0x3ff] = data;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn ppu_register_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x2000 => self.tall_sprites = data & 0x20 != 0,
+            0x2001 if data & 0x18 == 0 => self.leave_frame(),
+            _ => {}
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || self.audio.irq_pending()
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.idle_cycles > 0 {
+            self.idle_cycles -= 1;
+            if self.idle_cycles == 0 {
+                self.leave_frame();
+            }
+        }
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.exram);
+        state.write_u8(self.prg_mode);
+        state.write_u8(self.chr_mode);
+        state.write_bytes(&self.ram_protect);
+        state.write_u8(self.exram_mode);
+        state.write_u8(self.nametables);
+        state.write_u8(self.fill_tile);
+        state.write_u8(self.fill_attribute);
+        state.write_bytes(&self.prg_banks);
+        for bank in self.chr_banks {
+            state.write_u16(bank);
+        }
+        state.write_u8(self.chr_upper);
+        state.write_bool(self.last_chr_background);
+        state.write_u8(self.split);
+        state.write_u8(self.split_scroll);
+        state.write_u8(self.split_bank);
+        state.write_u8(self.irq_compare);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+        state.write_u8(self.multiplicand);
+        state.write_u8(self.multiplier);
+        state.write_bool(self.tall_sprites);
+        state.write_bool(self.in_frame);
+        state.write_u8(self.scanline);
+        state.write_u8(self.idle_cycles);
+        state.write_u16(self.last_fetch);
+        state.write_u8(self.same_fetches);
+        state.write_u16(self.fetches);
+        state.write_bool(self.in_split);
+        state.write_u16(self.split_tile);
+        state.write_u8(self.ext_attribute);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.exram)?;
+        self.prg_mode = state.read_u8()?;
+        self.chr_mode = state.read_u8()?;
+        state.read_bytes(&mut self.ram_protect)?;
+        self.exram_mode = state.read_u8()?;
+        self.nametables = state.read_u8()?;
+        self.fill_tile = state.read_u8()?;
+        self.fill_attribute = state.read_u8()?;
+        state.read_bytes(&mut self.prg_banks)?;
+        for bank in self.chr_banks.iter_mut() {
+            *bank = state.read_u16()?;
+        }
+        self.chr_upper = state.read_u8()?;
+        self.last_chr_background = state.read_bool()?;
+        self.split = state.read_u8()?;
+        self.split_scroll = state.read_u8()?;
+        self.split_bank = state.read_u8()?;
+        self.irq_compare = state.read_u8()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        self.multiplicand = state.read_u8()?;
+        self.multiplier = state.read_u8()?;
+        self.tall_sprites = state.read_bool()?;
+        self.in_frame = state.read_bool()?;
+        self.scanline = state.read_u8()?;
+        self.idle_cycles = state.read_u8()?;
+        self.last_fetch = state.read_u16()?;
+        self.same_fetches = state.read_u8()?;
+        self.fetches = state.read_u16()?;
+        self.in_split = state.read_bool()?;
+        self.split_tile = state.read_u16()?;
+        self.ext_attribute = state.read_u8()?;
+        self.audio.load_state(state)
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+/// the ppu's reads over one rendered scanline, starting at dot 1. the last
+/// two nametable reads plus the first of the next line start a scanline
+#[cfg(test)]
+fn render_line(mapper: &mut Mmc5, pattern: &mut Vec<u8>) {
+    let mut tile = |mapper: &mut Mmc5, x: u16| {
+        mapper.nametable_read(0x2000 | (x & 0x1f));
+        mapper.nametable_read(0x23c0 | ((x & 0x1f) >> 2));
+        pattern.push(mapper.ppu_read(0x0000));
+        mapper.ppu_read(0x0008);
+    };
+    for x in 2..34 {
+        tile(mapper, x);
+    }
+    for _ in 0..8 {
+        mapper.nametable_read(0x2000);
+        mapper.nametable_read(0x2000);
+        mapper.ppu_read(0x1000);
+        mapper.ppu_read(0x1008);
+    }
+    for x in 0..2 {
+        tile(mapper, x);
+    }
+    mapper.nametable_read(0x2002);
+    mapper.nametable_read(0x2002);
+}
+
+#[test]
+fn test_prg_modes() {
+    let mut mapper = Mmc5::new(banked(16, PRG_BANK_SIZE), banked(8, 0x400), 0x2000, 0x10000);
+    let prg = |mapper: &Mmc5| [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.peek(address));
+    // powers on in 8k mode with the last bank at $E000
+    assert_eq!(mapper.peek(0xe000), 15);
+
+    for (reg, bank) in [0x81, 0x82, 0x83, 0x8d].iter().enumerate() {
+        mapper.cpu_write(0x5114 + reg as u16, *bank);
+    }
+    assert_eq!(prg(&mapper), [1, 2, 3, 13]);
+    mapper.cpu_write(0x5100, 0);
+    assert_eq!(prg(&mapper), [12, 13, 14, 15]);
+    mapper.cpu_write(0x5100, 1);
+    assert_eq!(prg(&mapper), [2, 3, 12, 13]);
+    mapper.cpu_write(0x5100, 2);
+    assert_eq!(prg(&mapper), [2, 3, 3, 13]);
+
+    // ram in rom space, writable only with both protect registers unlocked
+    mapper.cpu_write(0x5115, 0x02);
+    mapper.cpu_write(0x8000, 0x55);
+    assert_eq!(mapper.peek(0x8000), 0);
+    mapper.cpu_write(0x5102, 0x02);
+    mapper.cpu_write(0x5103, 0x01);
+    mapper.cpu_write(0x8000, 0x55);
+    mapper.cpu_write(0x5113, 0x02);
+    assert_eq!(mapper.peek(0x6000), 0x55);
+    assert_eq!(mapper.save_ram().unwrap()[0x4000], 0x55);
+
+    mapper.cpu_write(0x5205, 200);
+    mapper.cpu_write(0x5206, 100);
+    assert_eq!(mapper.peek(0x5205), (20000 & 0xff) as u8);
+    assert_eq!(mapper.peek(0x5206), (20000 >> 8) as u8);
+}
+
+#[test]
+fn test_scanline_irq_and_chr_sets() {
+    let mut mapper = Mmc5::new(banked(4, PRG_BANK_SIZE), banked(32, 0x400), 0x2000, 0x2000);
+    mapper.cpu_write(0x5101, 3);
+    for reg in 0..12 {
+        mapper.cpu_write(0x5120 + reg, reg as u8 + 8);
+    }
+    mapper.ppu_register_write(0x2000, 0x20);
+    mapper.cpu_write(0x5203, 2);
+    mapper.cpu_write(0x5204, 0x80);
+
+    // the pre-render line, then lines 0, 1 and 2
+    let mut pattern = vec![];
+    render_line(&mut mapper, &mut pattern);
+    assert!(!mapper.in_frame);
+    render_line(&mut mapper, &mut pattern);
+    assert!(mapper.in_frame);
+    render_line(&mut mapper, &mut pattern);
+    assert!(!mapper.irq_pending());
+    // backgrounds come from $5128 with 8x16 sprites
+    assert_eq!(pattern[pattern.len() - 1], 16);
+    render_line(&mut mapper, &mut pattern);
+    assert!(mapper.irq_pending());
+    assert_eq!(mapper.cpu_read(0x5204), 0xc0);
+    assert!(!mapper.irq_pending());
+
+    // sprite fetches in the middle of the line use $5120-$5127
+    mapper.fetches = SPRITE_FETCHES.start;
+    assert_eq!(mapper.chr[mapper.chr_offset(0x1000)], 12);
+
+    // the ppu going quiet ends the frame, $2007 then uses the last set written
+    for _ in 0..IDLE_CYCLES {
+        mapper.cpu_clock();
+    }
+    assert!(!mapper.in_frame);
+    assert_eq!(mapper.ppu_read(0x0400), 17);
+    mapper.cpu_write(0x5123, 3);
+    assert_eq!(mapper.ppu_read(0x0c00), 3);
+}
+
+#[test]
+fn test_exram_and_split() {
+    let mut mapper = Mmc5::new(banked(4, PRG_BANK_SIZE), banked(64, 0x400), 0x2000, 0x2000);
+    // exram as the second nametable, fill mode for the other two
+    mapper.cpu_write(0x5105, 0b1111_1000);
+    mapper.cpu_write(0x5106, 0x42);
+    mapper.cpu_write(0x5107, 0x2);
+    assert!(mapper.nametable_write(0x2405, 0x99));
+    assert_eq!(mapper.nametable_read(0x2405), Some(0x99));
+    assert_eq!(mapper.nametable_read(0x2800), Some(0x42));
+    assert_eq!(mapper.nametable_read(0x2bc0), Some(0xaa));
+    assert_eq!(mapper.nametable_read(0x2005), None);
+    assert_eq!(mapper.nametable_offset(0x2005), 0x005);
+
+    // extended attributes: the tile's exram byte picks palette and 4k bank
+    mapper.cpu_write(0x5105, 0);
+    mapper.cpu_write(0x5104, 2);
+    mapper.cpu_write(0x5c04, 0xc3);
+    mapper.cpu_write(0x5104, 1);
+    let mut pattern = vec![];
+    render_line(&mut mapper, &mut pattern);
+    render_line(&mut mapper, &mut pattern);
+    assert_eq!(pattern[pattern.len() - 34 + 2], 12);
+    assert_eq!(pattern[pattern.len() - 34 + 4], 0);
+
+    // left split up to tile 4 with chr from $5202
+    mapper.cpu_write(0x5104, 0);
+    mapper.cpu_write(0x5200, 0x84);
+    mapper.cpu_write(0x5202, 5);
+    render_line(&mut mapper, &mut pattern);
+    let line = &pattern[pattern.len() - 34..];
+    assert_eq!(&line[..3], &[20, 20, 0]);
+    assert_eq!(&line[32..], &[20, 20]);
+}
+
+#[test]
+fn test_pcm_irq_state() {
+    let mut mapper = Mmc5::new(banked(4, PRG_BANK_SIZE), banked(8, 0x400), 0x2000, 0x2000);
+    // pcm read mode with its irq on, then a zero byte read from $8000
+    mapper.cpu_write(0x5010, 0x81);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert!(mapper.irq_pending());
+
+    // the pending pcm irq comes back with a savestate
+    let mut state = StateWriter::default();
+    mapper.save_state(&mut state);
+    let state = state.into_inner();
+    mapper.cpu_read(0x5010);
+    assert!(!mapper.irq_pending());
+    mapper.load_state(&mut StateReader::new(&state)).unwrap();
+    assert!(mapper.irq_pending());
+}
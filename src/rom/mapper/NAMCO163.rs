@@ -0,0 +1,274 @@
+use std::borrow::Cow;
+
+use crate::apu::expansion::Namco163Audio;
+use crate::apu::ExpansionAudio;
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+/// chr and nametable bank numbers from here up pick a page of ciram
+const CIRAM_BANKS: u8 = 0xe0;
+
+/// namco 163 (mapper 19): three switchable 8k prg banks, eight 1k chr banks,
+/// four nametable banks that pick either ciram or chr rom, a 15-bit cpu cycle
+/// irq counter and the wavetable sound chip. pattern table banks pointing
+/// into ciram read chr rom instead
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    irq_enabled: bool,
+    irq_counter: u16,
+    audio: Namco163Audio,
+}
+
+impl Namco163 {
+    pub fn new(prg_rom: Vec<u8>, mut chr: Vec<u8>, chr_ram_size: usize) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [CIRAM_BANKS; 4],
+            irq_enabled: false,
+            irq_counter: 0,
+            audio: Namco163Audio::default(),
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match address {
+            0x8000..=0x9fff => self.prg_banks[0] as usize,
+            0xa000..=0xbfff => self.prg_banks[1] as usize,
+            0xc000..=0xdfff => self.prg_banks[2] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_bank_offset(&self, bank: u8, address: u16) -> usize {
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank as usize % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn nametable_bank(&self, address: u16) -> u8 {
+        self.nametable_banks[(address as usize >> 10) & 0x3]
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x4800..=0x4fff => self.audio.read_data(),
+            _ => self.peek(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x4800..=0x4fff => self.audio.write_data(data),
+            0x5000..=0x57ff => self.irq_counter = (self.irq_counter & 0x7f00) | data as u16,
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | (data as u16 & 0x7f) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+            }
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000] = data,
+            0x8000..=0xbfff => self.chr_banks[(address as usize - 0x8000) >> 11] = data,
+            0xc000..=0xdfff => self.nametable_banks[(address as usize - 0xc000) >> 11] = data,
+            0xe000..=0xe7ff => {
+                self.prg_banks[0] = data & 0x3f;
+                self.audio.set_enabled(data & 0x40 == 0);
+            }
+            0xe800..=0xefff => self.prg_banks[1] = data & 0x3f,
+            0xf000..=0xf7ff => self.prg_banks[2] = data & 0x3f,
+            0xf800..=0xffff => self.audio.write_address(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[address as usize / CHR_BANK_SIZE];
+        self.chr[self.chr_bank_offset(bank, address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[address as usize / CHR_BANK_SIZE];
+            let offset = self.chr_bank_offset(bank, address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x5000..=0x57ff => self.irq_counter as u8,
+            0x5800..=0x5fff => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    /// only a hint, `nametable_offset` follows the nametable banks
+    fn mirroring(&self) -> MirrorType {
+        if self.nametable_banks[1] & 0x1 == 0 {
+            MirrorType::Horizontal
+        } else {
+            MirrorType::Vertical
+        }
+    }
+
+    fn nametable_offset(&self, address: u16) -> usize {
+        (self.nametable_bank(address) as usize & 0x1) * 0x400 + (address as usize & 0x3ff)
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        let bank = self.nametable_bank(address);
+        (bank < CIRAM_BANKS).then(|| self.chr[self.chr_bank_offset(bank, address)])
+    }
+
+    /// nametables banked to chr rom swallow the write
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        self.nametable_bank(address) < CIRAM_BANKS
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_enabled && self.irq_counter == 0x7fff
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7fff {
+            self.irq_counter += 1;
+        }
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    /// the sound chip's ram goes after the prg ram, some games keep their
+    /// saves in it
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Owned([&self.prg_ram[..], self.audio.ram()].concat()))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+        if let Some(data) = data.get(self.prg_ram.len()..) {
+            load_ram(self.audio.ram_mut(), data);
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_banks);
+        state.write_bytes(&self.chr_banks);
+        state.write_bytes(&self.nametable_banks);
+        state.write_bool(self.irq_enabled);
+        state.write_u16(self.irq_counter);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.prg_banks)?;
+        state.read_bytes(&mut self.chr_banks)?;
+        state.read_bytes(&mut self.nametable_banks)?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_counter = state.read_u16()?;
+        self.audio.load_state(state)
+    }
+}
+
+#[test]
+fn test_namco163() {
+    let prg = (0..16)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect();
+    let chr = (0..32)
+        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+        .collect();
+    let mut mapper = Namco163::new(prg, chr, 0x2000);
+    mapper.cpu_write(0xe000, 3);
+    mapper.cpu_write(0xe800, 4);
+    mapper.cpu_write(0xf000, 5);
+    mapper.cpu_write(0xb800, 9);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xa000), 4);
+    assert_eq!(mapper.cpu_read(0xc000), 5);
+    assert_eq!(mapper.cpu_read(0xe000), 15);
+    assert_eq!(mapper.ppu_read(0x1c00), 9);
+
+    // $E0 and up is ciram, anything lower is chr rom
+    mapper.cpu_write(0xc800, 0xe1);
+    assert_eq!(mapper.nametable_offset(0x2405), 0x405);
+    assert_eq!(mapper.nametable_read(0x2405), None);
+    mapper.cpu_write(0xd800, 7);
+    assert_eq!(mapper.nametable_read(0x2c05), Some(7));
+    assert!(mapper.nametable_write(0x2c05, 0));
+
+    // the sound chip's ram through $F800/$4800
+    mapper.cpu_write(0xf800, 0x80);
+    mapper.cpu_write(0x4800, 0x12);
+    mapper.cpu_write(0xf800, 0x00);
+    assert_eq!(mapper.cpu_read(0x4800), 0x12);
+
+    mapper.cpu_write(0x5000, 0xfd);
+    mapper.cpu_write(0x5800, 0xff);
+    mapper.cpu_clock();
+    assert!(!mapper.irq_pending());
+    mapper.cpu_clock();
+    assert!(mapper.irq_pending());
+    assert_eq!(mapper.cpu_read(0x5800), 0xff);
+    mapper.cpu_write(0x5800, 0x7f);
+    assert!(!mapper.irq_pending());
+}
+
+#[test]
+fn test_namco163_sound_ram() {
+    let mut mapper = Namco163::new(vec![0; 0x10000], vec![], 0x2000);
+    mapper.cpu_write(0x6000, 0x11);
+    mapper.cpu_write(0xf800, 0x80 | 0x7f);
+    mapper.cpu_write(0x4800, 0x22);
+
+    // the sound chip's ram follows the prg ram in a .sav
+    let save = mapper.save_ram().unwrap().into_owned();
+    assert_eq!(save.len(), 0x2080);
+    assert_eq!((save[0], save[0x207f]), (0x11, 0x22));
+
+    // and is part of savestates, the cpu reads it back through $4800
+    let mut state = StateWriter::default();
+    mapper.save_state(&mut state);
+    let state = state.into_inner();
+    mapper.cpu_write(0xf800, 0x7f);
+    mapper.cpu_write(0x4800, 0x33);
+    mapper.load_state(&mut StateReader::new(&state)).unwrap();
+    mapper.cpu_write(0xf800, 0x7f);
+    assert_eq!(mapper.cpu_read(0x4800), 0x22);
+
+    let mut mapper = Namco163::new(vec![0; 0x10000], vec![], 0x2000);
+    mapper.load_save_ram(&save);
+    mapper.cpu_write(0xf800, 0x7f);
+    assert_eq!(mapper.cpu_read(0x4800), 0x22);
+    assert_eq!(mapper.cpu_read(0x6000), 0x11);
+}
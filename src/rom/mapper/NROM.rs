@@ -1,89 +1,121 @@
-use crate::rom::mapper::{Mapper, split_word};
+use std::borrow::Cow;
 
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+/// no banking: 16k or 32k of prg rom, 16k mirrored into both halves, 8k of
+/// chr rom or ram and 8k of prg ram for the family basic carts
 pub struct NRom {
     prg_rom: Vec<u8>,
     chr: Vec<u8>,
+    chr_ram: bool,
     prg_ram: [u8; 1024 * 8],
-    is_mirroring: bool,
+    mirroring: MirrorType,
 }
 
 impl NRom {
-    pub fn new(mut prg_rom: Vec<u8>, mut chr_rom: Vec<u8>, is_mirroring: bool) -> Self {
-        if chr_rom.is_empty() {
-            chr_rom.resize(0x2000, 0);
-        }
-        
-        if prg_rom.len() <= 1024 * 16 {
-            let len = prg_rom.len();
-            let bak = prg_rom.clone();
-            prg_rom.resize(32 * 1024, 0);
-            prg_rom[16 * 1024..16 * 1024 + len].copy_from_slice(&bak);
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
         }
-        
+
         Self {
             prg_rom,
-            chr: chr_rom,
+            chr,
+            chr_ram,
             prg_ram: [0; 1024 * 8],
-            is_mirroring,
-        }
-    }
-    
-    fn parse_address(&self, mut address: u16) -> u16 {
-        address &= 0xffff;
-        
-        if address <= 0x2000 {
-            address
-        } else if self.is_mirroring {
-            (address & 0b1011_1111_1111_1111) - 0x8000
-        } else {
-            address - 0x8000
+            mirroring,
         }
     }
 }
 
 impl Mapper for NRom {
-    fn read(&self, address: u16) -> u8 {
-        *self.data_ref(address)
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
     }
-    
-    fn write(&mut self, address: u16, data: u8) {
-        *self.data_ref_mut(address) = data;
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        if let 0x6000..=0x7fff = address {
+            self.prg_ram[address as usize - 0x6000] = data;
+        }
     }
-    
-    fn read_word(&self, address: u16) -> u16 {
-        (self.read(address + 1) as u16) << 8 | (self.read(address) as u16)
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize & 0x1fff]
     }
-    
-    fn write_word(&mut self, address: u16, data: u16) {
-        let (high, low) = split_word(data);
-        self.write(address, low);
-        self.write(address + 1, high);
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[address as usize & 0x1fff] = data;
+        }
     }
-    
-    fn data_ref(&self, address: u16) -> &u8 {
-        if address <= 0x2000 {
-            self.chr.get(address as usize)
-        } else if address >= 0x8000 {
-            self.prg_rom.get(self.parse_address(address) as usize)
-        } else if address >= 0x6000 {
-            self.prg_ram.get((address - 0x6000) as usize)
-        } else {
-            panic!("invalid address {:#x}", address)
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[(address as usize - 0x8000) % self.prg_rom.len()],
+            _ => 0,
         }
-            .unwrap()
     }
-    
-    fn data_ref_mut(&mut self, address: u16) -> &mut u8 {
-        if address <= 0x2000 {
-            self.chr.get_mut(address as usize)
-        } else if address >= 0x8000 {
-            let address = self.parse_address(address) as usize;
-            self.prg_rom.get_mut(address)
-        } else if address >= 0x6000 {
-            self.prg_ram.get_mut((address - 0x6000) as usize)
-        } else {
-            panic!("invalid address {:#x}", address)
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
         }
-            .unwrap()
+        Ok(())
     }
 }
+
+#[test]
+fn test_nrom() {
+    let prg = (0..0x4000).map(|i| (i >> 8) as u8).collect();
+    let mut mapper = NRom::new(prg, vec![], 0x2000, MirrorType::Vertical);
+    // 16k shows up in both halves
+    assert_eq!(mapper.cpu_read(0x8100), 0x01);
+    assert_eq!(mapper.cpu_read(0xc100), 0x01);
+    mapper.cpu_write(0x8100, 0xff);
+    assert_eq!(mapper.cpu_read(0x8100), 0x01);
+
+    // cpu $0000-$1FFF and ppu $0000-$1FFF no longer collide
+    mapper.ppu_write(0x0010, 0x55);
+    assert_eq!(mapper.ppu_read(0x0010), 0x55);
+    mapper.cpu_write(0x6010, 0xaa);
+    assert_eq!(mapper.peek(0x6010), 0xaa);
+
+    let mut state = StateWriter::default();
+    mapper.save_state(&mut state);
+    let state = state.into_inner();
+    mapper.cpu_write(0x6010, 0);
+    mapper.ppu_write(0x0010, 0);
+    mapper.load_state(&mut StateReader::new(&state)).unwrap();
+    assert_eq!(mapper.peek(0x6010), 0xaa);
+    assert_eq!(mapper.ppu_read(0x0010), 0x55);
+}
@@ -0,0 +1,383 @@
+use crate::apu::expansion::{
+    FdsAudio, Mmc5Audio, Namco163Audio, Sunsoft5BAudio, Vrc6Audio, Vrc7Audio,
+};
+use crate::apu::ExpansionAudio;
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::rom::nsf::{ExpansionChips, Nsf};
+use crate::state::{StateReader, StateWriter};
+
+const BANK_SIZE: usize = 0x1000;
+
+/// the sound chips an nsf asked for, mixed together
+#[derive(Default)]
+struct NsfAudio {
+    vrc6: Option<Vrc6Audio>,
+    vrc7: Option<Vrc7Audio>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<Mmc5Audio>,
+    n163: Option<Namco163Audio>,
+    s5b: Option<Sunsoft5BAudio>,
+}
+
+impl NsfAudio {
+    fn new(chips: ExpansionChips) -> Self {
+        Self {
+            vrc6: chips
+                .contains(ExpansionChips::VRC6)
+                .then(Vrc6Audio::default),
+            vrc7: chips
+                .contains(ExpansionChips::VRC7)
+                .then(Vrc7Audio::default),
+            fds: chips.contains(ExpansionChips::FDS).then(FdsAudio::default),
+            mmc5: chips
+                .contains(ExpansionChips::MMC5)
+                .then(Mmc5Audio::default),
+            n163: chips
+                .contains(ExpansionChips::N163)
+                .then(Namco163Audio::default),
+            s5b: chips
+                .contains(ExpansionChips::S5B)
+                .then(Sunsoft5BAudio::default),
+        }
+    }
+
+    fn chips(&mut self) -> [Option<&mut dyn ExpansionAudio>; 6] {
+        [
+            self.vrc6
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+            self.vrc7
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+            self.fds
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+            self.mmc5
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+            self.n163
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+            self.s5b
+                .as_mut()
+                .map(|chip| chip as &mut dyn ExpansionAudio),
+        ]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.vrc6.is_none()
+            && self.vrc7.is_none()
+            && self.fds.is_none()
+            && self.mmc5.is_none()
+            && self.n163.is_none()
+            && self.s5b.is_none()
+    }
+}
+
+impl ExpansionAudio for NsfAudio {
+    fn clock(&mut self) {
+        for chip in self.chips().into_iter().flatten() {
+            chip.clock();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let mut output = 0.0;
+        output += self.vrc6.as_ref().map_or(0.0, |chip| chip.output());
+        output += self.vrc7.as_ref().map_or(0.0, |chip| chip.output());
+        output += self.fds.as_ref().map_or(0.0, |chip| chip.output());
+        output += self.mmc5.as_ref().map_or(0.0, |chip| chip.output());
+        output += self.n163.as_ref().map_or(0.0, |chip| chip.output());
+        output += self.s5b.as_ref().map_or(0.0, |chip| chip.output());
+        output
+    }
+
+    /// in the order of `chips`, which is how `load_state` reads them back
+    fn save_state(&self, state: &mut StateWriter) {
+        if let Some(chip) = &self.vrc6 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.vrc7 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.fds {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.mmc5 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.n163 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.s5b {
+            chip.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        for chip in self.chips().into_iter().flatten() {
+            chip.load_state(state)?;
+        }
+        Ok(())
+    }
+}
+
+/// the hardware an nsf player provides: 4k banks at $8000-$FFFF switched
+/// through $5FF8-$5FFF, 8k of ram at $6000, and the requested sound chips.
+/// fds tunes instead get ram from $6000 up, with banks copied into it
+pub struct NsfMapper {
+    data: Vec<u8>,
+    banks: [u8; 8],
+    ram: Vec<u8>,
+    fds: bool,
+    exram: [u8; 0x400],
+    multiplier: [u8; 2],
+    audio: NsfAudio,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        let fds = nsf.chips.contains(ExpansionChips::FDS);
+        let (data, banks) = match nsf.banks {
+            Some(banks) => {
+                // bank 0 starts at the load address rounded down to 4k
+                let padding = nsf.load_address as usize & (BANK_SIZE - 1);
+                let mut data = vec![0u8; padding];
+                data.extend_from_slice(&nsf.data);
+                (data, banks)
+            }
+            None => {
+                // a flat image of $8000-$FFFF (or $6000-$FFFF for fds)
+                let base = if fds { 0x6000 } else { 0x8000 };
+                let offset = (nsf.load_address as usize).saturating_sub(base);
+                let mut data = vec![0u8; 0x10000 - base];
+                let len = nsf.data.len().min(data.len() - offset);
+                data[offset..offset + len].copy_from_slice(&nsf.data[..len]);
+                let first = (0x8000 - base) / BANK_SIZE;
+                let banks = [0, 1, 2, 3, 4, 5, 6, 7].map(|bank| (bank + first) as u8);
+                (data, banks)
+            }
+        };
+        let mut data = data;
+        let len = data.len().div_ceil(BANK_SIZE).max(1) * BANK_SIZE;
+        data.resize(len, 0);
+
+        let mut mapper = Self {
+            data,
+            banks,
+            ram: vec![0; if fds { 0xa000 } else { 0x2000 }],
+            fds,
+            exram: [0; 0x400],
+            multiplier: [0; 2],
+            audio: NsfAudio::new(nsf.chips),
+        };
+        if fds {
+            // fds ram is filled from the banks, $6000/$7000 use banks 6 and 7
+            // for bankswitched tunes and the flat image otherwise
+            let low = match nsf.banks {
+                Some(banks) => [banks[6], banks[7]],
+                None => [0, 1],
+            };
+            mapper.switch_bank(0, low[0]);
+            mapper.switch_bank(1, low[1]);
+            for (slot, bank) in banks.iter().enumerate() {
+                mapper.switch_bank(slot + 2, *bank);
+            }
+        }
+        mapper
+    }
+
+    /// `slot` counts 4k windows from $6000 on fds and from $8000 otherwise
+    fn switch_bank(&mut self, slot: usize, bank: u8) {
+        if self.fds {
+            let start = self.bank_offset(bank);
+            let window = slot * BANK_SIZE;
+            self.ram[window..window + BANK_SIZE]
+                .copy_from_slice(&self.data[start..start + BANK_SIZE]);
+        } else {
+            self.banks[slot] = bank;
+        }
+    }
+
+    fn bank_offset(&self, bank: u8) -> usize {
+        (bank as usize % (self.data.len() / BANK_SIZE)) * BANK_SIZE
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let slot = (address as usize - 0x8000) / BANK_SIZE;
+        self.data[self.bank_offset(self.banks[slot]) + (address as usize & (BANK_SIZE - 1))]
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        let audio = &mut self.audio;
+        match address {
+            0x4040..=0x4092 if audio.fds.is_some() => audio
+                .fds
+                .as_ref()
+                .and_then(|fds| fds.read(address))
+                .unwrap_or(0),
+            0x4800..=0x4fff if audio.n163.is_some() => audio.n163.as_mut().unwrap().read_data(),
+            0x5000..=0x5015 if audio.mmc5.is_some() => {
+                audio.mmc5.as_mut().unwrap().read(address).unwrap_or(0)
+            }
+            0x8000..=0xffff if !self.fds => {
+                let val = self.read_rom(address);
+                if address < 0xc000 {
+                    if let Some(mmc5) = self.audio.mmc5.as_mut() {
+                        mmc5.pcm_read(val);
+                    }
+                }
+                val
+            }
+            _ => self.peek(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        let audio = &mut self.audio;
+        match address {
+            0x4040..=0x408a => {
+                if let Some(fds) = audio.fds.as_mut() {
+                    fds.write(address, data);
+                }
+            }
+            0x4800..=0x4fff => {
+                if let Some(n163) = audio.n163.as_mut() {
+                    n163.write_data(data);
+                }
+            }
+            0x5000..=0x5015 => {
+                if let Some(mmc5) = audio.mmc5.as_mut() {
+                    mmc5.write(address, data);
+                }
+            }
+            0x5205 => self.multiplier[0] = data,
+            0x5206 => self.multiplier[1] = data,
+            0x5c00..=0x5ff5 => self.exram[address as usize - 0x5c00] = data,
+            0x5ff6..=0x5ff7 if self.fds => self.switch_bank(address as usize - 0x5ff6, data),
+            0x5ff8..=0x5fff => {
+                let slot = address as usize - 0x5ff8;
+                self.switch_bank(if self.fds { slot + 2 } else { slot }, data);
+            }
+            // fds ram is writable up to $DFFF, the rest holds the vectors
+            0x6000..=0xdfff if self.fds => self.ram[address as usize - 0x6000] = data,
+            0x6000..=0x7fff => self.ram[address as usize - 0x6000] = data,
+            _ => {}
+        }
+
+        // chip registers sit on top of rom, so they are decoded independently
+        let audio = &mut self.audio;
+        if let Some(vrc6) = audio.vrc6.as_mut() {
+            if matches!(address, 0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002) {
+                vrc6.write(address, data);
+            }
+        }
+        if let Some(vrc7) = audio.vrc7.as_mut() {
+            match address {
+                0x9010 => vrc7.write_address(data),
+                0x9030 => vrc7.write_data(data),
+                _ => {}
+            }
+        }
+        if let Some(n163) = audio.n163.as_mut() {
+            if address >= 0xf800 {
+                n163.write_address(data);
+            }
+        }
+        if let Some(s5b) = audio.s5b.as_mut() {
+            if address >= 0xc000 {
+                s5b.write(address, data);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        0
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x5205 => (self.multiplier[0] as u16 * self.multiplier[1] as u16) as u8,
+            0x5206 => ((self.multiplier[0] as u16 * self.multiplier[1] as u16) >> 8) as u8,
+            0x5c00..=0x5ff5 => self.exram[address as usize - 0x5c00],
+            0x6000..=0xffff if self.fds => self.ram[address as usize - 0x6000],
+            0x6000..=0x7fff => self.ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.read_rom(address),
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        MirrorType::Vertical
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.banks);
+        state.write_bytes(&self.ram);
+        state.write_bytes(&self.exram);
+        state.write_bytes(&self.multiplier);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.banks)?;
+        state.read_bytes(&mut self.ram)?;
+        state.read_bytes(&mut self.exram)?;
+        state.read_bytes(&mut self.multiplier)?;
+        self.audio.load_state(state)
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        if self.audio.is_empty() {
+            None
+        } else {
+            Some(&mut self.audio)
+        }
+    }
+}
+
+#[test]
+fn test_bankswitching() {
+    let nsf = Nsf {
+        load_address: 0x8100,
+        banks: Some([0, 1, 2, 3, 4, 5, 6, 7]),
+        data: (0..0x3000).map(|i| (i / 0x100) as u8).collect(),
+        ..Nsf::default()
+    };
+    let mut mapper = NsfMapper::new(&nsf);
+    // the load address offset pads the first bank
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    assert_eq!(mapper.cpu_read(0x8100), 0);
+    assert_eq!(mapper.cpu_read(0x8200), 1);
+    assert_eq!(mapper.cpu_read(0x9000), 0x0f);
+
+    mapper.cpu_write(0x5fff, 2);
+    assert_eq!(mapper.cpu_read(0xf000), 0x1f);
+    mapper.cpu_write(0x6000, 0xaa);
+    assert_eq!(mapper.cpu_read(0x6000), 0xaa);
+    mapper.cpu_write(0x8000, 0xaa);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+}
+
+#[test]
+fn test_fds_ram() {
+    let nsf = Nsf {
+        load_address: 0x6000,
+        chips: ExpansionChips::FDS,
+        data: vec![0x11; 0x3000],
+        ..Nsf::default()
+    };
+    let mut mapper = NsfMapper::new(&nsf);
+    assert_eq!(mapper.cpu_read(0x8000), 0x11);
+    mapper.cpu_write(0x8000, 0x22);
+    assert_eq!(mapper.cpu_read(0x8000), 0x22);
+    mapper.cpu_write(0xe000, 0x22);
+    assert_eq!(mapper.cpu_read(0xe000), 0);
+    assert!(mapper.expansion_audio().is_some());
+}
@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::flash::Flash;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// unrom 512 (mapper 30): uxrom style prg banking over up to 512k, four 8k
+/// banks of chr ram and the header's mirroring bits picking horizontal,
+/// vertical, one screen switched by bit 7 of the bank register, or four
+/// screen out of the last chr ram bank. boards with a battery bit have no
+/// battery at all but rewrite their own flash through $8000-$BFFF
+pub struct Unrom512 {
+    flash: Flash,
+    chr_ram: Vec<u8>,
+    /// header byte 6 bits 0 and 3
+    mirror_bits: u8,
+    flashable: bool,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl Unrom512 {
+    pub fn new(prg_rom: Vec<u8>, mirror_bits: u8, flashable: bool, submapper: u8) -> Self {
+        Self {
+            flash: Flash::new(prg_rom),
+            chr_ram: vec![0; CHR_BANK_SIZE * 4],
+            mirror_bits,
+            flashable,
+            // nes 2.0 submapper 1 is the discrete board without a flash chip,
+            // the only one with bus conflicts (nesdev wiki, unrom 512)
+            bus_conflicts: !flashable && submapper == 1,
+            bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.flash.data().len() / PRG_BANK_SIZE;
+        let bank = match address {
+            0x8000..=0xbfff => (self.bank & 0x1f) as usize % bank_count,
+            _ => bank_count - 1,
+        };
+        bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        ((self.bank as usize >> 5) & 0x3) * CHR_BANK_SIZE + (address as usize & 0x1fff)
+    }
+
+    fn four_screen(&self) -> bool {
+        self.mirror_bits == 0b1001
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, mut data: u8) {
+        match address {
+            0x8000..=0xbfff if self.flashable => {
+                let offset = self.prg_offset(address);
+                self.flash.write(offset, data);
+            }
+            0x8000..=0xffff => {
+                if self.bus_conflicts {
+                    data &= self.peek(address);
+                }
+                self.bank = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr_ram[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        let offset = self.chr_offset(address);
+        self.chr_ram[offset] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.flash.read(self.prg_offset(address)),
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        match self.mirror_bits {
+            0b0000 => MirrorType::Horizontal,
+            0b0001 => MirrorType::Vertical,
+            0b1000 if self.bank & 0x80 == 0 => MirrorType::SingleScreenLower,
+            0b1000 => MirrorType::SingleScreenUpper,
+            _ => MirrorType::FourScreen,
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        self.four_screen()
+            .then(|| self.chr_ram[3 * CHR_BANK_SIZE + (address as usize & 0x1fff)])
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        if self.four_screen() {
+            self.chr_ram[3 * CHR_BANK_SIZE + (address as usize & 0x1fff)] = data;
+        }
+        self.four_screen()
+    }
+
+    /// the flash itself, so what the game wrote survives a restart
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        self.flashable.then(|| Cow::Borrowed(self.flash.data()))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(self.flash.data_mut(), data);
+    }
+
+    fn nonvolatile(&self) -> bool {
+        self.flashable
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.flash.save_state(state);
+        state.write_bytes(&self.chr_ram);
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.flash.load_state(state)?;
+        state.read_bytes(&mut self.chr_ram)?;
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_unrom512() {
+    let prg = (0..32)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect::<Vec<u8>>();
+    let mut mapper = Unrom512::new(prg, 0b1000, true, 0);
+    mapper.cpu_write(0xc000, 0xa5);
+    assert_eq!(mapper.cpu_read(0x8000), 5);
+    assert_eq!(mapper.cpu_read(0xc000), 31);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenUpper);
+    mapper.ppu_write(0x0010, 0x77);
+    assert_eq!(mapper.chr_ram[CHR_BANK_SIZE + 0x10], 0x77);
+
+    // program a byte the way homebrew does, through banks 1 and 0
+    let command = |mapper: &mut Unrom512, bank: u8, address: u16, data: u8| {
+        mapper.cpu_write(0xc000, bank);
+        mapper.cpu_write(address, data);
+    };
+    command(&mut mapper, 1, 0x9555, 0xaa);
+    command(&mut mapper, 0, 0xaaaa, 0x55);
+    command(&mut mapper, 1, 0x9555, 0xa0);
+    command(&mut mapper, 4, 0x8123, 0x00);
+    assert_eq!(mapper.cpu_read(0x8123), 0x00);
+    assert_eq!(mapper.save_ram().unwrap()[4 * PRG_BANK_SIZE + 0x123], 0x00);
+
+    // four screen nametables live in the last chr ram bank
+    let mut mapper = Unrom512::new(vec![0; 0x8000], 0b1001, false, 0);
+    assert!(mapper.nametable_write(0x2c01, 0x33));
+    assert_eq!(mapper.nametable_read(0x2c01), Some(0x33));
+    mapper.cpu_write(0x8000, 0x60);
+    assert_eq!(mapper.ppu_read(0x0c01), 0x33);
+
+    // submapper 1 ands the bank number with the rom byte under it
+    let mut mapper = Unrom512::new(vec![0x0f; 0x8000], 0b0000, false, 1);
+    mapper.cpu_write(0x8000, 0x61);
+    assert_eq!(mapper.bank, 0x01);
+}
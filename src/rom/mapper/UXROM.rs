@@ -0,0 +1,122 @@
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::Mapper;
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// uxrom (mapper 2): a switchable 16k bank at $8000, the last one fixed at
+/// $C000 and 8k of chr ram
+pub struct UxRom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    mirroring: MirrorType,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl UxRom {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mirroring: MirrorType,
+        bus_conflicts: bool,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            mirroring,
+            bus_conflicts,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for UxRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, mut data: u8) {
+        if address >= 0x8000 {
+            if self.bus_conflicts {
+                data &= self.peek(address);
+            }
+            self.bank = data;
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize & 0x1fff]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[address as usize & 0x1fff] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0xbfff => self.bank as usize % bank_count,
+            0xc000..=0xffff => bank_count - 1,
+            _ => return 0,
+        };
+        self.prg_rom
+            [(bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()]
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_uxrom() {
+    let mut prg = (0..8)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect::<Vec<u8>>();
+    // a bank table the way games avoid conflicts
+    prg[0x3ff0..0x3ff8].copy_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7]);
+    let mut mapper = UxRom::new(prg, vec![], 0x2000, MirrorType::Vertical, true);
+    assert_eq!(mapper.cpu_read(0xc000), 7);
+    mapper.cpu_write(0xbff3, 3);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    // rom holds 3 here, so writing 6 only gets 2 through
+    mapper.cpu_write(0x8000, 6);
+    assert_eq!(mapper.cpu_read(0x8000), 2);
+
+    // an 8k image mirrors into both halves instead of panicking
+    let mut mapper = UxRom::new(
+        vec![0x42; 0x2000],
+        vec![],
+        0x2000,
+        MirrorType::Vertical,
+        false,
+    );
+    assert_eq!(mapper.cpu_read(0x8000), 0x42);
+    assert_eq!(mapper.cpu_read(0xffff), 0x42);
+}
@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::vrc_irq::VrcIrq;
+use crate::rom::mapper::{load_ram, Mapper, VRC2A, VRC4AC, VRC4EF};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// konami vrc2 and vrc4 (mappers 21, 22, 23 and 25): two switchable 8k prg
+/// banks, eight 1k chr banks written a nibble at a time and, on the vrc4, a
+/// prg swap mode, single screen mirroring and the vrc irq counter. each board
+/// wires different cpu address lines to the chip's two register select pins
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    vrc2: bool,
+    /// the cpu address lines on register selects 0 and 1, boards without a
+    /// submapper get every wiring the mapper number could mean
+    lines: (u16, u16),
+    /// vrc2a leaves chr A10 off the chip, so banks count in 2k
+    chr_shift: u8,
+    prg_banks: [u8; 2],
+    chr_banks: [u16; 8],
+    swap_mode: bool,
+    mirroring: MirrorType,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        mut chr: Vec<u8>,
+        chr_ram_size: usize,
+        mapper: usize,
+        submapper: u8,
+    ) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        let (vrc2, lines) = match (mapper, submapper) {
+            (VRC4AC, 1) => (false, (0x02, 0x04)),
+            (VRC4AC, 2) => (false, (0x40, 0x80)),
+            (VRC4AC, _) => (false, (0x42, 0x84)),
+            (VRC2A, _) => (true, (0x02, 0x01)),
+            (VRC4EF, 1) => (false, (0x01, 0x02)),
+            (VRC4EF, 2) => (false, (0x04, 0x08)),
+            (VRC4EF, 3) => (true, (0x01, 0x02)),
+            (VRC4EF, _) => (false, (0x05, 0x0a)),
+            // what's left is VRC4BD, which is also where vrc2c lives
+            (_, 1) => (false, (0x02, 0x01)),
+            (_, 2) => (false, (0x08, 0x04)),
+            (_, 3) => (true, (0x02, 0x01)),
+            _ => (false, (0x0a, 0x05)),
+        };
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            vrc2,
+            lines,
+            chr_shift: (mapper == VRC2A) as u8,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            swap_mode: false,
+            mirroring: MirrorType::Vertical,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match (address, self.swap_mode) {
+            (0x8000..=0x9fff, false) | (0xc000..=0xdfff, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9fff, true) | (0xc000..=0xdfff, false) => bank_count - 2,
+            (0xa000..=0xbfff, _) => self.prg_banks[1] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = (self.chr_banks[address as usize / CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    /// fold the board's wiring onto $x000-$x003
+    fn register(&self, address: u16) -> u16 {
+        let low = (address & self.lines.0 != 0) as u16;
+        let high = (address & self.lines.1 != 0) as u16;
+        address & 0xf000 | high << 1 | low
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1f,
+            0x9000..=0x9003 if self.vrc2 => self.mirroring = MirrorType::from(data & 0x1 == 0),
+            0x9000 | 0x9001 => {
+                self.mirroring = match data & 0x3 {
+                    0 => MirrorType::Vertical,
+                    1 => MirrorType::Horizontal,
+                    2 => MirrorType::SingleScreenLower,
+                    _ => MirrorType::SingleScreenUpper,
+                };
+            }
+            0x9002 | 0x9003 => self.swap_mode = data & 0x2 != 0,
+            0xa000..=0xa003 => self.prg_banks[1] = data & 0x1f,
+            reg @ 0xb000..=0xefff => {
+                // each register pair is one bank, low nibble then high bits
+                let bank = (((reg - 0xb000) >> 12) * 2 + ((reg >> 1) & 0x1)) as usize;
+                let data = data as u16;
+                self.chr_banks[bank] = if reg & 0x1 == 0 {
+                    (self.chr_banks[bank] & 0x1f0) | (data & 0xf)
+                } else {
+                    let high = if self.vrc2 { 0xf } else { 0x1f };
+                    (self.chr_banks[bank] & 0xf) | (data & high) << 4
+                };
+            }
+            0xf000 if !self.vrc2 => self.irq.write_latch_low(data),
+            0xf001 if !self.vrc2 => self.irq.write_latch_high(data),
+            0xf002 if !self.vrc2 => self.irq.write_control(data),
+            0xf003 if !self.vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000] = data,
+            0x8000..=0xffff => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_banks);
+        for bank in self.chr_banks {
+            state.write_u16(bank);
+        }
+        state.write_bool(self.swap_mode);
+        state.write_u8(self.mirroring as u8);
+        self.irq.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.prg_banks)?;
+        for bank in self.chr_banks.iter_mut() {
+            *bank = state.read_u16()?;
+        }
+        self.swap_mode = state.read_bool()?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.irq.load_state(state)
+    }
+}
+
+#[cfg(test)]
+fn banked(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+#[test]
+fn test_vrc4() {
+    let mut mapper = Vrc4::new(
+        banked(16, PRG_BANK_SIZE),
+        banked(256, CHR_BANK_SIZE),
+        0x2000,
+        VRC4AC,
+        0,
+    );
+    let prg = |mapper: &Vrc4| [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mapper.peek(address));
+    mapper.cpu_write(0x8000, 3);
+    mapper.cpu_write(0xa000, 4);
+    assert_eq!(prg(&mapper), [3, 4, 14, 15]);
+    // vrc4c wiring reaches $9002 through A7
+    mapper.cpu_write(0x9080, 0x2);
+    assert_eq!(prg(&mapper), [14, 4, 3, 15]);
+    // and vrc4a through A2
+    mapper.cpu_write(0x9004, 0x0);
+    assert_eq!(prg(&mapper), [3, 4, 14, 15]);
+
+    // $D002/$D003 on vrc4a are chr bank 5, nine bits wide
+    mapper.cpu_write(0xd004, 0x5);
+    mapper.cpu_write(0xd006, 0x1c);
+    assert_eq!(mapper.ppu_read(0x1400), 0xc5);
+    mapper.cpu_write(0x9000, 3);
+    assert_eq!(mapper.mirroring(), MirrorType::SingleScreenUpper);
+
+    // cycle mode irq
+    mapper.cpu_write(0xf000, 0xe);
+    mapper.cpu_write(0xf002, 0xf);
+    mapper.cpu_write(0xf004, 0x7);
+    mapper.cpu_clock();
+    assert!(!mapper.irq_pending());
+    mapper.cpu_clock();
+    assert!(mapper.irq_pending());
+    mapper.cpu_write(0xf006, 0);
+    assert!(!mapper.irq_pending());
+}
+
+#[test]
+fn test_vrc2() {
+    let mut mapper = Vrc4::new(
+        banked(16, PRG_BANK_SIZE),
+        banked(128, CHR_BANK_SIZE),
+        0x2000,
+        VRC2A,
+        0,
+    );
+    // vrc2a swaps A0 and A1, and drops the low chr bank bit
+    mapper.cpu_write(0xb001, 0x6);
+    mapper.cpu_write(0xb003, 0x2);
+    assert_eq!(mapper.ppu_read(0x0400), 0x13);
+    mapper.cpu_write(0x9000, 1);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+    // no swap mode or irq
+    mapper.cpu_write(0x9001, 0x2);
+    assert_eq!(mapper.cpu_read(0x8000), 0);
+    mapper.cpu_write(0xf001, 0xff);
+    mapper.cpu_write(0xf002, 0x7);
+    for _ in 0..4 {
+        mapper.cpu_clock();
+    }
+    assert!(!mapper.irq_pending());
+}
@@ -0,0 +1,216 @@
+use std::borrow::Cow;
+
+use crate::apu::expansion::Vrc6Audio;
+use crate::apu::ExpansionAudio;
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::vrc_irq::VrcIrq;
+use crate::rom::mapper::{load_ram, Mapper, VRC6B};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// konami vrc6 (mappers 24 and 26): a 16k prg bank at $8000, an 8k bank at
+/// $C000, eight 1k chr banks, the vrc irq counter and the vrc6 sound chip.
+/// mapper 26 swaps A0 and A1 on the way to the chip. only the $B003 chr mode
+/// every released game uses (eight 1k banks) is emulated
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    swapped: bool,
+    prg_banks: [u8; 2],
+    chr_banks: [u8; 8],
+    mirroring: MirrorType,
+    ram_enabled: bool,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(prg_rom: Vec<u8>, mut chr: Vec<u8>, chr_ram_size: usize, mapper: usize) -> Self {
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            swapped: mapper == VRC6B,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            mirroring: MirrorType::Vertical,
+            ram_enabled: false,
+            irq: VrcIrq::default(),
+            audio: Vrc6Audio::default(),
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match address {
+            0x8000..=0xbfff => self.prg_banks[0] as usize * 2 + (address as usize >> 13 & 0x1),
+            0xc000..=0xdfff => self.prg_banks[1] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize / CHR_BANK_SIZE] as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let address = if self.swapped {
+            address & 0xf000 | (address & 0x1) << 1 | (address >> 1) & 0x1
+        } else {
+            address & 0xf003
+        };
+        match address {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0xf,
+            0xb003 => {
+                self.mirroring = match (data >> 2) & 0x3 {
+                    0 => MirrorType::Vertical,
+                    1 => MirrorType::Horizontal,
+                    2 => MirrorType::SingleScreenLower,
+                    _ => MirrorType::SingleScreenUpper,
+                };
+                self.ram_enabled = data & 0x80 != 0;
+            }
+            0x9000..=0xb002 => self.audio.write(address, data),
+            0xc000..=0xc003 => self.prg_banks[1] = data & 0x1f,
+            0xd000..=0xd003 => self.chr_banks[(address & 0x3) as usize] = data,
+            0xe000..=0xe003 => self.chr_banks[(address & 0x3) as usize + 4] = data,
+            0xf000 => self.irq.write_latch(data),
+            0xf001 => self.irq.write_control(data),
+            0xf002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled => self.prg_ram[address as usize - 0x6000] = data,
+            0x8000..=0xffff => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_banks);
+        state.write_bytes(&self.chr_banks);
+        state.write_u8(self.mirroring as u8);
+        state.write_bool(self.ram_enabled);
+        self.irq.save_state(state);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.prg_banks)?;
+        state.read_bytes(&mut self.chr_banks)?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.ram_enabled = state.read_bool()?;
+        self.irq.load_state(state)?;
+        self.audio.load_state(state)
+    }
+}
+
+#[test]
+fn test_vrc6() {
+    let prg = (0..32)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect::<Vec<u8>>();
+    let chr = (0..64)
+        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+        .collect::<Vec<u8>>();
+    let mut mapper = Vrc6::new(prg.clone(), chr.clone(), 0x2000, crate::rom::mapper::VRC6A);
+    mapper.cpu_write(0x8000, 3);
+    mapper.cpu_write(0xc000, 9);
+    mapper.cpu_write(0xe002, 40);
+    assert_eq!(mapper.cpu_read(0x8000), 6);
+    assert_eq!(mapper.cpu_read(0xa000), 7);
+    assert_eq!(mapper.cpu_read(0xc000), 9);
+    assert_eq!(mapper.cpu_read(0xe000), 31);
+    assert_eq!(mapper.ppu_read(0x1800), 40);
+
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+    mapper.cpu_write(0xb003, 0x84);
+    assert_eq!(mapper.mirroring(), MirrorType::Horizontal);
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0x55);
+
+    // mapper 26 reaches $E002 through A0
+    let mut mapper = Vrc6::new(prg, chr, 0x2000, VRC6B);
+    mapper.cpu_write(0xe001, 41);
+    assert_eq!(mapper.ppu_read(0x1800), 41);
+    mapper.cpu_write(0xf000, 0xfe);
+    mapper.cpu_write(0xf002, 0x06);
+    mapper.cpu_clock();
+    assert!(!mapper.irq_pending());
+    mapper.cpu_clock();
+    assert!(mapper.irq_pending());
+}
@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+
+use crate::apu::expansion::Vrc7Audio;
+use crate::apu::ExpansionAudio;
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::rom::mapper::vrc_irq::VrcIrq;
+use crate::rom::mapper::{load_ram, Mapper};
+use crate::state::{StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// konami vrc7 (mapper 85): three switchable 8k prg banks, eight 1k chr banks,
+/// the vrc irq counter and the OPLL derived fm sound chip
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: [u8; 0x2000],
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    mirroring: MirrorType,
+    ram_enabled: bool,
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+}
+
+impl Vrc7 {
+    pub fn new(prg_rom: Vec<u8>, mut chr: Vec<u8>, chr_ram_size: usize) -> Self {
+        // lagrange point runs on 8k of chr ram
+        let chr_ram = chr.is_empty();
+        if chr_ram {
+            chr.resize(chr_ram_size, 0);
+        }
+        Self {
+            prg_rom,
+            chr,
+            chr_ram,
+            prg_ram: [0; 0x2000],
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            mirroring: MirrorType::Vertical,
+            ram_enabled: false,
+            irq: VrcIrq::default(),
+            audio: Vrc7Audio::default(),
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match address {
+            0x8000..=0x9fff => self.prg_banks[0] as usize,
+            0xa000..=0xbfff => self.prg_banks[1] as usize,
+            0xc000..=0xdfff => self.prg_banks[2] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize / CHR_BANK_SIZE] as usize;
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        (bank % bank_count) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    /// vrc7a selects registers with A4 and vrc7b with A3, fold both onto A4
+    fn write_register(&mut self, address: u16, data: u8) {
+        if address & 0xf030 == 0x9010 {
+            self.audio.write_address(data);
+            return;
+        }
+        if address & 0xf030 == 0x9030 {
+            self.audio.write_data(data);
+            return;
+        }
+
+        let high = if address & 0x18 != 0 { 0x10 } else { 0 };
+        match address & 0xf000 | high {
+            0x8000 => self.prg_banks[0] = data & 0x3f,
+            0x8010 => self.prg_banks[1] = data & 0x3f,
+            0x9000 => self.prg_banks[2] = data & 0x3f,
+            0xa000 => self.chr_banks[0] = data,
+            0xa010 => self.chr_banks[1] = data,
+            0xb000 => self.chr_banks[2] = data,
+            0xb010 => self.chr_banks[3] = data,
+            0xc000 => self.chr_banks[4] = data,
+            0xc010 => self.chr_banks[5] = data,
+            0xd000 => self.chr_banks[6] = data,
+            0xd010 => self.chr_banks[7] = data,
+            0xe000 => {
+                self.mirroring = match data & 0x3 {
+                    0 => MirrorType::Vertical,
+                    1 => MirrorType::Horizontal,
+                    2 => MirrorType::SingleScreenLower,
+                    _ => MirrorType::SingleScreenUpper,
+                };
+                self.ram_enabled = data & 0x80 != 0;
+                self.audio.set_muted(data & 0x40 != 0);
+            }
+            0xe010 => self.irq.write_latch(data),
+            0xf000 => self.irq.write_control(data),
+            0xf010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled => self.prg_ram[address as usize - 0x6000] = data,
+            0x8000..=0xffff => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.chr_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.ram_enabled => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn mirroring(&self) -> MirrorType {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self.prg_ram))
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        load_ram(&mut self.prg_ram, data);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_banks);
+        state.write_bytes(&self.chr_banks);
+        state.write_u8(self.mirroring as u8);
+        state.write_bool(self.ram_enabled);
+        self.irq.save_state(state);
+        self.audio.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        if self.chr_ram {
+            state.read_bytes(&mut self.chr)?;
+        }
+        state.read_bytes(&mut self.prg_banks)?;
+        state.read_bytes(&mut self.chr_banks)?;
+        self.mirroring = MirrorType::from_u8(state.read_u8()?);
+        self.ram_enabled = state.read_bool()?;
+        self.irq.load_state(state)?;
+        self.audio.load_state(state)
+    }
+}
+
+#[test]
+fn test_banking() {
+    let prg = (0..16)
+        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+        .collect();
+    let chr = (0..32)
+        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+        .collect();
+    let mut mapper = Vrc7::new(prg, chr, 0x2000);
+    assert_eq!(mapper.cpu_read(0xe000), 15);
+
+    // vrc7b (A3) and vrc7a (A4) register addresses reach the same banks
+    mapper.cpu_write(0x8000, 3);
+    mapper.cpu_write(0x8008, 4);
+    mapper.cpu_write(0x9000, 5);
+    mapper.cpu_write(0xa010, 9);
+    mapper.cpu_write(0xd008, 31);
+    assert_eq!(mapper.cpu_read(0x8000), 3);
+    assert_eq!(mapper.cpu_read(0xa000), 4);
+    assert_eq!(mapper.cpu_read(0xc000), 5);
+    assert_eq!(mapper.ppu_read(0x0400), 9);
+    assert_eq!(mapper.ppu_read(0x1c00), 31);
+
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0);
+    mapper.cpu_write(0xe000, 0x83);
+    assert!(matches!(mapper.mirroring(), MirrorType::SingleScreenUpper));
+    mapper.cpu_write(0x6000, 0x55);
+    assert_eq!(mapper.cpu_read(0x6000), 0x55);
+}
@@ -0,0 +1,305 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EepromChip {
+    /// 128 bytes, the first byte after a start holds the address and r/w
+    X24C01,
+    /// 256 bytes behind an $A0/$A1 device byte and a separate word address
+    C24C02,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Device,
+    Address,
+    Write,
+    Read,
+}
+
+impl Phase {
+    fn from_u8(val: u8) -> Self {
+        match val {
+            1 => Phase::Device,
+            2 => Phase::Address,
+            3 => Phase::Write,
+            4 => Phase::Read,
+            _ => Phase::Idle,
+        }
+    }
+}
+
+/// serial eeprom driven bit by bit over i2c. the board sets scl and sda, data
+/// bits are taken on scl rising and the chip answers on scl falling
+pub struct Eeprom {
+    chip: EepromChip,
+    data: Vec<u8>,
+    scl: bool,
+    sda: bool,
+    phase: Phase,
+    /// phase to go to once the acknowledge clock ends
+    next: Phase,
+    bit: u8,
+    shift: u8,
+    address: u8,
+    nak: bool,
+    out: bool,
+}
+
+impl Eeprom {
+    pub fn new(chip: EepromChip) -> Self {
+        let size = match chip {
+            EepromChip::X24C01 => 0x80,
+            EepromChip::C24C02 => 0x100,
+        };
+        Self {
+            chip,
+            data: vec![0xff; size],
+            scl: false,
+            sda: false,
+            phase: Phase::Idle,
+            next: Phase::Idle,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            nak: false,
+            out: true,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// sda as the chip drives it, high when it lets go of the line
+    pub fn read(&self) -> bool {
+        self.out
+    }
+
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && sda != self.sda {
+            if sda {
+                self.phase = Phase::Idle;
+                self.out = true;
+            } else {
+                self.start();
+            }
+        } else if !self.scl && scl {
+            self.rise(sda);
+        } else if self.scl && !scl {
+            self.fall();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.phase = Phase::Device;
+        self.bit = 0;
+        self.shift = 0;
+        self.out = true;
+    }
+
+    /// bits are counted as they are taken, 9 is the acknowledge clock
+    fn rise(&mut self, sda: bool) {
+        if self.phase == Phase::Idle {
+            return;
+        }
+        if self.bit < 8 {
+            if self.phase != Phase::Read {
+                self.shift = self.shift << 1 | sda as u8;
+            }
+            self.bit += 1;
+        } else if self.bit == 8 {
+            // the master acknowledging, or not, the byte it just read
+            self.nak = sda;
+            self.bit = 9;
+        }
+    }
+
+    fn fall(&mut self) {
+        match (self.phase, self.bit) {
+            (Phase::Idle, _) => {}
+            (Phase::Read, 9) if self.nak => {
+                self.phase = Phase::Idle;
+                self.out = true;
+            }
+            (Phase::Read, 9) => {
+                self.bit = 0;
+                self.address = self.address.wrapping_add(1) & self.mask();
+                self.out = self.current() & 0x80 != 0;
+            }
+            (Phase::Read, bit) => self.out = bit >= 8 || self.current() << bit & 0x80 != 0,
+            (_, 8) if self.out => self.receive(),
+            (_, 9) => {
+                self.bit = 0;
+                self.shift = 0;
+                self.phase = self.next;
+                self.nak = false;
+                self.out = self.phase != Phase::Read || self.current() & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// a whole byte came in, acknowledge it or drop off the bus
+    fn receive(&mut self) {
+        let byte = self.shift;
+        self.next = match (self.phase, self.chip) {
+            (Phase::Device, EepromChip::X24C01) => {
+                self.address = byte >> 1;
+                if byte & 0x1 != 0 {
+                    Phase::Read
+                } else {
+                    Phase::Write
+                }
+            }
+            (Phase::Device, _) if byte & 0xf0 != 0xa0 => {
+                self.phase = Phase::Idle;
+                return;
+            }
+            (Phase::Device, _) if byte & 0x1 != 0 => Phase::Read,
+            (Phase::Device, _) => Phase::Address,
+            (Phase::Address, _) => {
+                self.address = byte;
+                Phase::Write
+            }
+            _ => {
+                self.data[self.address as usize] = byte;
+                // writes wrap inside a page, 4 bytes on the 24c01 and 8 on the 24c02
+                let page = match self.chip {
+                    EepromChip::X24C01 => 0x3,
+                    EepromChip::C24C02 => 0x7,
+                };
+                self.address = (self.address & !page) | (self.address.wrapping_add(1) & page);
+                Phase::Write
+            }
+        };
+        self.out = false;
+    }
+
+    fn mask(&self) -> u8 {
+        (self.data.len() - 1) as u8
+    }
+
+    fn current(&self) -> u8 {
+        self.data[self.address as usize & self.mask() as usize]
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        state.write_bool(self.scl);
+        state.write_bool(self.sda);
+        state.write_u8(self.phase as u8);
+        state.write_u8(self.next as u8);
+        state.write_u8(self.bit);
+        state.write_u8(self.shift);
+        state.write_u8(self.address);
+        state.write_bool(self.nak);
+        state.write_bool(self.out);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.data)?;
+        self.scl = state.read_bool()?;
+        self.sda = state.read_bool()?;
+        self.phase = Phase::from_u8(state.read_u8()?);
+        self.next = Phase::from_u8(state.read_u8()?);
+        self.bit = state.read_u8()?;
+        self.shift = state.read_u8()?;
+        self.address = state.read_u8()?;
+        self.nak = state.read_bool()?;
+        self.out = state.read_bool()?;
+        Ok(())
+    }
+}
+
+/// the master's side of the bus, for tests
+#[cfg(test)]
+struct Master<'a>(&'a mut Eeprom);
+
+#[cfg(test)]
+impl Master<'_> {
+    fn start(&mut self) {
+        self.0.write(false, true);
+        self.0.write(true, true);
+        self.0.write(true, false);
+        self.0.write(false, false);
+    }
+
+    fn stop(&mut self) {
+        self.0.write(false, false);
+        self.0.write(true, false);
+        self.0.write(true, true);
+    }
+
+    fn clock(&mut self, sda: bool) -> bool {
+        self.0.write(false, sda);
+        self.0.write(true, sda);
+        let val = self.0.read();
+        self.0.write(false, sda);
+        val
+    }
+
+    /// true when the chip acknowledged
+    fn send(&mut self, byte: u8) -> bool {
+        for n in (0..8).rev() {
+            self.clock(byte >> n & 0x1 != 0);
+        }
+        !self.clock(true)
+    }
+
+    fn receive(&mut self, ack: bool) -> u8 {
+        let byte = (0..8).fold(0, |byte, _| byte << 1 | self.clock(true) as u8);
+        self.clock(!ack);
+        byte
+    }
+}
+
+#[test]
+fn test_24c02() {
+    let mut chip = Eeprom::new(EepromChip::C24C02);
+    let mut bus = Master(&mut chip);
+    bus.start();
+    assert!(bus.send(0xa0));
+    assert!(bus.send(0x10));
+    assert!(bus.send(0x12));
+    assert!(bus.send(0x34));
+    bus.stop();
+
+    // random read: dummy write for the address, then a repeated start
+    bus.start();
+    assert!(bus.send(0xa0));
+    assert!(bus.send(0x10));
+    bus.start();
+    assert!(bus.send(0xa1));
+    assert_eq!(bus.receive(true), 0x12);
+    assert_eq!(bus.receive(false), 0x34);
+    bus.stop();
+
+    // other devices on the bus are ignored
+    bus.start();
+    assert!(!bus.send(0x50));
+    bus.stop();
+    assert_eq!(&chip.data()[0x10..0x12], &[0x12, 0x34]);
+}
+
+#[test]
+fn test_x24c01() {
+    let mut chip = Eeprom::new(EepromChip::X24C01);
+    let mut bus = Master(&mut chip);
+    bus.start();
+    assert!(bus.send(0x05 << 1));
+    assert!(bus.send(0xab));
+    bus.stop();
+    bus.start();
+    assert!(bus.send(0x05 << 1 | 1));
+    assert_eq!(bus.receive(false), 0xab);
+    bus.stop();
+    assert_eq!(chip.data()[0x05], 0xab);
+}
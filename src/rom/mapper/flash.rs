@@ -0,0 +1,151 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+const SECTOR_SIZE: usize = 0x1000;
+const MANUFACTURER_ID: u8 = 0xbf;
+const DEVICE_ID: u8 = 0xb7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Idle,
+    /// $AA written to $5555
+    Unlock,
+    /// then $55 to $2AAA, waiting for the command
+    Command,
+    Program,
+    /// $80 written, the erase wants a second unlock
+    Erase,
+    EraseUnlock,
+    EraseCommand,
+}
+
+impl Step {
+    fn from_u8(val: u8) -> Self {
+        match val {
+            1 => Step::Unlock,
+            2 => Step::Command,
+            3 => Step::Program,
+            4 => Step::Erase,
+            5 => Step::EraseUnlock,
+            6 => Step::EraseCommand,
+            _ => Step::Idle,
+        }
+    }
+}
+
+/// sst39sf040 style flash holding a homebrew board's prg. programs and erases
+/// run through the jedec unlock sequence and finish at once, programming can
+/// only clear bits
+pub struct Flash {
+    data: Vec<u8>,
+    step: Step,
+    id_mode: bool,
+}
+
+impl Flash {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            step: Step::Idle,
+            id_mode: false,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// `offset` is the chip address, the board works it out from its banks
+    pub fn read(&self, offset: usize) -> u8 {
+        if self.id_mode {
+            if offset & 0x1 == 0 {
+                MANUFACTURER_ID
+            } else {
+                DEVICE_ID
+            }
+        } else {
+            self.data[offset % self.data.len()]
+        }
+    }
+
+    pub fn write(&mut self, offset: usize, data: u8) {
+        // only A0-A14 take part in the command addresses
+        let command = offset & 0x7fff;
+        self.step = match (self.step, command, data) {
+            (Step::Program, _, _) => {
+                let len = self.data.len();
+                self.data[offset % len] &= data;
+                Step::Idle
+            }
+            (_, _, 0xf0) => {
+                self.id_mode = false;
+                Step::Idle
+            }
+            (Step::Idle, 0x5555, 0xaa) => Step::Unlock,
+            (Step::Unlock, 0x2aaa, 0x55) => Step::Command,
+            (Step::Command, 0x5555, 0xa0) => Step::Program,
+            (Step::Command, 0x5555, 0x80) => Step::Erase,
+            (Step::Command, 0x5555, 0x90) => {
+                self.id_mode = true;
+                Step::Idle
+            }
+            (Step::Erase, 0x5555, 0xaa) => Step::EraseUnlock,
+            (Step::EraseUnlock, 0x2aaa, 0x55) => Step::EraseCommand,
+            (Step::EraseCommand, 0x5555, 0x10) => {
+                self.data.fill(0xff);
+                Step::Idle
+            }
+            (Step::EraseCommand, _, 0x30) => {
+                let start = (offset % self.data.len()) & !(SECTOR_SIZE - 1);
+                let end = (start + SECTOR_SIZE).min(self.data.len());
+                self.data[start..end].fill(0xff);
+                Step::Idle
+            }
+            _ => Step::Idle,
+        };
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        state.write_u8(self.step as u8);
+        state.write_bool(self.id_mode);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        state.read_bytes(&mut self.data)?;
+        self.step = Step::from_u8(state.read_u8()?);
+        self.id_mode = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_flash() {
+    let mut flash = Flash::new(vec![0xff; 0x80000]);
+    let unlock = |flash: &mut Flash, command: u8| {
+        flash.write(0x5555, 0xaa);
+        flash.write(0x2aaa, 0x55);
+        flash.write(0x5555, command);
+    };
+    unlock(&mut flash, 0xa0);
+    flash.write(0x12345, 0x5a);
+    assert_eq!(flash.read(0x12345), 0x5a);
+    // a write outside a command does nothing
+    flash.write(0x12346, 0x00);
+    assert_eq!(flash.read(0x12346), 0xff);
+
+    unlock(&mut flash, 0x90);
+    assert_eq!([flash.read(0), flash.read(1)], [0xbf, 0xb7]);
+    flash.write(0, 0xf0);
+    assert_eq!(flash.read(0), 0xff);
+
+    unlock(&mut flash, 0x80);
+    flash.write(0x5555, 0xaa);
+    flash.write(0x2aaa, 0x55);
+    flash.write(0x12000, 0x30);
+    assert_eq!(flash.read(0x12345), 0xff);
+}
@@ -1,10 +1,97 @@
+use std::borrow::Cow;
+
+use crate::apu::ExpansionAudio;
+use crate::error::EmuError;
+use crate::rom::cartridge::MirrorType;
+use crate::state::{StateReader, StateWriter};
+
+pub use AXROM::AxRom;
+pub use BANDAI_FCG::BandaiFcg;
+pub use BNROM::BnRom;
+pub use CAMERICA::Camerica;
+pub use CNROM::CnRom;
+pub use COLOR_DREAMS::ColorDreams;
+pub use FME7::Fme7;
+pub use GTROM::GtRom;
+pub use GXROM::GxRom;
+pub use MAGIC_FLOOR::MagicFloor;
+pub use MMC1::Mmc1;
+pub use MMC2::Mmc2;
+pub use MMC3::{Board as Mmc3Board, IrqRevision, Mmc3};
+pub use MMC5::Mmc5;
+pub use NAMCO163::Namco163;
 pub use NROM::NRom;
+pub use NSF::NsfMapper;
+pub use UNROM512::Unrom512;
+pub use UXROM::UxRom;
+pub use VRC4::Vrc4;
+pub use VRC6::Vrc6;
+pub use VRC7::Vrc7;
 
+mod AXROM;
+mod BANDAI_FCG;
+mod BNROM;
+mod CAMERICA;
+mod CNROM;
+mod COLOR_DREAMS;
+mod FME7;
+mod GTROM;
+mod GXROM;
+mod MAGIC_FLOOR;
+mod MMC1;
+mod MMC2;
 mod MMC3;
+mod MMC5;
+mod NAMCO163;
 mod NROM;
+mod NSF;
+mod UNROM512;
+mod UXROM;
+mod VRC4;
+mod VRC6;
+mod VRC7;
+mod eeprom;
+mod flash;
+mod vrc_irq;
 
 pub const NROM: usize = 0;
+pub const MMC1: usize = 1;
+pub const UXROM: usize = 2;
+pub const CNROM: usize = 3;
 pub const MMC3: usize = 4;
+pub const MMC5: usize = 5;
+pub const AXROM: usize = 7;
+pub const MMC2: usize = 9;
+pub const MMC4: usize = 10;
+pub const COLOR_DREAMS: usize = 11;
+pub const BANDAI_FCG: usize = 16;
+pub const NAMCO_163: usize = 19;
+pub const VRC4AC: usize = 21;
+pub const VRC2A: usize = 22;
+pub const VRC4EF: usize = 23;
+pub const VRC6A: usize = 24;
+pub const VRC4BD: usize = 25;
+pub const VRC6B: usize = 26;
+pub const UNROM512: usize = 30;
+pub const BNROM: usize = 34;
+pub const GXROM: usize = 66;
+pub const FME7: usize = 69;
+pub const CAMERICA: usize = 71;
+pub const NAMCO_3446: usize = 76;
+pub const VRC7: usize = 85;
+pub const NAMCO_3433: usize = 88;
+pub const NAMCO_3425: usize = 95;
+pub const GTROM: usize = 111;
+pub const TXSROM: usize = 118;
+pub const TQROM: usize = 119;
+pub const BANDAI_SRAM: usize = 153;
+pub const NAMCO_3453: usize = 154;
+pub const DATACH: usize = 157;
+pub const BANDAI_24C01: usize = 159;
+pub const NAMCO_108: usize = 206;
+pub const MAGIC_FLOOR: usize = 218;
+/// not an ines mapper, nsf files get their player hardware through this number
+pub const NSF: usize = 0x1000;
 
 pub enum AccessArea {
     ChrRom,
@@ -12,16 +99,63 @@ pub enum AccessArea {
     
 }
 
+/// a cartridge board as both buses see it. the cpu side covers $4020-$FFFF,
+/// the ppu side the pattern tables at $0000-$1FFF, nametables go through
+/// the console's vram following `nametable_offset`
 pub trait Mapper {
-    fn read(&self, address: u16) -> u8;
-    fn write(&mut self, address: u16, data: u8);
-    fn read_word(&self, address: u16) -> u16;
-    fn write_word(&mut self, address: u16, data: u16);
-    fn data_ref(&self, address: u16) -> &u8;
-    fn data_ref_mut(&mut self, address: u16) -> &mut u8;
+    fn cpu_read(&mut self, address: u16) -> u8;
+    fn cpu_write(&mut self, address: u16, data: u8);
+    fn ppu_read(&mut self, address: u16) -> u8;
+    fn ppu_write(&mut self, address: u16, data: u8);
+    /// what `cpu_read` would return, without acknowledging or latching anything
+    fn peek(&self, address: u16) -> u8;
+    fn mirroring(&self) -> MirrorType;
+    /// where a $2000-$3EFF address lands in the console's 4k of vram, for
+    /// boards that drive ciram a10 from something other than a fixed layout
+    fn nametable_offset(&self, address: u16) -> usize {
+        self.mirroring().nametable_offset(address)
+    }
+    /// nametable reads go past the board first, `None` leaves them to vram
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        None
+    }
+    /// true when the board took the write instead of vram
+    fn nametable_write(&mut self, address: u16, data: u8) -> bool {
+        false
+    }
+    /// the cpu writing a ppu register at $2000-$2007, for boards that listen in
+    fn ppu_register_write(&mut self, address: u16, data: u8) {}
+    fn irq_pending(&self) -> bool {
+        false
+    }
+    /// called once per cpu cycle, for irq counters and the like
+    fn cpu_clock(&mut self) {}
+    /// called at the end of every rendered scanline
+    fn scanline(&mut self) {}
+    /// sound chip on the board, clocked every cpu cycle and mixed with the apu
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        None
+    }
+    /// the ram a battery would keep, whether the board has one is up to the
+    /// header. owned when it has to be pieced together from more than one chip
+    fn save_ram(&self) -> Option<Cow<'_, [u8]>> {
+        None
+    }
+    fn load_save_ram(&mut self, data: &[u8]) {}
+    /// the board keeps its saves without a battery, e.g. in an eeprom, so they
+    /// go to disk whatever the header's battery bit says
+    fn nonvolatile(&self) -> bool {
+        false
+    }
+    /// banks, ram, irq state and the sound chip's registers, all of which the
+    /// cpu can observe
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError>;
 }
 
-/// return (high byte,low byte)
-pub fn split_word(data: u16) -> (u8, u8) {
-    (((data & 0xff00) >> 8) as u8, (data & 0x00ff) as u8)
+/// copy what fits of a .sav into `ram`, files from other emulators may be
+/// padded or short
+pub fn load_ram(ram: &mut [u8], data: &[u8]) {
+    let len = ram.len().min(data.len());
+    ram[..len].copy_from_slice(&data[..len]);
 }
@@ -0,0 +1,133 @@
+use crate::error::EmuError;
+use crate::state::{StateReader, StateWriter};
+
+/// cpu cycles per scanline times three, the prescaler counts down by 3 each cycle
+const PRESCALER_PERIOD: i16 = 341;
+
+/// the irq counter shared by konami's vrc4, vrc6 and vrc7: an 8-bit up
+/// counter reloaded from a latch on overflow, clocked either every cpu cycle
+/// or once per scanline through a prescaler
+#[derive(Default)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn write_latch(&mut self, val: u8) {
+        self.latch = val;
+    }
+
+    /// vrc4 splits the latch across two registers
+    pub fn write_latch_low(&mut self, val: u8) {
+        self.latch = (self.latch & 0xf0) | (val & 0xf);
+    }
+
+    pub fn write_latch_high(&mut self, val: u8) {
+        self.latch = (self.latch & 0x0f) | (val << 4);
+    }
+
+    /// `---- -MEA`: cycle mode, enable, enable after acknowledge
+    pub fn write_control(&mut self, val: u8) {
+        self.enable_after_ack = val & 0x1 != 0;
+        self.enabled = val & 0x2 != 0;
+        self.cycle_mode = val & 0x4 != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.tick();
+            return;
+        }
+        self.prescaler -= 3;
+        if self.prescaler <= 0 {
+            self.prescaler += PRESCALER_PERIOD;
+            self.tick();
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.latch);
+        state.write_u8(self.counter);
+        state.write_u16(self.prescaler as u16);
+        state.write_bool(self.enabled);
+        state.write_bool(self.enable_after_ack);
+        state.write_bool(self.cycle_mode);
+        state.write_bool(self.pending);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), EmuError> {
+        self.latch = state.read_u8()?;
+        self.counter = state.read_u8()?;
+        self.prescaler = state.read_u16()? as i16;
+        self.enabled = state.read_bool()?;
+        self.enable_after_ack = state.read_bool()?;
+        self.cycle_mode = state.read_bool()?;
+        self.pending = state.read_bool()?;
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[test]
+fn test_scanline_mode() {
+    let mut irq = VrcIrq::default();
+    irq.write_latch(0xfe);
+    irq.write_control(0x02);
+    // two scanlines of 113.67 cycles
+    for _ in 0..227 {
+        irq.clock();
+    }
+    assert!(!irq.pending());
+    irq.clock();
+    assert!(irq.pending());
+    irq.acknowledge();
+    assert!(!irq.pending());
+    // acknowledged without the A bit, so counting stops
+    for _ in 0..1000 {
+        irq.clock();
+    }
+    assert!(!irq.pending());
+}
+
+#[test]
+fn test_cycle_mode() {
+    let mut irq = VrcIrq::default();
+    irq.write_latch(0xfd);
+    irq.write_control(0x07);
+    irq.clock();
+    irq.clock();
+    assert!(!irq.pending());
+    irq.clock();
+    assert!(irq.pending());
+}
@@ -1,6 +1,10 @@
-pub use cartridge::{Cartridge, CartridgeInfo};
+pub use cartridge::{Cartridge, CartridgeInfo, MirrorType, Timing};
 pub use mapper::Mapper;
+pub use md5::md5;
+pub use nsf::{ExpansionChips, Nsf};
 
 mod cartridge;
 mod mapper;
+mod md5;
+mod nsf;
 
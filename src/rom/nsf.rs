@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use bitflags::bitflags;
+
+use crate::error::EmuError;
+use crate::region::Region;
+
+bitflags! {
+    /// sound chips an nsf expects on the cartridge
+    #[derive(Default)]
+    pub struct ExpansionChips: u8 {
+        const VRC6 = 0b0000_0001;
+        const VRC7 = 0b0000_0010;
+        const FDS = 0b0000_0100;
+        const MMC5 = 0b0000_1000;
+        const N163 = 0b0001_0000;
+        const S5B = 0b0010_0000;
+    }
+}
+
+/// a ripped soundtrack: the music code and data, where to load and call it,
+/// and whatever metadata the file carries
+#[derive(Debug, Default)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: String,
+    pub tracks: u8,
+    /// 0-based
+    pub start_track: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    /// initial $5FF8-$5FFF values, only for bankswitched files
+    pub banks: Option<[u8; 8]>,
+    /// play routine period in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    pub region: Region,
+    pub chips: ExpansionChips,
+    pub track_labels: Vec<String>,
+    /// track lengths in milliseconds, when known
+    pub track_times: Vec<Option<u32>>,
+    pub track_fades: Vec<Option<u32>>,
+    pub data: Vec<u8>,
+}
+
+const NSF_MAGIC: &[u8; 5] = b"NESM\x1a";
+const NSFE_MAGIC: &[u8; 4] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+fn error(reason: &str, line: u32) -> EmuError {
+    EmuError::new(reason.to_string(), file!().to_string(), line)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    buf[offset] as u16 | (buf[offset + 1] as u16) << 8
+}
+
+/// a fixed size, zero padded string field
+fn fixed_string(buf: &[u8]) -> String {
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).to_string()
+}
+
+/// a run of null terminated strings
+fn string_list(buf: &[u8]) -> Vec<String> {
+    let mut strings = buf
+        .split(|&c| c == 0)
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect::<Vec<String>>();
+    if buf.last() == Some(&0) {
+        strings.pop();
+    }
+    strings
+}
+
+fn region_from_flags(flags: u8) -> Region {
+    // bit 1 is dual region, which plays as ntsc
+    if flags & 0x3 == 0x1 {
+        Region::Pal
+    } else {
+        Region::Ntsc
+    }
+}
+
+impl Nsf {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EmuError> {
+        let buf = std::fs::read(path).map_err(|e| error(&e.to_string(), line!()))?;
+        Self::parse(&buf)
+    }
+
+    pub fn parse(buf: &[u8]) -> Result<Self, EmuError> {
+        if buf.starts_with(NSF_MAGIC) {
+            Self::parse_nsf(buf)
+        } else if buf.starts_with(NSFE_MAGIC) {
+            Self::parse_nsfe(&buf[4..])
+        } else {
+            Err(error("magic check failed, invalid nsf file", line!()))
+        }
+    }
+
+    fn parse_nsf(buf: &[u8]) -> Result<Self, EmuError> {
+        if buf.len() < NSF_HEADER_SIZE {
+            return Err(error("nsf header is truncated", line!()));
+        }
+        let mut banks = [0u8; 8];
+        banks.copy_from_slice(&buf[0x70..0x78]);
+        Ok(Self {
+            title: fixed_string(&buf[0x0e..0x2e]),
+            artist: fixed_string(&buf[0x2e..0x4e]),
+            copyright: fixed_string(&buf[0x4e..0x6e]),
+            tracks: buf[0x06],
+            start_track: buf[0x07].saturating_sub(1),
+            load_address: read_u16(buf, 0x08),
+            init_address: read_u16(buf, 0x0a),
+            play_address: read_u16(buf, 0x0c),
+            banks: banks.iter().any(|&bank| bank != 0).then_some(banks),
+            ntsc_speed: read_u16(buf, 0x6e),
+            pal_speed: read_u16(buf, 0x78),
+            region: region_from_flags(buf[0x7a]),
+            chips: ExpansionChips::from_bits_truncate(buf[0x7b]),
+            data: buf[NSF_HEADER_SIZE..].to_vec(),
+            ..Self::default()
+        })
+    }
+
+    /// nsfe is a list of `length, fourcc, data` chunks ending with NEND
+    fn parse_nsfe(mut buf: &[u8]) -> Result<Self, EmuError> {
+        let mut nsf = Self {
+            ntsc_speed: DEFAULT_NTSC_SPEED,
+            pal_speed: DEFAULT_PAL_SPEED,
+            ..Self::default()
+        };
+        let mut has_info = false;
+        let mut has_data = false;
+
+        while buf.len() >= 8 {
+            let length = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            let id = &buf[4..8];
+            let chunk = buf
+                .get(8..8 + length)
+                .ok_or_else(|| error("nsfe chunk is truncated", line!()))?;
+            buf = &buf[8 + length..];
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err(error("nsfe INFO chunk is too short", line!()));
+                    }
+                    nsf.load_address = read_u16(chunk, 0);
+                    nsf.init_address = read_u16(chunk, 2);
+                    nsf.play_address = read_u16(chunk, 4);
+                    nsf.region = region_from_flags(chunk[6]);
+                    nsf.chips = ExpansionChips::from_bits_truncate(chunk[7]);
+                    nsf.tracks = chunk.get(8).copied().unwrap_or(1);
+                    nsf.start_track = chunk.get(9).copied().unwrap_or(0);
+                    has_info = true;
+                }
+                b"DATA" => {
+                    nsf.data = chunk.to_vec();
+                    has_data = true;
+                }
+                b"BANK" => {
+                    let mut banks = [0u8; 8];
+                    let count = chunk.len().min(8);
+                    banks[..count].copy_from_slice(&chunk[..count]);
+                    nsf.banks = Some(banks);
+                }
+                b"RATE" => {
+                    if chunk.len() >= 2 {
+                        nsf.ntsc_speed = read_u16(chunk, 0);
+                    }
+                    if chunk.len() >= 4 {
+                        nsf.pal_speed = read_u16(chunk, 2);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = string_list(chunk).into_iter();
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                    nsf.ripper = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => nsf.track_labels = string_list(chunk),
+                b"time" => nsf.track_times = durations(chunk),
+                b"fade" => nsf.track_fades = durations(chunk),
+                b"NEND" => break,
+                // lowercase chunks are optional, uppercase ones we don't know are fatal
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(error("nsfe has an unsupported required chunk", line!()));
+                }
+                _ => {}
+            }
+        }
+
+        if !has_info || !has_data {
+            return Err(error("nsfe is missing its INFO or DATA chunk", line!()));
+        }
+        Ok(nsf)
+    }
+
+    /// microseconds between calls of the play routine, rips that leave the
+    /// field at 0 get the usual 60hz or 50hz
+    pub fn play_speed(&self, region: Region) -> u16 {
+        match region {
+            Region::Ntsc if self.ntsc_speed == 0 => DEFAULT_NTSC_SPEED,
+            Region::Ntsc => self.ntsc_speed,
+            Region::Pal if self.pal_speed == 0 => DEFAULT_PAL_SPEED,
+            Region::Pal => self.pal_speed,
+        }
+    }
+
+    pub fn track_label(&self, track: u8) -> Option<&str> {
+        self.track_labels
+            .get(track as usize)
+            .map(|label| label.as_str())
+    }
+
+    /// length of a track including its fade out, in milliseconds
+    pub fn track_length(&self, track: u8) -> Option<u32> {
+        let time = self.track_times.get(track as usize).copied().flatten()?;
+        let fade = self.track_fades.get(track as usize).copied().flatten();
+        Some(time + fade.unwrap_or(0))
+    }
+}
+
+/// signed millisecond values, negative means unknown
+fn durations(buf: &[u8]) -> Vec<Option<u32>> {
+    buf.chunks_exact(4)
+        .map(|val| {
+            let ms = i32::from_le_bytes([val[0], val[1], val[2], val[3]]);
+            (ms >= 0).then_some(ms as u32)
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_nsf() {
+    let mut buf = vec![0u8; NSF_HEADER_SIZE + 4];
+    buf[..5].copy_from_slice(NSF_MAGIC);
+    buf[0x06] = 12;
+    buf[0x07] = 3;
+    buf[0x08..0x0e].copy_from_slice(&[0x00, 0x80, 0x03, 0x80, 0x06, 0x80]);
+    buf[0x0e..0x13].copy_from_slice(b"Title");
+    buf[0x2e..0x34].copy_from_slice(b"Artist");
+    buf[0x6e..0x70].copy_from_slice(&DEFAULT_NTSC_SPEED.to_le_bytes());
+    buf[0x7b] = 0x21;
+
+    let nsf = Nsf::parse(&buf).unwrap();
+    assert_eq!(nsf.title, "Title");
+    assert_eq!(nsf.artist, "Artist");
+    assert_eq!(nsf.tracks, 12);
+    assert_eq!(nsf.start_track, 2);
+    assert_eq!(
+        (nsf.load_address, nsf.init_address, nsf.play_address),
+        (0x8000, 0x8003, 0x8006)
+    );
+    assert_eq!(nsf.banks, None);
+    assert_eq!(nsf.region, Region::Ntsc);
+    assert_eq!(nsf.chips, ExpansionChips::VRC6 | ExpansionChips::S5B);
+    assert_eq!(nsf.data.len(), 4);
+    // the pal field was left at 0
+    assert_eq!(nsf.play_speed(Region::Pal), DEFAULT_PAL_SPEED);
+}
+
+#[test]
+fn test_parse_nsfe() {
+    let mut buf = NSFE_MAGIC.to_vec();
+    let mut chunk = |id: &[u8], data: &[u8]| {
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(id);
+        buf.extend_from_slice(data);
+    };
+    chunk(
+        b"INFO",
+        &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x01, 0x04, 2, 1],
+    );
+    chunk(b"DATA", &[0x60; 16]);
+    chunk(b"BANK", &[0, 1, 2]);
+    chunk(b"auth", b"Game\0Composer\0\0Ripper\0");
+    chunk(b"tlbl", b"Intro\0Boss\0");
+    let times = [1000i32.to_le_bytes(), (-1i32).to_le_bytes()].concat();
+    chunk(b"time", &times);
+    chunk(b"fade", &500i32.to_le_bytes());
+    chunk(b"NEND", &[]);
+
+    let nsf = Nsf::parse(&buf).unwrap();
+    assert_eq!(nsf.region, Region::Pal);
+    assert_eq!(nsf.chips, ExpansionChips::FDS);
+    assert_eq!((nsf.tracks, nsf.start_track), (2, 1));
+    assert_eq!(nsf.banks, Some([0, 1, 2, 0, 0, 0, 0, 0]));
+    assert_eq!(nsf.title, "Game");
+    assert_eq!(nsf.artist, "Composer");
+    assert_eq!(nsf.copyright, "");
+    assert_eq!(nsf.ripper, "Ripper");
+    assert_eq!(nsf.track_label(1), Some("Boss"));
+    assert_eq!(nsf.track_length(0), Some(1500));
+    assert_eq!(nsf.track_length(1), None);
+    assert_eq!(nsf.play_speed(Region::Pal), DEFAULT_PAL_SPEED);
+}
@@ -0,0 +1,139 @@
+use crate::error::EmuError;
+
+/// little endian serializer for savestates, fields go in and come back out
+/// in the same order
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// length prefixed, so a reader can check it against what it expects
+    pub fn write_bytes(&mut self, val: &[u8]) {
+        self.write_u32(val.len() as u32);
+        self.buf.extend_from_slice(val);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+}
+
+fn truncated() -> EmuError {
+    EmuError::new(
+        "savestate is truncated".to_string(),
+        file!().to_string(),
+        line!(),
+    )
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EmuError> {
+        if self.buf.len() < len {
+            return Err(truncated());
+        }
+        let (val, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(val)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, EmuError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, EmuError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EmuError> {
+        let val = self.take(2)?;
+        Ok(u16::from_le_bytes([val[0], val[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EmuError> {
+        let val = self.take(4)?;
+        Ok(u32::from_le_bytes([val[0], val[1], val[2], val[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EmuError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// fill `out`, which has to be as long as what was written
+    pub fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), EmuError> {
+        let len = self.read_u32()? as usize;
+        if len != out.len() {
+            return Err(EmuError::new(
+                format!("savestate holds {} bytes where {} fit", len, out.len()),
+                file!().to_string(),
+                line!(),
+            ));
+        }
+        out.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+
+    /// a length prefixed block of whatever size was written
+    pub fn read_vec(&mut self) -> Result<Vec<u8>, EmuError> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+#[test]
+fn test_state_roundtrip() {
+    let mut writer = StateWriter::default();
+    writer.write_u8(0x12);
+    writer.write_bool(true);
+    writer.write_u16(0x3456);
+    writer.write_u32(0x789a_bcde);
+    writer.write_u64(0x0123_4567_89ab_cdef);
+    writer.write_bytes(&[1, 2, 3]);
+    let buf = writer.into_inner();
+
+    let mut reader = StateReader::new(&buf);
+    assert_eq!(reader.read_u8().unwrap(), 0x12);
+    assert!(reader.read_bool().unwrap());
+    assert_eq!(reader.read_u16().unwrap(), 0x3456);
+    assert_eq!(reader.read_u32().unwrap(), 0x789a_bcde);
+    assert_eq!(reader.read_u64().unwrap(), 0x0123_4567_89ab_cdef);
+    let mut bytes = [0u8; 3];
+    reader.read_bytes(&mut bytes).unwrap();
+    assert_eq!(bytes, [1, 2, 3]);
+    assert!(reader.read_u8().is_err());
+    let mut reader = StateReader::new(&buf[buf.len() - 7..]);
+    assert_eq!(reader.read_vec().unwrap(), [1, 2, 3]);
+
+    let mut reader = StateReader::new(&buf[15..]);
+    assert!(reader.read_bytes(&mut [0u8; 2]).is_err());
+}
@@ -1,4 +1,6 @@
+use crate::apu::APU;
 use crate::cpu::{Flags, Regs};
+use crate::input::ControllerPorts;
 use crate::{CPUBus, CPURam, Cartridge, CPU};
 use regex::Regex;
 use std::cell::RefCell;
@@ -11,6 +13,7 @@ use std::rc::Rc;
 
 struct Trace {
     regs: Regs,
+    cycles: u64,
 }
 
 impl Debug for Trace {
@@ -29,9 +32,10 @@ impl Trace {
             .filter(|t| !t.is_empty())
             .collect::<Vec<&str>>();
         let mut vec = vec![];
-        let regex =
-            Regex::new(r"A:(?P<A>\w+)\sX:(?P<X>\w+)\sY:(?P<Y>\w+)\sP:(?P<P>\w+)\sSP:(?P<SP>\w+)")
-                .unwrap();
+        let regex = Regex::new(
+            r"A:(?P<A>\w+)\sX:(?P<X>\w+)\sY:(?P<Y>\w+)\sP:(?P<P>\w+)\sSP:(?P<SP>\w+).*CYC:(?P<CYC>\d+)",
+        )
+        .unwrap();
 
         for line in line_buf {
             vec.push(Trace::generate_one_trace(line, &regex));
@@ -59,6 +63,7 @@ impl Trace {
                 },
                 PC: pc,
             },
+            cycles: caps["CYC"].parse().unwrap(),
         }
     }
 }
@@ -66,20 +71,27 @@ impl Trace {
 pub fn trace<P: AsRef<Path>>(path: P) {
     let cart = Cartridge::new("./test/nestest.nes");
     let ram = CPURam::default();
-    let bus = CPUBus::connect(Rc::new(RefCell::new(cart)), Rc::new(RefCell::new(ram)));
+    let apu = APU::default();
+    let bus = CPUBus::connect(
+        Rc::new(RefCell::new(cart)),
+        Rc::new(RefCell::new(ram)),
+        Rc::new(RefCell::new(apu)),
+        Rc::new(RefCell::new(ControllerPorts::default())),
+    );
     let mut cpu = CPU::new(Rc::new(RefCell::new(bus)), 0xc000);
 
     let trace_vec = Trace::generate_all_trace(path);
     for trace in trace_vec {
-        if trace.regs == cpu.get_regs() {
-            let inst = cpu.get_next_inst();
-            cpu.exec_once(inst);
+        if trace.regs == cpu.get_regs() && trace.cycles == cpu.get_cycles() {
+            cpu.step();
         } else {
             println!(
-                "Error occur at {:#x}\nregs: {:#?}\ntrace:{:#?}",
+                "Error occur at {:#x}\nregs: {:#?} cycles: {}\ntrace:{:#?} cycles: {}",
                 cpu.get_regs().PC,
                 cpu.get_regs(),
-                trace
+                cpu.get_cycles(),
+                trace,
+                trace.cycles
             );
             exit(-1);
         }